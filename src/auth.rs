@@ -0,0 +1,137 @@
+//! Game Port Authentication
+//!
+//! When tokens are configured, the first line a game sends must be
+//!
+//! ```json
+//! {"cmd": "auth", "token": "s3cret"}
+//! ```
+//!
+//! Anything else gets an error and the connection is closed, as does a peer
+//! that takes over 10 seconds or 4 KB to send that line. Each token can be
+//! limited to sessions whose IDs match a glob (`*` matches anything), e.g.
+//! `s3cret=session_10_0_*` only lets that game see players connecting from
+//! 10.0.x.x. A token without patterns may target every session.
+
+/// A shared secret a game can authenticate with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthToken {
+    pub token: String,
+    /// Sessions this token may target
+    pub scope: SessionScope,
+}
+
+impl AuthToken {
+    /// Parse `token` or `token=pattern,pattern,...`
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (token, patterns) = match spec.split_once('=') {
+            Some((token, patterns)) => (token, patterns.split(',').map(str::trim).filter(|p| !p.is_empty()).collect()),
+            None => (spec, Vec::new()),
+        };
+        if token.is_empty() {
+            return None;
+        }
+        Some(Self {
+            token: token.to_string(),
+            scope: SessionScope::new(patterns.into_iter().map(String::from).collect()),
+        })
+    }
+}
+
+/// Find the token a game presented (None if it matches no configured token)
+pub fn authenticate<'a>(tokens: &'a [AuthToken], presented: &str) -> Option<&'a AuthToken> {
+    // Check every token so timing doesn't reveal which one (or how much) matched
+    tokens.iter().fold(None, |found, t| {
+        if constant_time_eq(t.token.as_bytes(), presented.as_bytes()) { Some(t) } else { found }
+    })
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The sessions a game may target
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SessionScope {
+    /// Session ID globs (empty allows every session)
+    patterns: Vec<String>,
+}
+
+impl SessionScope {
+    pub fn new(patterns: Vec<String>) -> Self {
+        Self { patterns }
+    }
+
+    /// Every session
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Whether every session is allowed
+    pub fn is_all(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn allows(&self, session_id: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|p| glob_match(p, session_id))
+    }
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No '*': the whole text must match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_scopes() {
+        let tokens = vec![
+            AuthToken::parse("admin").unwrap(),
+            AuthToken::parse("lan=session_10_0_*, session_127_0_0_1_*").unwrap(),
+        ];
+        assert!(authenticate(&tokens, "nope").is_none());
+        assert!(authenticate(&tokens, "admi").is_none());
+
+        let admin = authenticate(&tokens, "admin").unwrap();
+        assert!(admin.scope.allows("session_8_8_8_8_1234"));
+
+        let lan = authenticate(&tokens, "lan").unwrap();
+        assert!(lan.scope.allows("session_10_0_3_7_50000"));
+        assert!(lan.scope.allows("session_127_0_0_1_6000"));
+        assert!(!lan.scope.allows("session_10_1_3_7_50000"));
+
+        assert!(AuthToken::parse("=session_*").is_none());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("abc", "abc"));
+        assert!(!glob_match("abc", "abcd"));
+        assert!(glob_match("a*d", "abcd"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "a_b_b_c"));
+        assert!(!glob_match("ab*ba", "aba"));
+    }
+}
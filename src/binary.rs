@@ -0,0 +1,391 @@
+//! Binary Game Protocol
+//!
+//! A compact alternative to JSON lines for games that push a lot of cells.
+//! A game switches by sending `{"cmd": "binary"}` as a JSON line; everything
+//! it sends after that line is length-prefixed frames. Events and replies to
+//! the game stay JSON lines.
+//!
+//! ```text
+//! frame:    u32 length (big-endian) | payload
+//! payload:  0x00 | JSON command (any command, same fields as a JSON line)
+//!           op   | session (str8, empty = all) | body
+//!
+//! 0x01 batch     u16 count, then per cell: x u16 | y u16 | char | fg | bg | window str8
+//! 0x02 print     window str8 | x u16 | y u16 | fg | bg | text str16
+//! 0x03 fill      window str8 | x u16 | y u16 | width u16 | height u16 | char | fg | bg
+//! 0x04 flush     flags u8 (bit 0: force_full)
+//! 0x05 set_cell  window str8 | x u16 | y u16 | char | fg | bg
+//!
+//! str8/str16: u8/u16 byte length, then UTF-8
+//! char:       u32 code point
+//! color:      0x00 index u8 (palette 0-255) | 0x01 r g b
+//! ```
+//!
+//! An empty window on print and set_cell draws on the background
+//! (`print_direct`, `set_direct`). Commands that don't fit the compact ops
+//! (or carry a `request_id`) go in JSON frames.
+
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::core::Color;
+use crate::protocol::{parse_targeted_command, BatchCell, Command, TargetedCommand};
+
+/// Largest frame accepted (a full 1920-cell batch is about 25 KB)
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const OP_JSON: u8 = 0x00;
+const OP_BATCH: u8 = 0x01;
+const OP_PRINT: u8 = 0x02;
+const OP_FILL: u8 = 0x03;
+const OP_FLUSH: u8 = 0x04;
+const OP_SET_CELL: u8 = 0x05;
+
+const COLOR_INDEX: u8 = 0x00;
+const COLOR_RGB: u8 = 0x01;
+
+/// Encode a command as a frame (length prefix included)
+pub fn encode_frame(targeted: &TargetedCommand) -> Vec<u8> {
+    let payload = encode_compact(targeted).unwrap_or_else(|| encode_json(targeted));
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Decode a frame's payload (without the length prefix)
+pub fn decode_frame(payload: &[u8]) -> Result<TargetedCommand, String> {
+    let (&op, rest) = payload.split_first().ok_or("Empty frame")?;
+    if op == OP_JSON {
+        let json = std::str::from_utf8(rest).map_err(|e| format!("Invalid UTF-8: {}", e))?;
+        return parse_targeted_command(json).map_err(|e| e.to_string());
+    }
+
+    let mut r = FrameReader { buf: rest, pos: 0 };
+    let session = Some(r.str8()?).filter(|s| !s.is_empty());
+    let command = match op {
+        OP_BATCH => {
+            let count = r.u16()?;
+            let mut cells = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                cells.push(BatchCell {
+                    x: r.u16()? as usize,
+                    y: r.u16()? as usize,
+                    char: r.char()?,
+                    fg: r.color()?,
+                    bg: r.color()?,
+                    window: Some(r.str8()?).filter(|w| !w.is_empty()),
+                });
+            }
+            Command::Batch { cells }
+        }
+        OP_PRINT => {
+            let window = r.str8()?;
+            let (x, y) = (r.u16()? as usize, r.u16()? as usize);
+            let (fg, bg) = (r.color()?, r.color()?);
+            let text = r.str16()?;
+            if window.is_empty() {
+                Command::PrintDirect { x, y, text, fg, bg }
+            } else {
+                Command::Print { window, x, y, text, fg, bg }
+            }
+        }
+        OP_FILL => Command::Fill {
+            window: r.str8()?,
+            x: r.u16()? as usize,
+            y: r.u16()? as usize,
+            width: r.u16()? as usize,
+            height: r.u16()? as usize,
+            char: r.char()?,
+            fg: r.color()?,
+            bg: r.color()?,
+        },
+        OP_FLUSH => Command::Flush { force_full: r.u8()? & 1 != 0 },
+        OP_SET_CELL => {
+            let window = r.str8()?;
+            let (x, y) = (r.u16()? as usize, r.u16()? as usize);
+            let (char, fg, bg) = (r.char()?, r.color()?, r.color()?);
+            if window.is_empty() {
+                Command::SetDirect { x, y, char, fg, bg }
+            } else {
+                Command::SetCell { window, x, y, char, fg, bg }
+            }
+        }
+        op => return Err(format!("Unknown opcode: {:#04x}", op)),
+    };
+    if r.pos != r.buf.len() {
+        return Err(format!("{} trailing bytes in frame", r.buf.len() - r.pos));
+    }
+    Ok(TargetedCommand { session, request_id: None, command })
+}
+
+/// Read one frame's payload (None at end of stream)
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Frame too large: {} bytes", len)));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// The compact form of a command, if it has one
+fn encode_compact(targeted: &TargetedCommand) -> Option<Vec<u8>> {
+    if targeted.request_id.is_some() {
+        return None;
+    }
+    let mut w = Vec::new();
+    let header = |op: u8, w: &mut Vec<u8>| -> Option<()> {
+        w.push(op);
+        put_str8(w, targeted.session.as_deref().unwrap_or(""))
+    };
+    match &targeted.command {
+        Command::Batch { cells } => {
+            header(OP_BATCH, &mut w)?;
+            w.extend_from_slice(&u16::try_from(cells.len()).ok()?.to_be_bytes());
+            for cell in cells {
+                put_u16(&mut w, cell.x)?;
+                put_u16(&mut w, cell.y)?;
+                put_char(&mut w, cell.char);
+                put_color(&mut w, cell.fg);
+                put_color(&mut w, cell.bg);
+                put_str8(&mut w, cell.window.as_deref().unwrap_or(""))?;
+            }
+        }
+        Command::Print { window, x, y, text, fg, bg } if !window.is_empty() => {
+            header(OP_PRINT, &mut w)?;
+            put_print(&mut w, window, *x, *y, text, *fg, *bg)?;
+        }
+        Command::PrintDirect { x, y, text, fg, bg } => {
+            header(OP_PRINT, &mut w)?;
+            put_print(&mut w, "", *x, *y, text, *fg, *bg)?;
+        }
+        Command::Fill { window, x, y, width, height, char, fg, bg } => {
+            header(OP_FILL, &mut w)?;
+            put_str8(&mut w, window)?;
+            for v in [*x, *y, *width, *height] {
+                put_u16(&mut w, v)?;
+            }
+            put_char(&mut w, *char);
+            put_color(&mut w, *fg);
+            put_color(&mut w, *bg);
+        }
+        Command::Flush { force_full } => {
+            header(OP_FLUSH, &mut w)?;
+            w.push(*force_full as u8);
+        }
+        Command::SetCell { window, x, y, char, fg, bg } if !window.is_empty() => {
+            header(OP_SET_CELL, &mut w)?;
+            put_cell(&mut w, window, *x, *y, *char, *fg, *bg)?;
+        }
+        Command::SetDirect { x, y, char, fg, bg } => {
+            header(OP_SET_CELL, &mut w)?;
+            put_cell(&mut w, "", *x, *y, *char, *fg, *bg)?;
+        }
+        _ => return None,
+    }
+    Some(w)
+}
+
+fn encode_json(targeted: &TargetedCommand) -> Vec<u8> {
+    let mut value = serde_json::to_value(&targeted.command).unwrap_or(Value::Null);
+    if let Some(obj) = value.as_object_mut() {
+        if let Some(session) = &targeted.session {
+            obj.insert("session".to_string(), Value::String(session.clone()));
+        }
+        if let Some(request_id) = &targeted.request_id {
+            obj.insert("request_id".to_string(), request_id.clone());
+        }
+    }
+    let mut payload = vec![OP_JSON];
+    payload.extend_from_slice(value.to_string().as_bytes());
+    payload
+}
+
+fn put_print(w: &mut Vec<u8>, window: &str, x: usize, y: usize, text: &str, fg: Color, bg: Color) -> Option<()> {
+    put_str8(w, window)?;
+    put_u16(w, x)?;
+    put_u16(w, y)?;
+    put_color(w, fg);
+    put_color(w, bg);
+    w.extend_from_slice(&u16::try_from(text.len()).ok()?.to_be_bytes());
+    w.extend_from_slice(text.as_bytes());
+    Some(())
+}
+
+fn put_cell(w: &mut Vec<u8>, window: &str, x: usize, y: usize, char: char, fg: Color, bg: Color) -> Option<()> {
+    put_str8(w, window)?;
+    put_u16(w, x)?;
+    put_u16(w, y)?;
+    put_char(w, char);
+    put_color(w, fg);
+    put_color(w, bg);
+    Some(())
+}
+
+fn put_u16(w: &mut Vec<u8>, v: usize) -> Option<()> {
+    w.extend_from_slice(&u16::try_from(v).ok()?.to_be_bytes());
+    Some(())
+}
+
+fn put_str8(w: &mut Vec<u8>, s: &str) -> Option<()> {
+    w.push(u8::try_from(s.len()).ok()?);
+    w.extend_from_slice(s.as_bytes());
+    Some(())
+}
+
+fn put_char(w: &mut Vec<u8>, c: char) {
+    w.extend_from_slice(&(c as u32).to_be_bytes());
+}
+
+fn put_color(w: &mut Vec<u8>, color: Color) {
+    match color {
+        Color::Rgb(r, g, b) => w.extend_from_slice(&[COLOR_RGB, r, g, b]),
+        _ => w.extend_from_slice(&[COLOR_INDEX, color.index().unwrap_or(7)]),
+    }
+}
+
+/// Cursor over a frame's body
+struct FrameReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl FrameReader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let bytes = self.buf.get(self.pos..self.pos + n).ok_or("Truncated frame")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn char(&mut self) -> Result<char, String> {
+        let b = self.take(4)?;
+        let code = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        char::from_u32(code).ok_or_else(|| format!("Invalid character: {:#x}", code))
+    }
+
+    fn color(&mut self) -> Result<Color, String> {
+        match self.u8()? {
+            COLOR_INDEX => Ok(Color::from(self.u8()?)),
+            COLOR_RGB => {
+                let b = self.take(3)?;
+                Ok(Color::Rgb(b[0], b[1], b[2]))
+            }
+            tag => Err(format!("Unknown color tag: {:#04x}", tag)),
+        }
+    }
+
+    fn str8(&mut self) -> Result<String, String> {
+        let len = self.u8()? as usize;
+        self.string(len)
+    }
+
+    fn str16(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        self.string(len)
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, String> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| format!("Invalid UTF-8: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targeted(session: Option<&str>, command: Command) -> TargetedCommand {
+        TargetedCommand { session: session.map(String::from), request_id: None, command }
+    }
+
+    /// Encode, read back through the framing, and decode
+    async fn round_trip(targeted: &TargetedCommand) -> TargetedCommand {
+        let frame = encode_frame(targeted);
+        let mut reader = frame.as_slice();
+        let payload = read_frame(&mut reader).await.unwrap().unwrap();
+        assert!(reader.is_empty());
+        decode_frame(&payload).unwrap()
+    }
+
+    fn assert_same(a: &TargetedCommand, b: &TargetedCommand) {
+        assert_eq!(a.session, b.session);
+        assert_eq!(a.request_id, b.request_id);
+        assert_eq!(serde_json::to_value(&a.command).unwrap(), serde_json::to_value(&b.command).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_compact_round_trip() {
+        let commands = vec![
+            targeted(Some("session_1"), Command::Batch {
+                cells: vec![
+                    BatchCell { x: 0, y: 0, char: '╔', fg: Color::BrightCyan, bg: Color::Blue, window: None },
+                    BatchCell { x: 79, y: 23, char: '@', fg: Color::Indexed(208), bg: Color::Rgb(1, 2, 3), window: Some("map".into()) },
+                ],
+            }),
+            targeted(None, Command::Print { window: "main".into(), x: 2, y: 3, text: "Héllo".into(), fg: Color::Yellow, bg: Color::Black }),
+            targeted(None, Command::PrintDirect { x: 0, y: 23, text: "status".into(), fg: Color::White, bg: Color::Red }),
+            targeted(Some("*"), Command::Fill { window: "main".into(), x: 1, y: 1, width: 10, height: 4, char: '░', fg: Color::BrightBlack, bg: Color::Black }),
+            targeted(None, Command::Flush { force_full: true }),
+            targeted(None, Command::SetCell { window: "main".into(), x: 5, y: 5, char: 'X', fg: Color::Green, bg: Color::Black }),
+            targeted(None, Command::SetDirect { x: 5, y: 5, char: '😀', fg: Color::Green, bg: Color::Black }),
+        ];
+        for command in &commands {
+            let frame = encode_frame(command);
+            assert_ne!(frame[4], OP_JSON, "{:?} should have a compact form", command.command);
+            assert_same(command, &round_trip(command).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_frames() {
+        // No compact form, a request_id, or fields too big for one all fall back to JSON
+        let commands = vec![
+            targeted(Some("session_1"), Command::BringToFront { id: "main".into() }),
+            TargetedCommand { session: None, request_id: Some(Value::from(7)), command: Command::Flush { force_full: false } },
+            targeted(None, Command::SetDirect { x: 70000, y: 0, char: 'X', fg: Color::White, bg: Color::Black }),
+        ];
+        for command in &commands {
+            assert_eq!(encode_frame(command)[4], OP_JSON);
+            assert_same(command, &round_trip(command).await);
+        }
+    }
+
+    #[test]
+    fn test_batch_is_compact() {
+        let cells = (0..1920)
+            .map(|i| BatchCell { x: i % 80, y: i / 80, char: '#', fg: Color::White, bg: Color::Black, window: None })
+            .collect();
+        let batch = targeted(None, Command::Batch { cells });
+        let json = serde_json::to_string(&batch.command).unwrap();
+        assert!(encode_frame(&batch).len() * 3 < json.len());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(decode_frame(&[]).is_err());
+        assert!(decode_frame(&[0x7f, 0]).is_err());
+        // Batch claiming a cell it doesn't have
+        assert!(decode_frame(&[OP_BATCH, 0, 0, 1]).is_err());
+        // Flush with a stray byte
+        assert!(decode_frame(&[OP_FLUSH, 0, 0, 0]).is_err());
+        // Surrogate code point
+        assert!(decode_frame(&[OP_SET_CELL, 0, 0, 0, 0, 0, 0, 0, 0xd8, 0x00, 0, 7, 0, 0]).is_err());
+    }
+}
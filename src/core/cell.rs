@@ -5,73 +5,235 @@
 //! - Foreground color (0-15 ANSI, or extended)
 //! - Background color (0-15 ANSI, or extended)
 //! - Attributes (bold, blink, reverse, etc.)
+//! - Optional hyperlink target
 
-use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-/// Standard ANSI 16-color palette
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[repr(u8)]
+/// Display color: the standard ANSI 16-color palette, the xterm 256-color
+/// palette, or 24-bit RGB truecolor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum Color {
-    Black = 0,
-    Red = 1,
-    Green = 2,
-    Yellow = 3,
-    Blue = 4,
-    Magenta = 5,
-    Cyan = 6,
-    White = 7,
-    BrightBlack = 8,   // Gray
-    BrightRed = 9,
-    BrightGreen = 10,
-    BrightYellow = 11,
-    BrightBlue = 12,
-    BrightMagenta = 13,
-    BrightCyan = 14,
-    BrightWhite = 15,
-}
-
-impl Default for Color {
-    fn default() -> Self {
-        Color::White
-    }
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    #[default]
+    White,
+    BrightBlack,   // Gray
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    /// xterm 256-color palette entry (16-255)
+    Indexed(u8),
+    /// 24-bit truecolor
+    Rgb(u8, u8, u8),
 }
 
+/// The 16 standard colors in palette order
+const ANSI16: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Yellow,
+    Color::Blue, Color::Magenta, Color::Cyan, Color::White,
+    Color::BrightBlack, Color::BrightRed, Color::BrightGreen, Color::BrightYellow,
+    Color::BrightBlue, Color::BrightMagenta, Color::BrightCyan, Color::BrightWhite,
+];
+
+/// RGB values for the 16 standard colors (IBM VGA palette)
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (170, 0, 0), (0, 170, 0), (170, 85, 0),
+    (0, 0, 170), (170, 0, 170), (0, 170, 170), (170, 170, 170),
+    (85, 85, 85), (255, 85, 85), (85, 255, 85), (255, 255, 85),
+    (85, 85, 255), (255, 85, 255), (85, 255, 255), (255, 255, 255),
+];
+
+/// Channel levels of the xterm 6x6x6 color cube (indices 16-231)
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
 impl From<u8> for Color {
     fn from(v: u8) -> Self {
         match v {
-            0 => Color::Black,
-            1 => Color::Red,
-            2 => Color::Green,
-            3 => Color::Yellow,
-            4 => Color::Blue,
-            5 => Color::Magenta,
-            6 => Color::Cyan,
-            7 => Color::White,
-            8 => Color::BrightBlack,
-            9 => Color::BrightRed,
-            10 => Color::BrightGreen,
-            11 => Color::BrightYellow,
-            12 => Color::BrightBlue,
-            13 => Color::BrightMagenta,
-            14 => Color::BrightCyan,
-            15 => Color::BrightWhite,
-            _ => Color::White,
+            0..=15 => ANSI16[v as usize],
+            _ => Color::Indexed(v),
         }
     }
 }
 
 impl Color {
-    /// Get ANSI SGR code for foreground
+    /// Palette index (0-255), or None for RGB colors
+    pub fn index(&self) -> Option<u8> {
+        match *self {
+            Color::Indexed(n) => Some(n),
+            Color::Rgb(..) => None,
+            named => ANSI16.iter().position(|c| *c == named).map(|i| i as u8),
+        }
+    }
+
+    /// Check if this is one of the 16 standard colors
+    pub fn is_ansi16(&self) -> bool {
+        matches!(self.index(), Some(n) if n < 16)
+    }
+
+    /// Get the RGB value this color displays as
+    pub fn to_rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            _ => {
+                let n = self.index().unwrap_or(7);
+                match n {
+                    0..=15 => ANSI16_RGB[n as usize],
+                    16..=231 => {
+                        let n = n - 16;
+                        (
+                            CUBE_LEVELS[(n / 36) as usize],
+                            CUBE_LEVELS[((n / 6) % 6) as usize],
+                            CUBE_LEVELS[(n % 6) as usize],
+                        )
+                    }
+                    _ => {
+                        let level = 8 + (n - 232) * 10;
+                        (level, level, level)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Downsample to the nearest of the 16 standard colors
+    pub fn to_ansi16(&self) -> Color {
+        if self.is_ansi16() {
+            return Color::from(self.index().unwrap_or(7));
+        }
+        let rgb = self.to_rgb();
+        let nearest = (0..16)
+            .min_by_key(|&i| rgb_distance(rgb, ANSI16_RGB[i]))
+            .unwrap_or(7);
+        ANSI16[nearest]
+    }
+
+    /// Downsample to the nearest xterm 256-color palette entry
+    pub fn to_indexed(&self) -> Color {
+        match *self {
+            Color::Rgb(r, g, b) => {
+                // Nearest color cube entry
+                let level = |v: u8| {
+                    (0..6).min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs()).unwrap_or(0) as u8
+                };
+                let (ri, gi, bi) = (level(r), level(g), level(b));
+                let cube = 16 + 36 * ri + 6 * gi + bi;
+
+                // Nearest grayscale ramp entry
+                let avg = (r as u32 + g as u32 + b as u32) / 3;
+                let gray = 232 + (avg.saturating_sub(3) / 10).min(23) as u8;
+
+                let target = (r, g, b);
+                let cube_dist = rgb_distance(target, Color::Indexed(cube).to_rgb());
+                let gray_dist = rgb_distance(target, Color::Indexed(gray).to_rgb());
+                Color::Indexed(if gray_dist < cube_dist { gray } else { cube })
+            }
+            other => Color::from(other.index().unwrap_or(7)),
+        }
+    }
+
+    /// Get ANSI SGR code for foreground (extended colors are downsampled to 16)
     pub fn fg_code(&self) -> u8 {
-        let v = *self as u8;
+        let v = self.to_ansi16().index().unwrap_or(7);
         if v < 8 { 30 + v } else { 90 + (v - 8) }
     }
 
-    /// Get ANSI SGR code for background
+    /// Get ANSI SGR code for background (extended colors are downsampled to 16)
     pub fn bg_code(&self) -> u8 {
-        let v = *self as u8;
+        let v = self.to_ansi16().index().unwrap_or(0);
         if v < 8 { 40 + v } else { 100 + (v - 8) }
     }
+
+    /// Get SGR parameters for foreground, using 256-color or truecolor forms as needed
+    pub fn fg_params(&self) -> String {
+        match *self {
+            Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+            _ if self.is_ansi16() => self.fg_code().to_string(),
+            _ => format!("38;5;{}", self.index().unwrap_or(7)),
+        }
+    }
+
+    /// Get SGR parameters for background, using 256-color or truecolor forms as needed
+    pub fn bg_params(&self) -> String {
+        match *self {
+            Color::Rgb(r, g, b) => format!("48;2;{};{};{}", r, g, b),
+            _ if self.is_ansi16() => self.bg_code().to_string(),
+            _ => format!("48;5;{}", self.index().unwrap_or(0)),
+        }
+    }
+
+    /// Parse a "#rrggbb" hex string
+    pub fn from_hex(s: &str) -> Option<Color> {
+        let hex = s.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+        Some(Color::Rgb(r, g, b))
+    }
+}
+
+/// Squared distance between two RGB values
+fn rgb_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+/// JSON form: palette colors are numbers (0-255), truecolor is "#rrggbb"
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Color::Rgb(r, g, b) => serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b)),
+            _ => serializer.serialize_u8(self.index().unwrap_or(7)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl Visitor<'_> for ColorVisitor {
+            type Value = Color;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a palette index (0-255) or a \"#rrggbb\" string")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Color, E> {
+                u8::try_from(v)
+                    .map(Color::from)
+                    .map_err(|_| E::custom(format!("color index out of range: {}", v)))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Color, E> {
+                u8::try_from(v)
+                    .map(Color::from)
+                    .map_err(|_| E::custom(format!("color index out of range: {}", v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Color, E> {
+                Color::from_hex(v).ok_or_else(|| E::custom(format!("invalid color: {}", v)))
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
 }
 
 /// Cell attributes (bold, blink, etc.)
@@ -123,6 +285,94 @@ impl Attrs {
     }
 }
 
+/// Double-width characters (CJK, emoji) and the characters that take no
+/// column of their own (combining marks, zero-width joiners, variation selectors)
+const WIDE_CHARS: &[(u32, u32)] = &[
+    (0x1100, 0x115F), (0x231A, 0x231B), (0x2329, 0x232A), (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0), (0x23F3, 0x23F3), (0x25FD, 0x25FE), (0x2614, 0x2615),
+    (0x2648, 0x2653), (0x267F, 0x267F), (0x2693, 0x2693), (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB), (0x26BD, 0x26BE), (0x26C4, 0x26C5), (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4), (0x26EA, 0x26EA), (0x26F2, 0x26F3), (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA), (0x26FD, 0x26FD), (0x2705, 0x2705), (0x270A, 0x270B),
+    (0x2728, 0x2728), (0x274C, 0x274C), (0x274E, 0x274E), (0x2753, 0x2755),
+    (0x2757, 0x2757), (0x2795, 0x2797), (0x27B0, 0x27B0), (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C), (0x2B50, 0x2B50), (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),   // CJK radicals, symbols and punctuation
+    (0x3041, 0x33FF),   // Kana, Bopomofo, Hangul compatibility jamo, CJK compatibility
+    (0x3400, 0x4DBF),   // CJK extension A
+    (0x4E00, 0x9FFF),   // CJK unified ideographs
+    (0xA000, 0xA4CF),   // Yi
+    (0xA960, 0xA97F),   // Hangul jamo extended A
+    (0xAC00, 0xD7A3),   // Hangul syllables
+    (0xF900, 0xFAFF),   // CJK compatibility ideographs
+    (0xFE10, 0xFE19), (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60), (0xFFE0, 0xFFE6),  // Fullwidth forms
+    (0x1B000, 0x1B2FF), // Kana supplement
+    (0x1F004, 0x1F004), (0x1F0CF, 0x1F0CF), (0x1F18E, 0x1F18E), (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F202), (0x1F210, 0x1F23B), (0x1F240, 0x1F248), (0x1F250, 0x1F251),
+    (0x1F260, 0x1F265), (0x1F300, 0x1F320), (0x1F32D, 0x1F335), (0x1F337, 0x1F37C),
+    (0x1F37E, 0x1F393), (0x1F3A0, 0x1F3CA), (0x1F3CF, 0x1F3D3), (0x1F3E0, 0x1F3F0),
+    (0x1F3F4, 0x1F3F4), (0x1F3F8, 0x1F43E), (0x1F440, 0x1F440), (0x1F442, 0x1F4FC),
+    (0x1F4FF, 0x1F53D), (0x1F54B, 0x1F54E), (0x1F550, 0x1F567), (0x1F57A, 0x1F57A),
+    (0x1F595, 0x1F596), (0x1F5A4, 0x1F5A4), (0x1F5FB, 0x1F64F), (0x1F680, 0x1F6C5),
+    (0x1F6CC, 0x1F6CC), (0x1F6D0, 0x1F6D2), (0x1F6D5, 0x1F6D7), (0x1F6DC, 0x1F6DF),
+    (0x1F6EB, 0x1F6EC), (0x1F6F4, 0x1F6FC), (0x1F7E0, 0x1F7EB), (0x1F7F0, 0x1F7F0),
+    (0x1F90C, 0x1F93A), (0x1F93C, 0x1F945), (0x1F947, 0x1F9FF), (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD), (0x30000, 0x3FFFD),  // CJK extensions B and later
+];
+
+const ZERO_WIDTH_CHARS: &[(u32, u32)] = &[
+    (0x0300, 0x036F), (0x0483, 0x0489), (0x0591, 0x05BD), (0x0610, 0x061A),
+    (0x064B, 0x065F), (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF), (0x200B, 0x200F),
+    (0x20D0, 0x20FF), (0xFE00, 0xFE0F), (0xFE20, 0xFE2F), (0xE0100, 0xE01EF),
+];
+
+fn in_table(table: &[(u32, u32)], ch: char) -> bool {
+    let c = ch as u32;
+    table.binary_search_by(|&(lo, hi)| {
+        if hi < c {
+            std::cmp::Ordering::Less
+        } else if lo > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }).is_ok()
+}
+
+/// Columns a character takes on a terminal: 2 for CJK and emoji, 0 for
+/// combining marks and other zero-width characters, 1 for everything else
+pub fn char_width(ch: char) -> usize {
+    if (ch as u32) < 0x300 {
+        1
+    } else if in_table(WIDE_CHARS, ch) {
+        2
+    } else if in_table(ZERO_WIDTH_CHARS, ch) {
+        0
+    } else {
+        1
+    }
+}
+
+/// How a cell takes part in a double-width character
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellWidth {
+    /// An ordinary one-column cell
+    #[default]
+    Single,
+    /// The left half of a double-width character (holds the character)
+    Wide,
+    /// The right half, covered by the wide character to its left
+    Continuation,
+}
+
+impl CellWidth {
+    fn is_single(&self) -> bool {
+        *self == CellWidth::Single
+    }
+}
+
 /// A single character cell
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cell {
@@ -134,6 +384,12 @@ pub struct Cell {
     pub bg: Color,
     /// Display attributes
     pub attrs: Attrs,
+    /// Whether this cell is half of a double-width character
+    #[serde(default, skip_serializing_if = "CellWidth::is_single")]
+    pub width: CellWidth,
+    /// URI this cell links to (OSC 8 hyperlinks from terminal windows)
+    #[serde(skip)]
+    pub link: Option<Arc<str>>,
     /// Whether this cell needs redrawing
     #[serde(skip)]
     pub dirty: bool,
@@ -146,6 +402,8 @@ impl Default for Cell {
             fg: Color::White,
             bg: Color::Black,
             attrs: Attrs::default(),
+            width: CellWidth::Single,
+            link: None,
             dirty: true,
         }
     }
@@ -167,6 +425,8 @@ impl Cell {
             fg,
             bg,
             attrs: Attrs::default(),
+            width: CellWidth::Single,
+            link: None,
             dirty: true,
         }
     }
@@ -178,6 +438,8 @@ impl Cell {
             fg,
             bg,
             attrs,
+            width: CellWidth::Single,
+            link: None,
             dirty: true,
         }
     }
@@ -206,17 +468,40 @@ impl Cell {
         }
     }
 
-    /// Set all properties and mark dirty if changed
+    /// Set all properties (as a one-column cell without a link) and mark dirty if changed
     pub fn set(&mut self, char: char, fg: Color, bg: Color, attrs: Attrs) {
-        if self.char != char || self.fg != fg || self.bg != bg || self.attrs != attrs {
+        if self.char != char || self.fg != fg || self.bg != bg || self.attrs != attrs || !self.width.is_single()
+            || self.link.is_some()
+        {
             self.char = char;
             self.fg = fg;
             self.bg = bg;
             self.attrs = attrs;
+            self.width = CellWidth::Single;
+            self.link = None;
             self.dirty = true;
         }
     }
 
+    /// Copy another cell's content (including its width) and mark dirty if changed
+    pub fn set_from(&mut self, other: &Cell) {
+        if !self.same_content(other) {
+            self.char = other.char;
+            self.fg = other.fg;
+            self.bg = other.bg;
+            self.attrs = other.attrs;
+            self.width = other.width;
+            self.link = other.link.clone();
+            self.dirty = true;
+        }
+    }
+
+    /// Check if two cells look the same on screen (ignores the dirty flag)
+    pub fn same_content(&self, other: &Cell) -> bool {
+        self.char == other.char && self.fg == other.fg && self.bg == other.bg && self.attrs == other.attrs
+            && self.width == other.width && self.link == other.link
+    }
+
     /// Clear the cell to defaults
     pub fn clear(&mut self) {
         *self = Self::default();
@@ -236,11 +521,58 @@ mod tests {
         assert_eq!(Color::BrightWhite.bg_code(), 107);
     }
 
+    #[test]
+    fn test_color_from_u8_keeps_extended() {
+        assert_eq!(Color::from(9), Color::BrightRed);
+        assert_eq!(Color::from(196), Color::Indexed(196));
+        assert_eq!(Color::from(196).fg_params(), "38;5;196");
+        assert_eq!(Color::Rgb(1, 2, 3).bg_params(), "48;2;1;2;3");
+        assert_eq!(Color::Green.fg_params(), "32");
+    }
+
+    #[test]
+    fn test_color_downsampling() {
+        assert_eq!(Color::Indexed(196).to_ansi16(), Color::Red);
+        assert_eq!(Color::Rgb(250, 80, 80).to_ansi16(), Color::BrightRed);
+        assert_eq!(Color::Rgb(255, 0, 0).to_indexed(), Color::Indexed(196));
+        assert_eq!(Color::Rgb(128, 128, 128).to_indexed(), Color::Indexed(244));
+        assert_eq!(Color::Blue.to_indexed(), Color::Blue);
+    }
+
+    #[test]
+    fn test_color_json() {
+        assert_eq!(serde_json::to_string(&Color::BrightGreen).unwrap(), "10");
+        assert_eq!(serde_json::to_string(&Color::Rgb(255, 128, 0)).unwrap(), "\"#ff8000\"");
+        assert_eq!(serde_json::from_str::<Color>("200").unwrap(), Color::Indexed(200));
+        assert_eq!(serde_json::from_str::<Color>("\"#00ff00\"").unwrap(), Color::Rgb(0, 255, 0));
+        assert!(serde_json::from_str::<Color>("300").is_err());
+    }
+
     #[test]
     fn test_cell_dirty() {
-        let mut cell = Cell::default();
-        cell.dirty = false;
+        let mut cell = Cell { dirty: false, ..Default::default() };
         cell.set_char('X');
         assert!(cell.dirty);
     }
+
+    #[test]
+    fn test_char_width() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('═'), 1);
+        assert_eq!(char_width('漢'), 2);
+        assert_eq!(char_width('한'), 2);
+        assert_eq!(char_width('Ａ'), 2);
+        assert_eq!(char_width('🎉'), 2);
+        assert_eq!(char_width('\u{301}'), 0);
+        assert_eq!(char_width('\u{200d}'), 0);
+    }
+
+    #[test]
+    fn test_same_content_ignores_dirty() {
+        let mut a = Cell::new('A');
+        let b = Cell::new('A');
+        a.dirty = false;
+        assert!(a.same_content(&b));
+        assert!(!a.same_content(&Cell::new('B')));
+    }
 }
@@ -3,7 +3,7 @@
 //! A 2D array of cells representing the terminal display.
 //! Supports efficient dirty-rectangle tracking for optimized updates.
 
-use super::cell::{Attrs, Cell, Color};
+use super::cell::{char_width, Attrs, Cell, CellWidth, Color};
 
 /// Box drawing character sets
 pub struct BoxChars {
@@ -61,6 +61,7 @@ pub mod box_styles {
 }
 
 /// The display grid - a 2D array of cells
+#[derive(Clone)]
 pub struct Grid {
     /// Grid width in columns
     pub cols: usize,
@@ -104,6 +105,59 @@ impl Grid {
         }
     }
 
+    /// Copy a cell (including its width) to a position
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: &Cell) {
+        if let Some(dst) = self.get_mut(x, y) {
+            dst.set_from(cell);
+        }
+    }
+
+    /// Put a character at a position, taking two cells if it's double-width
+    /// Returns the columns used (0 if it doesn't fit or takes no space)
+    pub fn put_char(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Color, attrs: Attrs) -> usize {
+        let width = char_width(ch);
+        if width == 0 || x + width > self.cols || y >= self.rows {
+            return 0;
+        }
+        if width == 1 {
+            self.set(x, y, ch, fg, bg, attrs);
+        } else {
+            let wide = Cell { width: CellWidth::Wide, ..Cell::full(ch, fg, bg, attrs) };
+            let continuation = Cell { width: CellWidth::Continuation, ..Cell::full(' ', fg, bg, attrs) };
+            self.set_cell(x, y, &wide);
+            self.set_cell(x + 1, y, &continuation);
+        }
+        width
+    }
+
+    /// What the cell at a position shows: its character and how many columns it
+    /// spans (2 for a wide character followed by its continuation). None for a
+    /// continuation, which the character to its left draws; half of a wide
+    /// character that lost its other half shows as a blank
+    pub fn glyph(&self, x: usize, y: usize) -> Option<(char, usize)> {
+        let cell = self.get(x, y)?;
+        match cell.width {
+            CellWidth::Single => Some((cell.char, 1)),
+            CellWidth::Wide => match self.get(x + 1, y) {
+                Some(next) if next.width == CellWidth::Continuation => Some((cell.char, 2)),
+                _ => Some((' ', 1)),
+            },
+            CellWidth::Continuation => match x.checked_sub(1).and_then(|px| self.get(px, y)) {
+                Some(prev) if prev.width == CellWidth::Wide => None,
+                _ => Some((' ', 1)),
+            },
+        }
+    }
+
+    /// The position that draws a cell: the wide character to its left for a
+    /// continuation, otherwise the cell itself
+    pub fn glyph_origin(&self, x: usize, y: usize) -> (usize, usize) {
+        match self.glyph(x, y) {
+            None => (x - 1, y),
+            Some(_) => (x, y),
+        }
+    }
+
     /// Set just the character at position
     pub fn set_char(&mut self, x: usize, y: usize, char: char) {
         if let Some(cell) = self.get_mut(x, y) {
@@ -130,31 +184,36 @@ impl Grid {
     }
 
     /// Copy contents from another grid
+    /// Only cells whose content actually changes are marked dirty
     pub fn copy_from(&mut self, other: &Grid) {
         // Only copy if dimensions match
         if self.cols == other.cols && self.rows == other.rows {
             for (dst, src) in self.cells.iter_mut().zip(other.cells.iter()) {
-                dst.char = src.char;
-                dst.fg = src.fg;
-                dst.bg = src.bg;
-                dst.attrs = src.attrs;
-                dst.dirty = true;
+                dst.set_from(src);
             }
         }
     }
 
-    /// Write a string at position
+    /// Write a string at position (double-width characters take two columns)
     pub fn write_str(&mut self, x: usize, y: usize, s: &str, fg: Color, bg: Color, attrs: Attrs) {
-        for (i, ch) in s.chars().enumerate() {
-            let px = x + i;
+        let mut px = x;
+        for ch in s.chars() {
             if px >= self.cols {
                 break;
             }
-            self.set(px, y, ch, fg, bg, attrs);
+            if char_width(ch) == 0 {
+                continue;
+            }
+            match self.put_char(px, y, ch, fg, bg, attrs) {
+                // A wide character that doesn't fit in the last column ends the line
+                0 => break,
+                width => px += width,
+            }
         }
     }
 
     /// Fill a rectangular region
+    #[allow(clippy::too_many_arguments)]
     pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, char: char, fg: Color, bg: Color) {
         for dy in 0..h {
             for dx in 0..w {
@@ -164,6 +223,7 @@ impl Grid {
     }
 
     /// Draw a box border
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_box(&mut self, x: usize, y: usize, w: usize, h: usize, style: &BoxChars, fg: Color, bg: Color) {
         if w < 2 || h < 2 {
             return;
@@ -237,6 +297,14 @@ impl Grid {
         self.iter().filter(|(_, _, cell)| cell.dirty)
     }
 
+    /// Get iterator over cells whose content differs from another grid of the same size
+    /// (e.g. the last frame a renderer sent)
+    pub fn iter_changed<'a>(&'a self, other: &'a Grid) -> impl Iterator<Item = (usize, usize, &'a Cell)> {
+        self.iter().filter(move |(x, y, cell)| {
+            other.get(*x, *y).is_none_or(|old| !cell.same_content(old))
+        })
+    }
+
     /// Resize the grid (content is lost)
     pub fn resize(&mut self, cols: usize, rows: usize) {
         self.cols = cols;
@@ -244,17 +312,32 @@ impl Grid {
         self.cells = vec![Cell::default(); cols * rows];
     }
 
+    /// Copy of this grid at another size: cropped (padded with blanks if
+    /// smaller), or scaled by sampling the nearest cell
+    pub fn fit_to(&self, cols: usize, rows: usize, scale: bool) -> Grid {
+        let mut grid = Grid::new(cols, rows);
+        if scale && self.cols > 0 && self.rows > 0 {
+            for y in 0..rows {
+                for x in 0..cols {
+                    if let Some(src) = self.get(x * self.cols / cols, y * self.rows / rows) {
+                        grid.set_cell(x, y, src);
+                    }
+                }
+            }
+        } else {
+            grid.blit(self, 0, 0, 0, 0, cols.min(self.cols), rows.min(self.rows));
+        }
+        grid
+    }
+
     /// Copy region from another grid
+    #[allow(clippy::too_many_arguments)]
     pub fn blit(&mut self, src: &Grid, src_x: usize, src_y: usize, dst_x: usize, dst_y: usize, w: usize, h: usize) {
         for dy in 0..h {
             for dx in 0..w {
                 if let Some(src_cell) = src.get(src_x + dx, src_y + dy) {
                     if let Some(dst_cell) = self.get_mut(dst_x + dx, dst_y + dy) {
-                        dst_cell.char = src_cell.char;
-                        dst_cell.fg = src_cell.fg;
-                        dst_cell.bg = src_cell.bg;
-                        dst_cell.attrs = src_cell.attrs;
-                        dst_cell.dirty = true;
+                        dst_cell.set_from(src_cell);
                     }
                 }
             }
@@ -292,4 +375,54 @@ mod tests {
         assert_eq!(grid.get(6, 10).unwrap().char, 'e');
         assert_eq!(grid.get(9, 10).unwrap().char, 'o');
     }
+
+    #[test]
+    fn test_write_str_wide_chars() {
+        let mut grid = Grid::new(6, 2);
+        grid.write_str(0, 0, "a漢b字", Color::White, Color::Black, Attrs::default());
+        assert_eq!(grid.glyph(0, 0), Some(('a', 1)));
+        assert_eq!(grid.glyph(1, 0), Some(('漢', 2)));
+        assert_eq!(grid.glyph(2, 0), None);
+        assert_eq!(grid.glyph_origin(2, 0), (1, 0));
+        assert_eq!(grid.glyph(3, 0), Some(('b', 1)));
+        assert_eq!(grid.glyph(4, 0), Some(('字', 2)));
+
+        // A wide character doesn't fit in the last column
+        grid.write_str(4, 1, "a漢", Color::White, Color::Black, Attrs::default());
+        assert_eq!(grid.get(5, 1).unwrap().char, ' ');
+
+        // Overwriting half of a wide character leaves the other half blank
+        grid.set(4, 0, 'x', Color::White, Color::Black, Attrs::default());
+        assert_eq!(grid.glyph(5, 0), Some((' ', 1)));
+    }
+
+    #[test]
+    fn test_copy_from_marks_only_changes() {
+        let mut src = Grid::new(10, 5);
+        let mut dst = Grid::new(10, 5);
+        dst.copy_from(&src);
+        dst.mark_all_clean();
+
+        src.set(3, 2, 'Z', Color::Red, Color::Black, Attrs::default());
+        dst.copy_from(&src);
+        assert_eq!(dst.iter_dirty().count(), 1);
+        assert_eq!(dst.get(3, 2).unwrap().char, 'Z');
+    }
+
+    #[test]
+    fn test_fit_to_crop_and_scale() {
+        let mut grid = Grid::new(8, 4);
+        grid.set_char(6, 2, 'X');
+
+        let cropped = grid.fit_to(4, 2, false);
+        assert_eq!((cropped.cols, cropped.rows), (4, 2));
+        assert!(cropped.iter().all(|(_, _, cell)| cell.char == ' '));
+
+        let scaled = grid.fit_to(4, 2, true);
+        assert_eq!(scaled.get(3, 1).unwrap().char, 'X');
+
+        // Larger targets are padded
+        let padded = grid.fit_to(10, 6, false);
+        assert_eq!(padded.get(6, 2).unwrap().char, 'X');
+    }
 }
@@ -9,6 +9,6 @@ pub mod cell;
 pub mod grid;
 pub mod window;
 
-pub use cell::{Attrs, Cell, Color};
+pub use cell::{char_width, Attrs, Cell, CellWidth, Color};
 pub use grid::{box_styles, BoxChars, Grid};
 pub use window::{Window, WindowManager, InteractionState, DragState, ResizeState, TitleBarClick};
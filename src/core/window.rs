@@ -4,13 +4,14 @@
 //! The WindowManager handles z-ordering and compositing.
 
 use std::collections::HashMap;
-use super::cell::{Attrs, Color};
+use super::cell::{Attrs, Cell, Color};
 use super::grid::{box_styles, BoxChars, Grid};
 
 /// Border style for windows
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum BorderStyle {
     None,
+    #[default]
     Single,
     Double,
     Rounded,
@@ -37,12 +38,6 @@ impl BorderStyle {
     }
 }
 
-impl Default for BorderStyle {
-    fn default() -> Self {
-        BorderStyle::Single
-    }
-}
-
 /// Title alignment
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TitleAlign {
@@ -53,6 +48,7 @@ pub enum TitleAlign {
 }
 
 /// A window on the display
+#[derive(Clone)]
 pub struct Window {
     /// Unique identifier
     pub id: String,
@@ -185,14 +181,21 @@ impl Window {
         self.dirty = true;
     }
 
-    /// Set a single cell in content area
+    /// Set a single cell in content area (a wide character takes two)
     pub fn set(&mut self, x: usize, y: usize, ch: char, fg: Color, bg: Option<Color>) {
         let bg = bg.unwrap_or(self.background);
-        self.content.set(x, y, ch, fg, bg, Attrs::default());
+        self.content.put_char(x, y, ch, fg, bg, Attrs::default());
+        self.dirty = true;
+    }
+
+    /// Copy a cell (e.g. half of a wide character) into the content area
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: &Cell) {
+        self.content.set_cell(x, y, cell);
         self.dirty = true;
     }
 
     /// Fill a rectangle in content area
+    #[allow(clippy::too_many_arguments)]
     pub fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, ch: char, fg: Color, bg: Option<Color>) {
         let bg = bg.unwrap_or(self.background);
         self.content.fill_rect(x, y, w, h, ch, fg, bg);
@@ -277,7 +280,8 @@ impl Window {
                     if tx < target.cols && ty < target.rows {
                         // Get current cell and swap fg/bg
                         if let Some(cell) = target.get(tx, ty) {
-                            target.set(tx, ty, cell.char, cell.bg, cell.fg, cell.attrs);
+                            let inverted = Cell { fg: cell.bg, bg: cell.fg, ..cell.clone() };
+                            target.set_cell(tx, ty, &inverted);
                         }
                     }
                 }
@@ -354,14 +358,7 @@ impl Window {
         let content_start_y = self.y + offset_y;
 
         for (cx, cy, cell) in self.content.iter() {
-            target.set(
-                content_start_x + cx,
-                content_start_y + cy,
-                cell.char,
-                cell.fg,
-                cell.bg,
-                cell.attrs,
-            );
+            target.set_cell(content_start_x + cx, content_start_y + cy, cell);
         }
     }
 }
@@ -16,7 +16,12 @@ pub enum InputEvent {
     Char { char: char },
 
     /// A key press
-    Key { key: Key },
+    Key {
+        key: Key,
+        /// Modifiers held with the key (left out of JSON when there are none)
+        #[serde(default, skip_serializing_if = "Modifiers::is_empty")]
+        modifiers: Modifiers,
+    },
 
     /// Mouse event
     Mouse {
@@ -28,6 +33,13 @@ pub enum InputEvent {
     },
 }
 
+impl InputEvent {
+    /// A key press without modifiers
+    pub fn key(key: Key) -> Self {
+        InputEvent::Key { key, modifiers: Modifiers::default() }
+    }
+}
+
 /// Special keys
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -79,6 +91,12 @@ pub struct Modifiers {
     pub alt: bool,
 }
 
+impl Modifiers {
+    pub fn is_empty(&self) -> bool {
+        !(self.shift || self.ctrl || self.alt)
+    }
+}
+
 /// Input parser state machine
 pub struct InputParser {
     /// Buffer for incomplete escape sequences
@@ -136,9 +154,9 @@ impl InputParser {
         // Control characters (< 32) and DEL (0x7f)
         if first < 32 || first == 0x7f {
             let event = match first {
-                0x0d | 0x0a => Some(InputEvent::Key { key: Key::Enter }),
-                0x09 => Some(InputEvent::Key { key: Key::Tab }),
-                0x7f | 0x08 => Some(InputEvent::Key { key: Key::Backspace }),
+                0x0d | 0x0a => Some(InputEvent::key(Key::Enter)),
+                0x09 => Some(InputEvent::key(Key::Tab)),
+                0x7f | 0x08 => Some(InputEvent::key(Key::Backspace)),
                 0x03 => Some(InputEvent::Char { char: '\x03' }), // Ctrl+C
                 _ => Some(InputEvent::Char { char: first as char }),
             };
@@ -216,38 +234,42 @@ impl InputParser {
 
     /// Decode CSI parameters into an event
     fn decode_csi(&self, params: &[u8], final_byte: u8) -> Option<InputEvent> {
-        match final_byte {
-            b'A' => Some(InputEvent::Key { key: Key::Up }),
-            b'B' => Some(InputEvent::Key { key: Key::Down }),
-            b'C' => Some(InputEvent::Key { key: Key::Right }),
-            b'D' => Some(InputEvent::Key { key: Key::Left }),
-            b'H' => Some(InputEvent::Key { key: Key::Home }),
-            b'F' => Some(InputEvent::Key { key: Key::End }),
-            b'~' => {
-                // Parse the number before ~
-                let num: u8 = params.iter()
-                    .take_while(|&&b| b.is_ascii_digit())
-                    .fold(0, |acc, &b| acc * 10 + (b - b'0'));
-                match num {
-                    1 => Some(InputEvent::Key { key: Key::Home }),
-                    2 => Some(InputEvent::Key { key: Key::Insert }),
-                    3 => Some(InputEvent::Key { key: Key::Delete }),
-                    4 => Some(InputEvent::Key { key: Key::End }),
-                    5 => Some(InputEvent::Key { key: Key::PageUp }),
-                    6 => Some(InputEvent::Key { key: Key::PageDown }),
-                    15 => Some(InputEvent::Key { key: Key::F5 }),
-                    17 => Some(InputEvent::Key { key: Key::F6 }),
-                    18 => Some(InputEvent::Key { key: Key::F7 }),
-                    19 => Some(InputEvent::Key { key: Key::F8 }),
-                    20 => Some(InputEvent::Key { key: Key::F9 }),
-                    21 => Some(InputEvent::Key { key: Key::F10 }),
-                    23 => Some(InputEvent::Key { key: Key::F11 }),
-                    24 => Some(InputEvent::Key { key: Key::F12 }),
-                    _ => None,
-                }
-            }
-            _ => None,
-        }
+        // Parameters are "number;modifiers" (e.g. ESC [ 5 ; 2 ~ is Shift+PageUp)
+        let mut fields = params.split(|&b| b == b';').map(|field| {
+            field.iter()
+                .take_while(|b| b.is_ascii_digit())
+                .fold(0u16, |acc, &b| acc.saturating_mul(10).saturating_add((b - b'0') as u16))
+        });
+        let num = fields.next().unwrap_or(0);
+        let modifiers = decode_key_modifiers(fields.next().unwrap_or(1));
+
+        let key = match final_byte {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            b'~' => match num {
+                1 => Key::Home,
+                2 => Key::Insert,
+                3 => Key::Delete,
+                4 => Key::End,
+                5 => Key::PageUp,
+                6 => Key::PageDown,
+                15 => Key::F5,
+                17 => Key::F6,
+                18 => Key::F7,
+                19 => Key::F8,
+                20 => Key::F9,
+                21 => Key::F10,
+                23 => Key::F11,
+                24 => Key::F12,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(InputEvent::Key { key, modifiers })
     }
 
     /// Parse SS3 sequence: ESC O
@@ -257,16 +279,16 @@ impl InputParser {
         }
 
         let event = match self.buffer[2] {
-            b'P' => Some(InputEvent::Key { key: Key::F1 }),
-            b'Q' => Some(InputEvent::Key { key: Key::F2 }),
-            b'R' => Some(InputEvent::Key { key: Key::F3 }),
-            b'S' => Some(InputEvent::Key { key: Key::F4 }),
-            b'A' => Some(InputEvent::Key { key: Key::Up }),
-            b'B' => Some(InputEvent::Key { key: Key::Down }),
-            b'C' => Some(InputEvent::Key { key: Key::Right }),
-            b'D' => Some(InputEvent::Key { key: Key::Left }),
-            b'H' => Some(InputEvent::Key { key: Key::Home }),
-            b'F' => Some(InputEvent::Key { key: Key::End }),
+            b'P' => Some(InputEvent::key(Key::F1)),
+            b'Q' => Some(InputEvent::key(Key::F2)),
+            b'R' => Some(InputEvent::key(Key::F3)),
+            b'S' => Some(InputEvent::key(Key::F4)),
+            b'A' => Some(InputEvent::key(Key::Up)),
+            b'B' => Some(InputEvent::key(Key::Down)),
+            b'C' => Some(InputEvent::key(Key::Right)),
+            b'D' => Some(InputEvent::key(Key::Left)),
+            b'H' => Some(InputEvent::key(Key::Home)),
+            b'F' => Some(InputEvent::key(Key::End)),
             _ => None,
         };
 
@@ -421,6 +443,16 @@ fn decode_x10_button(cb: u8) -> (MouseButton, MouseEvent) {
     (button, event)
 }
 
+/// Decode the xterm key modifier parameter (1 + shift 1 | alt 2 | ctrl 4)
+fn decode_key_modifiers(param: u16) -> Modifiers {
+    let bits = param.saturating_sub(1);
+    Modifiers {
+        shift: (bits & 1) != 0,
+        alt: (bits & 2) != 0,
+        ctrl: (bits & 4) != 0,
+    }
+}
+
 /// Decode X10 modifiers
 fn decode_x10_modifiers(cb: u8) -> Modifiers {
     let b = cb.saturating_sub(32);
@@ -489,10 +521,10 @@ mod tests {
         let mut parser = InputParser::new();
 
         let events = parser.parse(b"\x1b[A");
-        assert_eq!(events, vec![InputEvent::Key { key: Key::Up }]);
+        assert_eq!(events, vec![InputEvent::key(Key::Up)]);
 
         let events = parser.parse(b"\x1b[B");
-        assert_eq!(events, vec![InputEvent::Key { key: Key::Down }]);
+        assert_eq!(events, vec![InputEvent::key(Key::Down)]);
     }
 
     #[test]
@@ -520,6 +552,22 @@ mod tests {
         assert_eq!(events[0], InputEvent::Char { char: 'a' });
         assert_eq!(events[1], InputEvent::Char { char: 'b' });
         assert_eq!(events[2], InputEvent::Char { char: 'c' });
-        assert_eq!(events[3], InputEvent::Key { key: Key::Up });
+        assert_eq!(events[3], InputEvent::key(Key::Up));
+    }
+
+    #[test]
+    fn test_parse_modified_keys() {
+        let mut parser = InputParser::new();
+        let events = parser.parse(b"\x1b[5;2~\x1b[1;5D\x1b[6~");
+        let shift = Modifiers { shift: true, ..Default::default() };
+        let ctrl = Modifiers { ctrl: true, ..Default::default() };
+        assert_eq!(events, vec![
+            InputEvent::Key { key: Key::PageUp, modifiers: shift },
+            InputEvent::Key { key: Key::Left, modifiers: ctrl },
+            InputEvent::key(Key::PageDown),
+        ]);
+
+        // Games only see modifiers when there are some
+        assert_eq!(serde_json::to_string(&events[2]).unwrap(), r#"{"type":"key","key":"page_down"}"#);
     }
 }
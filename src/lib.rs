@@ -7,9 +7,9 @@
 //! APU provides:
 //! - A cell grid for display buffering
 //! - A window manager for overlapping windows
-//! - Renderers for different terminal types (starting with IBM ANSI)
+//! - Renderers for different terminal types (IBM ANSI, VT100, plain ASCII)
 //! - A JSON protocol for game integration
-//! - A TCP server for network connections
+//! - A TCP server for network connections (telnet and WebSocket clients)
 //!
 //! # Example
 //!
@@ -25,19 +25,25 @@
 //!
 //! let mut renderer = AnsiIbmRenderer::standard();
 //! let output = renderer.render_full(&wm.display);
-//! print!("{}", output);
+//! std::io::Write::write_all(&mut std::io::stdout(), &output).unwrap();
 //! ```
 
+pub mod auth;
+pub mod binary;
 pub mod core;
 pub mod renderer;
 pub mod protocol;
+pub mod recording;
 pub mod server;
+pub mod telnet;
 pub mod input;
 pub mod terminal;
+pub mod transport;
+pub mod websocket;
 
 // Re-export commonly used types
 pub use core::{Cell, Color, Attrs, Grid, Window, WindowManager};
-pub use renderer::{AnsiIbmRenderer, Renderer};
+pub use renderer::{AnsiIbmRenderer, AsciiRenderer, Charset, Renderer, Vt100Renderer};
 pub use protocol::{Command, Response};
 pub use server::Server;
 pub use input::{InputEvent, InputParser, Key, MouseButton, MouseEvent, Modifiers};
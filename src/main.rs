@@ -5,6 +5,17 @@
 //! Options:
 //!   --game-bind <addr>  Bind game port to address (default: 127.0.0.1)
 //!                       Use 0.0.0.0 for network access (requires auth)
+//!   --websocket-port <port>  Also accept WebSocket clients (e.g. xterm.js) on this port
+//!   --auth-token <token>[=<session glob>,...]  Require games to authenticate
+//!                       (repeatable; also read from APU_AUTH_TOKEN)
+//!   --resume-grace <secs>  Keep dropped sessions this long for resuming (default 60, 0 = off)
+//!   --game-socket <path>  Also accept game connections on this Unix socket
+//!   --game-exec <command>  Run the game as a child process, speaking the protocol
+//!                       over its stdin/stdout (the server exits when it does)
+//!   --allow-local-terminals  Let games open terminal windows running local
+//!                       programs (`pty` and `ssh` transports)
+//!   --record-dir <dir>  Write recordings here (games can then ask for them)
+//!   --record-sessions <log|cast|both>  Record every session's output
 //!
 //! Default ports:
 //! - Game port: 6122 (games connect here to send commands)
@@ -13,6 +24,8 @@
 use std::env;
 use log::info;
 
+use ascii_processing_unit::auth::AuthToken;
+use ascii_processing_unit::recording::RecordFormats;
 use ascii_processing_unit::Server;
 
 #[tokio::main]
@@ -26,6 +39,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut game_port: u16 = 6122;
     let mut client_port: u16 = 6123;
     let mut game_bind = "127.0.0.1".to_string();
+    let mut websocket_port: Option<u16> = None;
+    let mut auth_tokens: Vec<AuthToken> = Vec::new();
+    let mut resume_grace: Option<u64> = None;
+    let mut game_socket: Option<String> = None;
+    let mut game_command: Option<String> = None;
+    let mut local_terminals = false;
+    let mut record_dir: Option<String> = None;
+    let mut record_sessions: Option<RecordFormats> = None;
+
+    if let Ok(spec) = env::var("APU_AUTH_TOKEN") {
+        match AuthToken::parse(&spec) {
+            Some(token) => auth_tokens.push(token),
+            None => {
+                eprintln!("Error: APU_AUTH_TOKEN is empty");
+                std::process::exit(1);
+            }
+        }
+    }
 
     let mut i = 1;
     while i < args.len() {
@@ -39,6 +70,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     std::process::exit(1);
                 }
             }
+            "--websocket-port" => {
+                match args.get(i + 1).and_then(|p| p.parse::<u16>().ok()) {
+                    Some(port) => {
+                        websocket_port = Some(port);
+                        i += 2;
+                    }
+                    None => {
+                        eprintln!("Error: --websocket-port requires a port number");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--auth-token" => {
+                match args.get(i + 1).and_then(|spec| AuthToken::parse(spec)) {
+                    Some(token) => {
+                        auth_tokens.push(token);
+                        i += 2;
+                    }
+                    None => {
+                        eprintln!("Error: --auth-token requires a token");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--resume-grace" => {
+                match args.get(i + 1).and_then(|s| s.parse::<u64>().ok()) {
+                    Some(secs) => {
+                        resume_grace = Some(secs);
+                        i += 2;
+                    }
+                    None => {
+                        eprintln!("Error: --resume-grace requires a number of seconds");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--game-socket" => {
+                if i + 1 < args.len() {
+                    game_socket = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Error: --game-socket requires a path");
+                    std::process::exit(1);
+                }
+            }
+            "--game-exec" => {
+                if i + 1 < args.len() {
+                    game_command = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Error: --game-exec requires a command");
+                    std::process::exit(1);
+                }
+            }
+            "--allow-local-terminals" => {
+                local_terminals = true;
+                i += 1;
+            }
+            "--record-dir" => {
+                if i + 1 < args.len() {
+                    record_dir = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("Error: --record-dir requires a directory");
+                    std::process::exit(1);
+                }
+            }
+            "--record-sessions" => {
+                match args.get(i + 1).and_then(|s| RecordFormats::parse(s)) {
+                    Some(formats) => {
+                        record_sessions = Some(formats);
+                        i += 2;
+                    }
+                    None => {
+                        eprintln!("Error: --record-sessions requires log, cast or both");
+                        std::process::exit(1);
+                    }
+                }
+            }
             "--help" | "-h" => {
                 println!("APU - ASCII Processing Unit v0.1.0");
                 println!();
@@ -47,11 +157,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("Options:");
                 println!("  --game-bind <addr>  Bind game port to address (default: 127.0.0.1)");
                 println!("                      Use 0.0.0.0 for network access");
+                println!("  --websocket-port <port>  Also accept WebSocket clients (browser terminals)");
+                println!("  --auth-token <token>[=<glob>,...]  Require games to send this token first,");
+                println!("                      optionally limited to matching session IDs (repeatable,");
+                println!("                      or set APU_AUTH_TOKEN)");
+                println!("  --resume-grace <secs>  Keep dropped sessions for resuming (default 60, 0 = off)");
+                println!("  --game-socket <path>  Also accept games on this Unix socket");
+                println!("  --game-exec <command>  Run the game as a child process over its stdin/stdout");
+                println!("  --allow-local-terminals  Let games run local programs (shells, ssh) in");
+                println!("                      terminal windows, as this server's user");
+                println!("  --record-dir <dir>  Write recordings here (games may ask to record terminals)");
+                println!("  --record-sessions <log|cast|both>  Record every session's output (raw log");
+                println!("                      and/or asciicast) in the recording directory");
                 println!("  --help, -h          Show this help");
                 println!();
                 println!("Examples:");
                 println!("  apu-server 6122 6123                    # Local game, public telnet");
-                println!("  apu-server 6122 6123 --game-bind 0.0.0.0  # Network game connections");
+                println!("  apu-server 6122 6123 --game-bind 0.0.0.0 --auth-token s3cret  # Network game connections");
+                println!("  apu-server 6122 6123 --websocket-port 6124  # Browser clients too");
+                println!("  apu-server --game-exec ./my-game        # Game talks over stdin/stdout");
                 std::process::exit(0);
             }
             arg => {
@@ -68,8 +192,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let network_warning = if game_bind == "0.0.0.0" {
-        "\n║  ⚠️  WARNING: Game port open to network!                       ║"
+    if record_sessions.is_some() && record_dir.is_none() {
+        eprintln!("Error: --record-sessions requires --record-dir");
+        std::process::exit(1);
+    }
+
+    let network_warning = if game_bind == "0.0.0.0" && auth_tokens.is_empty() {
+        "\n║  ⚠️  WARNING: Game port open to network without --auth-token!  ║"
     } else {
         ""
     };
@@ -80,12 +209,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("╠═══════════════════════════════════════════════════════════════╣");
     info!("║  Game port:   {} (bind: {})                       ║", game_port, game_bind);
     info!("║  Client port: {} (bind: 0.0.0.0)                          ║", client_port);
+    if let Some(port) = websocket_port {
+        info!("║  WebSocket:   {} (bind: 0.0.0.0)                          ║", port);
+    }
+    if let Some(path) = &game_socket {
+        info!("║  Game socket: {}", path);
+    }
     if !network_warning.is_empty() {
         info!("{}", network_warning.trim_start_matches('\n'));
     }
     info!("╚═══════════════════════════════════════════════════════════════╝");
 
-    let server = Server::new(game_port, client_port, game_bind);
+    let mut server = Server::new(game_port, client_port, game_bind);
+    server.websocket_port = websocket_port;
+    server.auth_tokens = auth_tokens;
+    server.game_socket = game_socket.map(Into::into);
+    server.game_command = game_command;
+    server.local_terminals = local_terminals;
+    server.record_dir = record_dir.map(Into::into);
+    server.record_sessions = record_sessions.unwrap_or_default();
+    if let Some(secs) = resume_grace {
+        server.resume_grace = std::time::Duration::from_secs(secs);
+    }
     server.run().await?;
 
     Ok(())
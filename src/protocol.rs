@@ -17,6 +17,8 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::core::Color;
+
 /// Commands from game to APU
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "cmd", rename_all = "snake_case")]
@@ -25,6 +27,19 @@ pub enum Command {
     Init {
         cols: Option<usize>,
         rows: Option<usize>,
+        /// Client color depth: "16" (default), "256" or "truecolor"
+        /// Colors beyond the client's depth are downsampled
+        #[serde(default)]
+        color_depth: Option<String>,
+        /// Renderer: "ansi-ibm", "vt100" or "ascii"
+        /// Defaults to the best match for the client's terminal type
+        #[serde(default)]
+        renderer: Option<String>,
+        /// Client character set: "utf-8" (default), "cp437", "petscii" or "atascii"
+        /// (the last two only with the "cells" renderer: their terminals don't
+        /// understand ANSI escape sequences)
+        #[serde(default)]
+        charset: Option<String>,
     },
 
     /// Shutdown display
@@ -105,9 +120,9 @@ pub enum Command {
         y: usize,
         char: char,
         #[serde(default = "default_fg")]
-        fg: u8,
-        #[serde(default)]
-        bg: u8,
+        fg: Color,
+        #[serde(default = "default_bg")]
+        bg: Color,
     },
 
     /// Write text to a window
@@ -117,9 +132,9 @@ pub enum Command {
         y: usize,
         text: String,
         #[serde(default = "default_fg")]
-        fg: u8,
-        #[serde(default)]
-        bg: u8,
+        fg: Color,
+        #[serde(default = "default_bg")]
+        bg: Color,
     },
 
     /// Clear a window
@@ -136,9 +151,9 @@ pub enum Command {
         height: usize,
         char: char,
         #[serde(default = "default_fg")]
-        fg: u8,
-        #[serde(default)]
-        bg: u8,
+        fg: Color,
+        #[serde(default = "default_bg")]
+        bg: Color,
     },
 
     /// Set a cell directly on display (no window)
@@ -147,9 +162,9 @@ pub enum Command {
         y: usize,
         char: char,
         #[serde(default = "default_fg")]
-        fg: u8,
-        #[serde(default)]
-        bg: u8,
+        fg: Color,
+        #[serde(default = "default_bg")]
+        bg: Color,
     },
 
     /// Write text directly to display
@@ -158,9 +173,9 @@ pub enum Command {
         y: usize,
         text: String,
         #[serde(default = "default_fg")]
-        fg: u8,
-        #[serde(default)]
-        bg: u8,
+        fg: Color,
+        #[serde(default = "default_bg")]
+        bg: Color,
     },
 
     /// Batch update - multiple cells at once
@@ -194,6 +209,28 @@ pub enum Command {
     /// Disable mouse tracking
     DisableMouse,
 
+    /// Authenticate with a shared secret (must be the first command when the
+    /// server has tokens configured)
+    Auth {
+        token: String,
+    },
+
+    /// Send the rest of this connection's commands as binary frames
+    Binary,
+
+    /// Name this game connection; the lobby game gets every new player
+    /// (so only an unscoped game may be it)
+    RegisterGame {
+        name: String,
+        #[serde(default)]
+        lobby: bool,
+    },
+
+    /// Hand the targeted session to another game (by name)
+    Handoff {
+        game: String,
+    },
+
     /// List all connected sessions
     ListSessions,
 
@@ -203,6 +240,12 @@ pub enum Command {
         source: String,
         /// Target session that will see the shared display
         target: String,
+        /// Drop the target's input while it watches (read-only spectator)
+        #[serde(default)]
+        spectator: bool,
+        /// Scale the display to the target's size instead of cropping it
+        #[serde(default)]
+        scale: bool,
     },
 
     /// Stop sharing display
@@ -214,6 +257,7 @@ pub enum Command {
     },
 
     /// Share a specific window from one session to another
+    /// The target gets a live mirror with ID `<window_id>@<source>`, updated on each flush of the source
     ShareWindow {
         /// Window ID to share
         window_id: String,
@@ -221,6 +265,14 @@ pub enum Command {
         source: String,
         /// Target session that will see the window
         target: String,
+        /// Position in the target (defaults to the source window's position)
+        #[serde(default)]
+        x: Option<usize>,
+        #[serde(default)]
+        y: Option<usize>,
+        /// Send input on the mirror to the game as input from the source session
+        #[serde(default)]
+        route_input: bool,
     },
 
     /// Stop sharing a window
@@ -240,10 +292,19 @@ pub enum Command {
     CreateTerminal {
         /// Window ID for the terminal
         id: String,
-        /// Remote host to connect to
+        /// Remote host to connect to (not needed for "pty")
+        #[serde(default)]
         host: String,
-        /// Remote port
+        /// Remote port (not needed for "pty"; "ssh" defaults to 22)
+        #[serde(default)]
         port: u16,
+        /// How to connect: "telnet" (default), "raw" (TCP without telnet
+        /// negotiation), "pty" (run `command` locally) or "ssh"
+        #[serde(default)]
+        transport: Option<String>,
+        /// Program and arguments for "pty"; the remote command for "ssh"
+        #[serde(default)]
+        command: Vec<String>,
         /// Window position
         x: usize,
         y: usize,
@@ -254,6 +315,9 @@ pub enum Command {
         /// Options: "ansi", "vt100", "xterm", "raw"
         #[serde(default = "default_terminal_type")]
         terminal_type: String,
+        /// Character encoding of the remote's output: "utf-8" (default) or "cp437"
+        #[serde(default)]
+        encoding: Option<String>,
         /// Border style (default: "single")
         /// Options: "none", "single", "double"
         #[serde(default = "default_border")]
@@ -289,6 +353,17 @@ pub enum Command {
         /// Line ending to send on Enter: "cr" or "crlf"
         #[serde(default)]
         line_ending: Option<String>,
+        /// Character encoding of the remote's output: "utf-8" or "cp437"
+        #[serde(default)]
+        encoding: Option<String>,
+        /// Log the raw remote byte stream to this file in the server's
+        /// recording directory ("" stops logging)
+        #[serde(default)]
+        log: Option<String>,
+        /// Record the terminal as an asciicast v2 file in the server's
+        /// recording directory ("" stops recording)
+        #[serde(default)]
+        record: Option<String>,
     },
 
     /// Resize a terminal window (updates window size and sends NAWS to remote)
@@ -318,6 +393,26 @@ pub enum Command {
         #[serde(default = "default_true")]
         draggable: bool,
     },
+
+    /// Scroll a terminal's view into its history (replies with the new position)
+    ScrollTerminal {
+        id: String,
+        /// Lines to scroll back (negative: toward the live screen)
+        #[serde(default)]
+        lines: isize,
+        /// Return to the live screen
+        #[serde(default)]
+        bottom: bool,
+    },
+
+    /// Get the text of a terminal's history, oldest line first
+    GetScrollback {
+        id: String,
+        #[serde(default)]
+        start: usize,
+        #[serde(default = "default_scrollback_count")]
+        count: usize,
+    },
 }
 
 /// A single cell in a batch update
@@ -327,9 +422,9 @@ pub struct BatchCell {
     pub y: usize,
     pub char: char,
     #[serde(default = "default_fg")]
-    pub fg: u8,
-    #[serde(default)]
-    pub bg: u8,
+    pub fg: Color,
+    #[serde(default = "default_bg")]
+    pub bg: Color,
     #[serde(default)]
     pub window: Option<String>,
 }
@@ -360,6 +455,19 @@ impl From<BorderStyle> for crate::core::window::BorderStyle {
     }
 }
 
+impl Command {
+    /// Sessions a server-level command names in its fields (not the `session` target)
+    pub fn sessions_named(&self) -> Vec<&str> {
+        match self {
+            Command::ShareDisplay { source, target, .. }
+            | Command::UnshareDisplay { source, target }
+            | Command::ShareWindow { source, target, .. }
+            | Command::UnshareWindow { source, target, .. } => vec![source, target],
+            _ => Vec::new(),
+        }
+    }
+}
+
 /// Response from APU to game
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -400,8 +508,31 @@ pub enum Response {
         session: String,
     },
 
+    /// Client reconnected to a detached session (using its resume token)
+    /// The client has been sent a full redraw
+    ClientReconnect {
+        session: String,
+    },
+
+    /// Client was handed to another game (sent to the game it left)
+    ClientHandoff {
+        session: String,
+        game: String,
+    },
+
+    /// Client reported a new window size (telnet NAWS)
+    /// The session's display has already been resized; games should relayout
+    ClientResized {
+        session: String,
+        cols: usize,
+        rows: usize,
+        /// Terminal type reported via TTYPE, if known
+        terminal_type: Option<String>,
+    },
+
     /// Window was moved (by dragging title bar)
     WindowMoved {
+        session: String,
         id: String,
         x: usize,
         y: usize,
@@ -409,6 +540,7 @@ pub enum Response {
 
     /// Window was resized (by dragging resize handle)
     WindowResized {
+        session: String,
         id: String,
         width: usize,
         height: usize,
@@ -416,16 +548,19 @@ pub enum Response {
 
     /// Close button was clicked
     WindowCloseRequested {
+        session: String,
         id: String,
     },
 
     /// Title bar was double-clicked (maximize/restore)
     WindowMaximizeRequested {
+        session: String,
         id: String,
     },
 
     /// Window was focused (clicked on)
     WindowFocused {
+        session: String,
         id: String,
     },
 
@@ -444,6 +579,7 @@ pub enum Response {
 
     /// Terminal connected successfully to remote host
     TerminalConnected {
+        session: String,
         id: String,
         host: String,
         port: u16,
@@ -451,15 +587,65 @@ pub enum Response {
 
     /// Terminal disconnected (connection closed or lost)
     TerminalDisconnected {
+        session: String,
         id: String,
         reason: String,
     },
 
     /// Terminal connection failed
     TerminalError {
+        session: String,
         id: String,
         error: String,
     },
+
+    /// The remote program set its window title (OSC 0/2; empty when cleared)
+    TerminalTitleChanged {
+        session: String,
+        id: String,
+        title: String,
+    },
+
+    /// Where a terminal's view is in its history (reply to `scroll_terminal`)
+    TerminalScroll {
+        id: String,
+        /// Lines scrolled back (0 = live screen)
+        offset: usize,
+        /// Lines of history
+        scrollback: usize,
+    },
+
+    /// Lines of a terminal's history (reply to `get_scrollback`)
+    Scrollback {
+        id: String,
+        start: usize,
+        lines: Vec<String>,
+    },
+}
+
+impl Response {
+    /// The session an event is about, if it names one
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            Response::Input { session, .. }
+            | Response::ClientConnect { session }
+            | Response::ClientDisconnect { session }
+            | Response::ClientHandoff { session, .. }
+            | Response::ClientReconnect { session }
+            | Response::ClientResized { session, .. }
+            | Response::RefreshRequested { session }
+            | Response::WindowMoved { session, .. }
+            | Response::WindowResized { session, .. }
+            | Response::WindowCloseRequested { session, .. }
+            | Response::WindowMaximizeRequested { session, .. }
+            | Response::WindowFocused { session, .. }
+            | Response::TerminalConnected { session, .. }
+            | Response::TerminalDisconnected { session, .. }
+            | Response::TerminalError { session, .. }
+            | Response::TerminalTitleChanged { session, .. } => Some(session),
+            _ => None,
+        }
+    }
 }
 
 /// Information about a connected session
@@ -471,6 +657,17 @@ pub struct SessionInfo {
     pub address: String,
     /// Connection time (Unix timestamp)
     pub connected_at: u64,
+    /// Display width
+    pub cols: usize,
+    /// Display height
+    pub rows: usize,
+    /// Terminal type reported via TTYPE, if known
+    pub terminal_type: Option<String>,
+    /// Token a client can use to resume this session after losing its connection
+    /// (None if the server couldn't make one)
+    pub resume_token: Option<String>,
+    /// Connection lost; waiting for the client to resume
+    pub detached: bool,
 }
 
 /// A command with optional session targeting
@@ -478,12 +675,23 @@ pub struct SessionInfo {
 pub struct TargetedCommand {
     /// Target session(s): None = broadcast, Some("*") = broadcast, Some(id) = specific session
     pub session: Option<String>,
+    /// Correlation ID chosen by the game, echoed on the reply
+    /// (`id` is taken: many commands use it for window IDs)
+    pub request_id: Option<Value>,
     /// The actual command
     pub command: Command,
 }
 
-fn default_fg() -> u8 {
-    7 // White
+fn default_scrollback_count() -> usize {
+    100
+}
+
+fn default_fg() -> Color {
+    Color::White
+}
+
+fn default_bg() -> Color {
+    Color::Black
 }
 
 fn default_mouse_mode() -> String {
@@ -521,17 +729,25 @@ pub fn parse_targeted_command(json: &str) -> Result<TargetedCommand, serde_json:
     // First parse as generic JSON to extract session field
     let mut value: Value = serde_json::from_str(json)?;
 
-    // Extract and remove the session field if present
-    let session = if let Some(obj) = value.as_object_mut() {
-        obj.remove("session").and_then(|v| v.as_str().map(String::from))
+    // Extract and remove the session and request_id fields if present
+    let (session, request_id) = if let Some(obj) = value.as_object_mut() {
+        (
+            obj.remove("session").and_then(|v| v.as_str().map(String::from)),
+            obj.remove("request_id"),
+        )
     } else {
-        None
+        (None, None)
     };
 
     // Parse the remaining JSON as a Command
     let command: Command = serde_json::from_value(value)?;
 
-    Ok(TargetedCommand { session, command })
+    Ok(TargetedCommand { session, request_id, command })
+}
+
+/// Recover the request_id from a command that failed to parse, if there is one
+pub fn parse_request_id(json: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(json).ok()?.get_mut("request_id").map(Value::take)
 }
 
 /// Serialize a response to JSON
@@ -539,6 +755,17 @@ pub fn serialize_response(response: &Response) -> String {
     serde_json::to_string(response).unwrap_or_else(|_| r#"{"type":"error","message":"Serialization failed"}"#.to_string())
 }
 
+/// Serialize a reply to a command, echoing the command's request_id
+pub fn serialize_reply(response: &Response, request_id: &Value) -> String {
+    match serde_json::to_value(response) {
+        Ok(Value::Object(mut obj)) => {
+            obj.insert("request_id".to_string(), request_id.clone());
+            Value::Object(obj).to_string()
+        }
+        _ => serialize_response(response),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -548,7 +775,7 @@ mod tests {
         let json = r#"{"cmd":"init","cols":80,"rows":24}"#;
         let cmd = parse_command(json).unwrap();
         match cmd {
-            Command::Init { cols, rows } => {
+            Command::Init { cols, rows, .. } => {
                 assert_eq!(cols, Some(80));
                 assert_eq!(rows, Some(24));
             }
@@ -566,9 +793,45 @@ mod tests {
                 assert_eq!(x, 5);
                 assert_eq!(y, 3);
                 assert_eq!(text, "Hello");
-                assert_eq!(fg, 10);
+                assert_eq!(fg, Color::BrightGreen);
             }
             _ => panic!("Wrong command type"),
         }
     }
+
+    #[test]
+    fn test_parse_extended_colors() {
+        let json = r##"{"cmd":"print","window":"main","x":0,"y":0,"text":"Hi","fg":208,"bg":"#102030"}"##;
+        match parse_command(json).unwrap() {
+            Command::Print { fg, bg, .. } => {
+                assert_eq!(fg, Color::Indexed(208));
+                assert_eq!(bg, Color::Rgb(0x10, 0x20, 0x30));
+            }
+            _ => panic!("Wrong command type"),
+        }
+
+        let json = r#"{"cmd":"set_direct","x":0,"y":0,"char":"X"}"#;
+        match parse_command(json).unwrap() {
+            Command::SetDirect { fg, bg, .. } => {
+                assert_eq!(fg, Color::White);
+                assert_eq!(bg, Color::Black);
+            }
+            _ => panic!("Wrong command type"),
+        }
+    }
+
+    #[test]
+    fn test_request_id_round_trip() {
+        let json = r#"{"cmd":"remove_window","id":"main","session":"a1","request_id":7}"#;
+        let targeted = parse_targeted_command(json).unwrap();
+        assert_eq!(targeted.session.as_deref(), Some("a1"));
+        assert_eq!(targeted.request_id, Some(Value::from(7)));
+        assert!(matches!(targeted.command, Command::RemoveWindow { ref id } if id == "main"));
+
+        let reply = serialize_reply(&Response::Error { message: "Window not found".into() }, &Value::from("x"));
+        assert_eq!(reply, r#"{"message":"Window not found","request_id":"x","type":"error"}"#);
+
+        // Still recoverable when the command itself doesn't parse
+        assert_eq!(parse_request_id(r#"{"cmd":"bogus","request_id":"r9"}"#), Some(Value::from("r9")));
+    }
 }
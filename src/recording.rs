@@ -0,0 +1,374 @@
+//! Session Recording
+//!
+//! What players saw, kept for moderation and replays. A [`Recording`] can
+//! write two files at once:
+//! - a raw log: the bytes exactly as they arrived (a terminal's remote
+//!   stream, telnet commands and all) or as sent (a session's rendered output)
+//! - an [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) file:
+//!   a JSON header, then one `[seconds, "o", text]` line per chunk of output
+//!   and `[seconds, "r", "COLSxROWS"]` per resize, playable with asciinema
+//!
+//! Files are written on threads of their own, so a slow or full disk holds up
+//! neither players nor games: a file that falls too far behind is closed.
+//!
+//! Files go in the server's recording directory (`--record-dir`). Games only
+//! name theirs, which are kept apart in its `games` subdirectory so they can't
+//! touch the session recordings next to it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde_json::json;
+
+use crate::renderer::charset::cp437_char;
+use crate::terminal::TerminalEncoding;
+
+/// Which files to record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordFormats {
+    /// Raw byte log (`.log`)
+    pub log: bool,
+    /// asciicast v2 (`.cast`)
+    pub cast: bool,
+}
+
+impl RecordFormats {
+    /// Parse "log", "cast" or "both" (also "log,cast")
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut formats = RecordFormats::default();
+        for name in s.split(',') {
+            match name.trim().to_lowercase().as_str() {
+                "log" | "raw" => formats.log = true,
+                "cast" | "asciicast" => formats.cast = true,
+                "both" => formats = RecordFormats { log: true, cast: true },
+                _ => return None,
+            }
+        }
+        Some(formats)
+    }
+}
+
+/// Subdirectory of the recording directory that games' recordings go in
+pub const GAME_RECORDINGS: &str = "games";
+
+/// Path for a recording file a game named, in the recording directory's
+/// games subdirectory (None if the name would reach outside it)
+pub fn recording_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.chars().any(char::is_control);
+    valid.then(|| dir.join(GAME_RECORDINGS).join(name))
+}
+
+/// Chunks of output a file may fall behind by before it's given up on
+const QUEUE_CHUNKS: usize = 4096;
+
+/// A file written by a thread of its own
+struct BackgroundFile {
+    tx: SyncSender<Vec<u8>>,
+    thread: JoinHandle<()>,
+}
+
+impl BackgroundFile {
+    fn new(file: File) -> io::Result<Self> {
+        let (tx, rx) = sync_channel::<Vec<u8>>(QUEUE_CHUNKS);
+        let thread = std::thread::Builder::new().name("apu-recording".to_string()).spawn(move || {
+            let mut out = BufWriter::new(file);
+            while let Ok(data) = rx.recv() {
+                // Write whatever else has queued up, then flush once
+                let written = std::iter::once(data).chain(rx.try_iter())
+                    .try_for_each(|data| out.write_all(&data))
+                    .and_then(|_| out.flush());
+                if let Err(e) = written {
+                    warn!("Recording write failed: {}", e);
+                    return;
+                }
+            }
+        })?;
+        Ok(Self { tx, thread })
+    }
+
+    /// Queue bytes to be written
+    /// (fails if the file is behind by too much, or a write has failed)
+    fn write(&self, data: Vec<u8>) -> io::Result<()> {
+        match self.tx.try_send(data) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(io::Error::other("the disk isn't keeping up")),
+            Err(TrySendError::Disconnected(_)) => Err(io::Error::other("the file can't be written")),
+        }
+    }
+
+    /// Close the file, waiting until everything queued is written
+    fn finish(self) {
+        drop(self.tx);
+        let _ = self.thread.join();
+    }
+}
+
+/// Raw log and/or asciicast being written (neither, by default)
+#[derive(Default)]
+pub struct Recording {
+    log: Option<BackgroundFile>,
+    cast: Option<Asciicast>,
+}
+
+impl Recording {
+    /// Whether anything is being recorded
+    pub fn is_active(&self) -> bool {
+        self.log.is_some() || self.cast.is_some()
+    }
+
+    /// Whether an asciicast is being written
+    pub fn is_casting(&self) -> bool {
+        self.cast.is_some()
+    }
+
+    /// Start logging raw bytes to a file (appending, if it exists)
+    pub fn start_log(&mut self, path: &Path) -> io::Result<()> {
+        self.log = Some(BackgroundFile::new(OpenOptions::new().create(true).append(true).open(path)?)?);
+        Ok(())
+    }
+
+    pub fn stop_log(&mut self) {
+        self.log = None;
+    }
+
+    /// Start a new asciicast file for a screen of the given size
+    /// (an existing file is never overwritten)
+    pub fn start_cast(&mut self, path: &Path, cols: usize, rows: usize, title: &str) -> io::Result<()> {
+        self.cast = Some(Asciicast::create(path, cols, rows, title)?);
+        Ok(())
+    }
+
+    pub fn stop_cast(&mut self) {
+        self.cast = None;
+    }
+
+    /// Start or stop recording as a game asked: files named in `dir`, an
+    /// empty name stops that file (None leaves it alone)
+    pub fn configure(&mut self, dir: Option<&Path>, log: Option<&str>, cast: Option<&str>, cols: usize, rows: usize, title: &str) -> Result<(), String> {
+        let path = |name: &str| {
+            let dir = dir.ok_or("Recording is disabled (start the server with --record-dir)")?;
+            let path = recording_path(dir, name).ok_or_else(|| format!("Invalid recording name: {:?}", name))?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| format!("Can't record to {}: {}", name, e))?;
+            }
+            Ok::<_, String>(path)
+        };
+        match log {
+            Some("") => self.stop_log(),
+            Some(name) => self.start_log(&path(name)?).map_err(|e| format!("Can't record to {}: {}", name, e))?,
+            None => {}
+        }
+        match cast {
+            Some("") => self.stop_cast(),
+            Some(name) => self.start_cast(&path(name)?, cols, rows, title).map_err(|e| format!("Can't record to {}: {}", name, e))?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Stop recording, waiting until everything is written
+    /// (this blocks; stopping a file or dropping the recording doesn't)
+    pub fn finish(&mut self) {
+        if let Some(log) = self.log.take() {
+            log.finish();
+        }
+        if let Some(cast) = self.cast.take() {
+            cast.file.finish();
+        }
+    }
+
+    /// Bytes for the raw log
+    /// (a file that can't be written to any more is closed, with a warning)
+    pub fn log(&mut self, data: &[u8]) {
+        if let Some(log) = &mut self.log {
+            if let Err(e) = log.write(data.to_vec()) {
+                warn!("Recording log write failed, stopping: {}", e);
+                self.log = None;
+            }
+        }
+    }
+
+    /// Output for the asciicast, in the given encoding
+    pub fn output(&mut self, data: &[u8], encoding: TerminalEncoding) {
+        if let Some(cast) = &mut self.cast {
+            if let Err(e) = cast.output(data, encoding) {
+                warn!("Recording asciicast write failed, stopping: {}", e);
+                self.cast = None;
+            }
+        }
+    }
+
+    /// The screen was resized
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        if let Some(cast) = &mut self.cast {
+            if let Err(e) = cast.event("r", &format!("{}x{}", cols, rows)) {
+                warn!("Recording asciicast write failed, stopping: {}", e);
+                self.cast = None;
+            }
+        }
+    }
+}
+
+/// An asciicast v2 file being written
+struct Asciicast {
+    file: BackgroundFile,
+    start: Instant,
+    /// Start of a UTF-8 sequence split across chunks
+    pending: Vec<u8>,
+}
+
+impl Asciicast {
+    fn create(path: &Path, cols: usize, rows: usize, title: &str) -> io::Result<Self> {
+        let file = BackgroundFile::new(OpenOptions::new().write(true).create_new(true).open(path)?)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let header = json!({ "version": 2, "width": cols, "height": rows, "timestamp": timestamp, "title": title });
+        file.write(format!("{}\n", header).into_bytes())?;
+        Ok(Self { file, start: Instant::now(), pending: Vec::new() })
+    }
+
+    fn output(&mut self, data: &[u8], encoding: TerminalEncoding) -> io::Result<()> {
+        let text = match encoding {
+            TerminalEncoding::Cp437 => data.iter().map(|&byte| cp437_char(byte)).collect(),
+            TerminalEncoding::Utf8 => {
+                self.pending.extend_from_slice(data);
+                decode_utf8(&mut self.pending)
+            }
+        };
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.event("o", &text)
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        // Microsecond precision is plenty for replays
+        let time = (self.start.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        self.file.write(format!("{}\n", json!([time, kind, data])).into_bytes())
+    }
+}
+
+/// Decode as much UTF-8 as possible, leaving an incomplete sequence at the end
+/// for next time (malformed bytes become U+FFFD)
+fn decode_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut rest = &bytes[..];
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    *bytes = rest.to_vec();
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("apu-recording-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_recording_names() {
+        let dir = Path::new("/var/apu");
+        assert_eq!(recording_path(dir, "bbs-1.cast"), Some(PathBuf::from("/var/apu/games/bbs-1.cast")));
+        assert_eq!(recording_path(dir, "../etc/passwd"), None);
+        assert_eq!(recording_path(dir, "/etc/passwd"), None);
+        assert_eq!(recording_path(dir, ".hidden"), None);
+        assert_eq!(recording_path(dir, ""), None);
+        assert_eq!(RecordFormats::parse("both"), Some(RecordFormats { log: true, cast: true }));
+        assert_eq!(RecordFormats::parse("cast"), Some(RecordFormats { log: false, cast: true }));
+        assert_eq!(RecordFormats::parse("video"), None);
+    }
+
+    #[test]
+    fn test_asciicast() {
+        let dir = temp_dir("cast");
+        let path = dir.join("t.cast");
+        let mut recording = Recording::default();
+        assert!(!recording.is_active());
+        recording.start_cast(&path, 80, 24, "bbs").unwrap();
+        assert!(recording.is_active());
+
+        // A UTF-8 sequence split across chunks comes out whole
+        recording.output(b"\x1b[1mh\xc3", TerminalEncoding::Utf8);
+        recording.output(b"\xa9", TerminalEncoding::Utf8);
+        recording.output(&[0xC9, 0xCD], TerminalEncoding::Cp437);
+        recording.resize(100, 30);
+        recording.finish();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!((lines[0]["width"].as_u64(), lines[0]["height"].as_u64()), (Some(80), Some(24)));
+        assert_eq!(lines[0]["title"], "bbs");
+        let events: Vec<(&str, &str)> = lines[1..].iter().map(|e| (e[1].as_str().unwrap(), e[2].as_str().unwrap())).collect();
+        assert_eq!(events, [("o", "\x1b[1mh"), ("o", "é"), ("o", "╔═"), ("r", "100x30")]);
+        assert!(lines[1][0].as_f64().unwrap() <= lines[4][0].as_f64().unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_configure() {
+        let dir = temp_dir("configure");
+        let mut recording = Recording::default();
+        assert!(recording.configure(None, Some("t.log"), None, 80, 24, "t").is_err());
+        assert!(recording.configure(Some(&dir), Some("../t.log"), None, 80, 24, "t").is_err());
+        // A game's files go in their own subdirectory, and never replace an existing cast
+        std::fs::write(dir.join("t.cast"), "session recording").unwrap();
+        assert_eq!(recording.configure(Some(&dir), Some("t.log"), Some("t.cast"), 80, 24, "t"), Ok(()));
+        assert!(dir.join("games/t.log").exists() && dir.join("games/t.cast").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("t.cast")).unwrap(), "session recording");
+        assert!(recording.configure(Some(&dir), None, Some("t.cast"), 80, 24, "t").is_err());
+        assert_eq!(recording.configure(None, Some(""), None, 80, 24, "t"), Ok(()));
+        assert!(recording.log.is_none() && recording.cast.is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_raw_log_appends() {
+        let dir = temp_dir("log");
+        let path = dir.join("t.log");
+        let mut recording = Recording::default();
+        recording.start_log(&path).unwrap();
+        recording.log(&[0xFF, 0xFB, 0x01, b'a']);
+        recording.finish();
+        recording.start_log(&path).unwrap();
+        recording.log(b"b");
+        recording.finish();
+        recording.log(b"c");
+        assert!(!recording.is_active());
+        assert_eq!(std::fs::read(&path).unwrap(), [0xFF, 0xFB, 0x01, b'a', b'b']);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
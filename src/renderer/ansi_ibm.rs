@@ -2,20 +2,57 @@
 //!
 //! Renders to IBM PC compatible ANSI terminals.
 //! - 80x24 fixed size (default) or dynamic
-//! - 16 colors (standard ANSI)
-//! - CP437/Unicode character set
+//! - 16 colors (standard ANSI), or 256/truecolor when the client supports it
+//! - Unicode, CP437, PETSCII or ATASCII character set
+//! - Keeps the last frame sent so incremental renders only emit changed cells
+//! - OSC 8 hyperlinks for clients that support them
 
-use crate::core::{Attrs, Cell, Color, Grid};
-use super::{MouseMode, Renderer};
+use std::sync::Arc;
+
+use crate::core::{char_width, Attrs, Color, Grid};
+use super::{Charset, ColorDepth, CursorState, CursorStyle, MouseMode, Renderer};
 
 /// ANSI escape sequences
 const CSI: &str = "\x1b[";
 
+/// Shift Out (select G1 character set) / Shift In (select G0)
+const SO: u8 = 0x0e;
+const SI: u8 = 0x0f;
+
+/// How a character should be sent to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyph {
+    /// Send as-is in the normal (G0) character set
+    Text(char),
+    /// Send in the line-drawing (G1) character set, e.g. DEC Special Graphics
+    LineDrawing(char),
+}
+
+/// OSC 8 sequence starting a hyperlink (or ending one, for None)
+fn link_sequence(uri: Option<&str>) -> String {
+    format!("\x1b]8;;{}\x1b\\", uri.unwrap_or(""))
+}
+
+/// Default glyph mapping: send every character unchanged
+fn unicode_glyph(ch: char) -> Glyph {
+    Glyph::Text(ch)
+}
+
 /// IBM ANSI Renderer
 pub struct AnsiIbmRenderer {
     /// Display dimensions
     pub cols: usize,
     pub rows: usize,
+    /// Colors the client can display (others are downsampled)
+    pub color_depth: ColorDepth,
+    /// Maps cell characters to what the client can display
+    pub glyph_map: fn(char) -> Glyph,
+    /// Encoding for cell text
+    pub charset: Charset,
+    /// Send cell links as OSC 8 hyperlinks
+    pub hyperlinks: bool,
+    /// Whether the client is currently shifted to the G1 character set
+    shifted: bool,
     /// Track cursor position for optimization
     cursor_x: usize,
     cursor_y: usize,
@@ -23,6 +60,14 @@ pub struct AnsiIbmRenderer {
     current_fg: Color,
     current_bg: Color,
     current_attrs: Attrs,
+    /// Hyperlink open on the client
+    current_link: Option<Arc<str>>,
+    /// Last frame sent to the client (front buffer)
+    front: Grid,
+    /// Whether the front buffer reflects what the client is showing
+    front_valid: bool,
+    /// Cursor shape last sent to the client
+    cursor_style: CursorStyle,
 }
 
 impl AnsiIbmRenderer {
@@ -31,11 +76,20 @@ impl AnsiIbmRenderer {
         Self {
             cols,
             rows,
+            color_depth: ColorDepth::default(),
+            glyph_map: unicode_glyph,
+            charset: Charset::default(),
+            hyperlinks: false,
+            shifted: false,
             cursor_x: 0,
             cursor_y: 0,
             current_fg: Color::White,
             current_bg: Color::Black,
             current_attrs: Attrs::default(),
+            current_link: None,
+            front: Grid::new(cols, rows),
+            front_valid: false,
+            cursor_style: CursorStyle::default(),
         }
     }
 
@@ -51,6 +105,7 @@ impl AnsiIbmRenderer {
         self.current_fg = Color::White;
         self.current_bg = Color::Black;
         self.current_attrs = Attrs::default();
+        self.current_link = None;
     }
 
     /// Generate cursor move sequence
@@ -60,9 +115,23 @@ impl AnsiIbmRenderer {
         format!("{}{};{}H", CSI, y + 1, x + 1)
     }
 
+    /// Generate cursor forward sequence (cheaper than an absolute move on the same row)
+    fn move_forward(&mut self, n: usize) -> String {
+        self.cursor_x += n;
+        format!("{}{}C", CSI, n)
+    }
+
     /// Generate SGR (color/attribute) sequence
     fn sgr(&mut self, fg: Color, bg: Color, attrs: Attrs) -> String {
-        let mut codes: Vec<u8> = Vec::new();
+        let mut codes: Vec<String> = Vec::new();
+
+        // Without bright colors, show bright foregrounds as bold (like ANSI.SYS)
+        let mut attrs = attrs;
+        if self.color_depth == ColorDepth::Ansi8 && fg.to_ansi16().index().unwrap_or(0) >= 8 {
+            attrs.bold = true;
+        }
+        let fg = self.color_depth.reduce(fg);
+        let bg = self.color_depth.reduce(bg);
 
         // Check if we need to reset (attrs were set before but not now)
         // All "turn on" attributes need reset to turn off - there's no SGR code to turn them off individually
@@ -74,7 +143,7 @@ impl AnsiIbmRenderer {
             || (self.current_attrs.reverse && !attrs.reverse);
 
         if needs_reset {
-            codes.push(0); // Reset
+            codes.push("0".to_string()); // Reset
             // After reset, terminal is at "default" state, not explicit White/Black
             // Use sentinel values to force color output for next cell
             self.current_fg = Color::BrightMagenta; // Unlikely color as sentinel
@@ -84,32 +153,32 @@ impl AnsiIbmRenderer {
 
         // Add attribute codes
         if attrs.bold && !self.current_attrs.bold {
-            codes.push(1);
+            codes.push("1".to_string());
         }
         if attrs.dim && !self.current_attrs.dim {
-            codes.push(2);
+            codes.push("2".to_string());
         }
         if attrs.italic && !self.current_attrs.italic {
-            codes.push(3);
+            codes.push("3".to_string());
         }
         if attrs.underline && !self.current_attrs.underline {
-            codes.push(4);
+            codes.push("4".to_string());
         }
         if attrs.blink && !self.current_attrs.blink {
-            codes.push(5);
+            codes.push("5".to_string());
         }
         if attrs.reverse && !self.current_attrs.reverse {
-            codes.push(7);
+            codes.push("7".to_string());
         }
 
         // Foreground color
         if fg != self.current_fg {
-            codes.push(fg.fg_code());
+            codes.push(fg.fg_params());
         }
 
         // Background color
         if bg != self.current_bg {
-            codes.push(bg.bg_code());
+            codes.push(bg.bg_params());
         }
 
         // Update current state
@@ -120,23 +189,71 @@ impl AnsiIbmRenderer {
         if codes.is_empty() {
             String::new()
         } else {
-            let code_strs: Vec<String> = codes.iter().map(|c| c.to_string()).collect();
-            format!("{}{}m", CSI, code_strs.join(";"))
+            format!("{}{}m", CSI, codes.join(";"))
         }
     }
 
-    /// Render a single cell (SGR + character)
-    fn render_cell(&mut self, cell: &Cell) -> String {
-        let mut output = self.sgr(cell.fg, cell.bg, cell.attrs);
+    /// Render the cell at a position (SGR + character), returning how many
+    /// columns the client's cursor moved (2 for a wide character, 0 for the
+    /// right half of one, which its left half draws)
+    fn render_cell(&mut self, grid: &Grid, x: usize, y: usize, output: &mut Vec<u8>) -> usize {
+        let (Some(cell), Some((ch, span))) = (grid.get(x, y), grid.glyph(x, y)) else {
+            return 0;
+        };
+        // A wide character in the last column would wrap; show it as a blank
+        let (ch, span) = if span == 2 && x + 1 >= self.cols { (' ', 1) } else { (ch, span) };
+        output.extend_from_slice(self.sgr(cell.fg, cell.bg, cell.attrs).as_bytes());
+        if self.hyperlinks && cell.link != self.current_link {
+            self.current_link = cell.link.clone();
+            output.extend_from_slice(link_sequence(cell.link.as_deref()).as_bytes());
+        }
         // Sanitize control characters to prevent terminal corruption
-        let ch = cell.char;
-        if ch < ' ' || ch == '\x7f' {
+        let ch = if ch < ' ' || ch == '\x7f' {
             // Replace control characters with space
-            output.push(' ');
+            ' '
         } else {
-            output.push(ch);
+            ch
+        };
+        let drawn_wide = match (self.glyph_map)(ch) {
+            Glyph::Text(c) => {
+                if self.shifted {
+                    output.push(SI);
+                    self.shifted = false;
+                }
+                self.charset.encode(c, output);
+                self.charset == Charset::Utf8 && char_width(c) == 2
+            }
+            Glyph::LineDrawing(c) => {
+                if !self.shifted {
+                    output.push(SO);
+                    self.shifted = true;
+                }
+                output.push(c as u8);
+                false
+            }
+        };
+        // Clients that can't show the wide character get a narrow stand-in and a blank
+        if span == 2 && !drawn_wide {
+            output.push(b' ');
+        }
+        span
+    }
+
+    /// End the hyperlink open on the client, if any (done after every frame,
+    /// so cells drawn later by other output aren't linked)
+    fn close_link(&mut self, output: &mut Vec<u8>) {
+        if self.current_link.take().is_some() {
+            output.extend_from_slice(link_sequence(None).as_bytes());
+        }
+    }
+
+    /// Copy what was drawn at a position into the front buffer
+    fn remember(&mut self, grid: &Grid, x: usize, y: usize, span: usize) {
+        for dx in 0..span {
+            if let (Some(cell), Some(front)) = (grid.get(x + dx, y), self.front.get_mut(x + dx, y)) {
+                front.set_from(cell);
+            }
         }
-        output
     }
 }
 
@@ -149,134 +266,346 @@ impl Renderer for AnsiIbmRenderer {
         (self.cols, self.rows)
     }
 
-    fn init(&mut self) -> String {
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.cols = cols;
+        self.rows = rows;
+        self.front = Grid::new(cols, rows);
+        self.invalidate();
+    }
+
+    fn init(&mut self) -> Vec<u8> {
         self.reset();
+        self.invalidate();
+        self.cursor_style = CursorStyle::default();
         format!(
             "{}?25l{}2J{}H{}0m",
             CSI, CSI, CSI, CSI
-        )
+        ).into_bytes()
     }
 
-    fn shutdown(&self) -> String {
+    fn shutdown(&self) -> Vec<u8> {
         // Disable mouse mode, reset attributes, show cursor, clear screen, home cursor
-        format!(
-            "{}{}0m{}?25h{}2J{}H",
-            self.disable_mouse(), CSI, CSI, CSI, CSI
-        )
+        let mut output = self.disable_mouse();
+        if self.cursor_style != CursorStyle::default() {
+            output.extend_from_slice(format!("{}0 q", CSI).as_bytes());
+        }
+        output.extend_from_slice(format!("{}0m{}?25h{}2J{}H", CSI, CSI, CSI, CSI).as_bytes());
+        output
+    }
+
+    fn clear(&self) -> Vec<u8> {
+        format!("{}2J{}H", CSI, CSI).into_bytes()
+    }
+
+    fn invalidate(&mut self) {
+        self.front_valid = false;
+    }
+
+    fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.color_depth = depth;
+    }
+
+    fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
     }
 
-    fn clear(&self) -> String {
-        format!("{}2J{}H", CSI, CSI)
+    fn set_hyperlinks(&mut self, enabled: bool) {
+        self.hyperlinks = enabled;
     }
 
-    fn render_full(&mut self, grid: &Grid) -> String {
-        let mut output = String::with_capacity(grid.cols * grid.rows * 10);
+    fn render_full(&mut self, grid: &Grid) -> Vec<u8> {
+        let mut output = Vec::with_capacity(grid.cols * grid.rows * 10);
 
-        // Reset state
+        // Reset state (leaving the line-drawing set if we were in it)
+        if self.shifted {
+            output.push(SI);
+            self.shifted = false;
+        }
         self.reset();
 
         // Just home cursor (no clear - that causes flicker during drag/resize)
-        output.push_str(&format!("{}H{}0m", CSI, CSI));
+        output.extend_from_slice(format!("{}H{}0m", CSI, CSI).as_bytes());
 
         // Render each row
+        let cols = grid.cols.min(self.cols);
         for y in 0..grid.rows.min(self.rows) {
-            output.push_str(&self.move_cursor(0, y));
-            for x in 0..grid.cols.min(self.cols) {
-                if let Some(cell) = grid.get(x, y) {
-                    output.push_str(&self.render_cell(cell));
-                }
+            output.extend_from_slice(self.move_cursor(0, y).as_bytes());
+            let mut x = 0;
+            while x < cols {
+                x += self.render_cell(grid, x, y, &mut output).max(1);
             }
         }
+        self.close_link(&mut output);
+
+        // Remember what the client is now showing
+        self.front = grid.clone();
+        self.front_valid = true;
 
         output
     }
 
-    fn render_dirty(&mut self, grid: &Grid) -> String {
-        // Count dirty cells
-        let dirty_count = grid.iter_dirty().count();
+    fn render_dirty(&mut self, grid: &Grid) -> Vec<u8> {
+        // Without a valid front buffer we can't know what the client shows
+        if !self.front_valid || self.front.cols != grid.cols || self.front.rows != grid.rows {
+            return self.render_full(grid);
+        }
+
+        // Diff against the last frame sent (row-major order)
+        let cols = grid.cols.min(self.cols);
+        let rows = grid.rows.min(self.rows);
+        let changed: Vec<(usize, usize)> = grid.iter_changed(&self.front)
+            .filter(|(x, y, _)| *x < cols && *y < rows)
+            .map(|(x, y, _)| (x, y))
+            .collect();
 
-        // If more than 50% dirty, do full redraw
-        let total = grid.cols * grid.rows;
-        if dirty_count > total / 2 {
+        // If more than 50% changed, do full redraw
+        if changed.len() > (cols * rows) / 2 {
             return self.render_full(grid);
         }
 
-        let mut output = String::with_capacity(dirty_count * 15);
-        let mut last_x: Option<usize> = None;
-        let mut last_y: Option<usize> = None;
-
-        // Collect dirty cells and sort by position
-        let mut dirty: Vec<_> = grid.iter_dirty().collect();
-        dirty.sort_by(|a, b| {
-            if a.1 != b.1 {
-                a.1.cmp(&b.1)
-            } else {
-                a.0.cmp(&b.0)
-            }
-        });
+        let mut output = Vec::with_capacity(changed.len() * 15);
+        // Where the client's cursor is after the last cell written
+        let mut next: Option<(usize, usize)> = None;
 
-        for (x, y, cell) in dirty {
-            // Move cursor if needed
-            let need_move = match (last_x, last_y) {
-                (Some(lx), Some(ly)) => !(y == ly && x == lx + 1),
-                _ => true,
-            };
+        // Changes to the right half of a wide character redraw the whole character
+        let mut origins: Vec<(usize, usize)> = changed.into_iter().map(|(x, y)| grid.glyph_origin(x, y)).collect();
+        origins.dedup();
 
-            if need_move {
-                output.push_str(&self.move_cursor(x, y));
+        for (x, y) in origins {
+            // Move cursor if needed
+            match next {
+                Some((nx, ny)) if ny == y && x == nx => {}
+                Some((nx, ny)) if ny == y && x > nx => output.extend_from_slice(self.move_forward(x - nx).as_bytes()),
+                _ => output.extend_from_slice(self.move_cursor(x, y).as_bytes()),
             }
 
-            output.push_str(&self.render_cell(cell));
-
-            last_x = Some(x);
-            last_y = Some(y);
+            let span = self.render_cell(grid, x, y, &mut output);
+            self.remember(grid, x, y, span);
+            next = Some((x + span, y));
         }
+        self.close_link(&mut output);
 
         output
     }
 
-    fn enable_mouse(&self, mode: MouseMode) -> String {
+    fn enable_mouse(&self, mode: MouseMode) -> Vec<u8> {
         match mode {
             MouseMode::None => self.disable_mouse(),
-            MouseMode::Normal => format!("{}?1000h", CSI),
-            MouseMode::Button => format!("{}?1002h", CSI),
-            MouseMode::Any => format!("{}?1003h", CSI),
+            MouseMode::Normal => format!("{}?1000h", CSI).into_bytes(),
+            MouseMode::Button => format!("{}?1002h", CSI).into_bytes(),
+            MouseMode::Any => format!("{}?1003h", CSI).into_bytes(),
             MouseMode::Sgr => {
                 // Enable SGR extended mode + button event tracking
-                format!("{}?1006h{}?1002h", CSI, CSI)
+                format!("{}?1006h{}?1002h", CSI, CSI).into_bytes()
             }
         }
     }
 
-    fn disable_mouse(&self) -> String {
+    fn disable_mouse(&self) -> Vec<u8> {
         // Disable all mouse modes
         format!(
             "{}?1000l{}?1002l{}?1003l{}?1006l",
             CSI, CSI, CSI, CSI
-        )
+        ).into_bytes()
+    }
+
+    fn set_cursor(&mut self, cursor: Option<CursorState>) -> Vec<u8> {
+        let Some(cursor) = cursor else {
+            return format!("{}?25l", CSI).into_bytes();
+        };
+        let mut output = self.move_cursor(cursor.x, cursor.y).into_bytes();
+        // Only send the shape when it changes (the default shape is the client's own preference)
+        if cursor.style != self.cursor_style {
+            let param = if cursor.style == CursorStyle::default() { 0 } else { cursor.style.param() };
+            output.extend_from_slice(format!("{}{} q", CSI, param).as_bytes());
+            self.cursor_style = cursor.style;
+        }
+        output.extend_from_slice(format!("{}?25h", CSI).as_bytes());
+        output
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::Cell;
+
+    fn text(output: Vec<u8>) -> String {
+        String::from_utf8(output).unwrap()
+    }
 
     #[test]
     fn test_renderer_init() {
         let mut renderer = AnsiIbmRenderer::standard();
-        let init = renderer.init();
+        let init = text(renderer.init());
         assert!(init.contains("\x1b[?25l")); // Hide cursor
         assert!(init.contains("\x1b[2J"));   // Clear screen
     }
 
+    #[test]
+    fn test_render_wide_chars() {
+        let mut grid = Grid::new(4, 1);
+        grid.write_str(0, 0, "漢a", Color::White, Color::Black, Attrs::default());
+
+        let mut renderer = AnsiIbmRenderer::new(4, 1);
+        let output = text(renderer.render_full(&grid));
+        assert!(output.contains("漢a "));
+
+        // Changing the right half redraws the whole character
+        grid.set_cell(1, 0, &Cell { bg: Color::Blue, ..grid.get(1, 0).unwrap().clone() });
+        assert!(text(renderer.render_dirty(&grid)).ends_with("漢"));
+
+        // Clients without the character get a stand-in and a blank
+        let mut renderer = AnsiIbmRenderer::new(4, 1);
+        renderer.set_charset(Charset::Cp437);
+        let output = renderer.render_full(&grid);
+        assert!(output.windows(3).any(|w| w == b"? a"));
+    }
+
+    #[test]
+    fn test_render_hyperlinks() {
+        let mut grid = Grid::new(4, 1);
+        grid.write_str(0, 0, "abcd", Color::White, Color::Black, Attrs::default());
+        let link: Arc<str> = Arc::from("https://example.com");
+        for x in 1..3 {
+            grid.set_cell(x, 0, &Cell { link: Some(link.clone()), ..grid.get(x, 0).unwrap().clone() });
+        }
+
+        // Only clients that asked for hyperlinks get them
+        let mut renderer = AnsiIbmRenderer::new(4, 1);
+        assert!(!text(renderer.render_full(&grid)).contains("\x1b]8"));
+
+        renderer.set_hyperlinks(true);
+        let output = text(renderer.render_full(&grid));
+        assert!(output.ends_with("a\x1b]8;;https://example.com\x1b\\bc\x1b]8;;\x1b\\d"));
+
+        // A link running to the end of the frame is closed after it
+        grid.set_cell(3, 0, &Cell { link: Some(link), ..grid.get(3, 0).unwrap().clone() });
+        assert!(text(renderer.render_dirty(&grid)).ends_with("d\x1b]8;;\x1b\\"));
+    }
+
+    #[test]
+    fn test_set_cursor() {
+        let mut renderer = AnsiIbmRenderer::standard();
+        renderer.init();
+        let cursor = CursorState { x: 4, y: 2, style: CursorStyle::default() };
+        assert_eq!(text(renderer.set_cursor(Some(cursor))), "\x1b[3;5H\x1b[?25h");
+
+        // The shape is only sent when it changes
+        let bar = CursorState { style: CursorStyle::SteadyBar, ..cursor };
+        assert_eq!(text(renderer.set_cursor(Some(bar))), "\x1b[3;5H\x1b[6 q\x1b[?25h");
+        assert_eq!(text(renderer.set_cursor(Some(bar))), "\x1b[3;5H\x1b[?25h");
+        assert_eq!(text(renderer.set_cursor(Some(cursor))), "\x1b[3;5H\x1b[0 q\x1b[?25h");
+
+        assert_eq!(text(renderer.set_cursor(None)), "\x1b[?25l");
+    }
+
     #[test]
     fn test_render_simple() {
         let mut renderer = AnsiIbmRenderer::new(10, 5);
         let mut grid = Grid::new(10, 5);
         grid.set(0, 0, 'X', Color::Red, Color::Black, Attrs::default());
 
-        let output = renderer.render_full(&grid);
+        let output = text(renderer.render_full(&grid));
         assert!(output.contains("X"));
         assert!(output.contains("31")); // Red foreground
     }
+
+    #[test]
+    fn test_render_color_depths() {
+        let mut grid = Grid::new(1, 1);
+        grid.set(0, 0, 'X', Color::Rgb(255, 0, 0), Color::Indexed(17), Attrs::default());
+
+        let mut renderer = AnsiIbmRenderer::new(1, 1);
+        let output = text(renderer.render_full(&grid));
+        assert!(output.contains("\x1b[31;44mX"));
+
+        let mut renderer = AnsiIbmRenderer::new(1, 1);
+        renderer.color_depth = ColorDepth::Indexed256;
+        let output = text(renderer.render_full(&grid));
+        assert!(output.contains("\x1b[38;5;196;48;5;17mX"));
+
+        let mut renderer = AnsiIbmRenderer::new(1, 1);
+        renderer.color_depth = ColorDepth::TrueColor;
+        let output = text(renderer.render_full(&grid));
+        assert!(output.contains("\x1b[38;2;255;0;0;48;5;17mX"));
+    }
+
+    #[test]
+    fn test_render_dirty_unchanged_frame_is_empty() {
+        let mut renderer = AnsiIbmRenderer::standard();
+        let grid = Grid::new(80, 24);
+        renderer.render_full(&grid);
+
+        assert!(renderer.render_dirty(&grid).is_empty());
+    }
+
+    #[test]
+    fn test_render_dirty_single_cell_edit() {
+        let mut renderer = AnsiIbmRenderer::standard();
+        let mut grid = Grid::new(80, 24);
+        renderer.render_full(&grid);
+
+        grid.set(10, 5, 'X', Color::White, Color::Black, Attrs::default());
+        let output = renderer.render_dirty(&grid);
+        assert_eq!(output, b"\x1b[6;11HX");
+        assert_eq!(output.len(), 8);
+    }
+
+    #[test]
+    fn test_render_dirty_same_row_uses_cursor_forward() {
+        let mut renderer = AnsiIbmRenderer::standard();
+        let mut grid = Grid::new(80, 24);
+        renderer.render_full(&grid);
+
+        grid.set(0, 0, 'A', Color::White, Color::Black, Attrs::default());
+        grid.set(5, 0, 'B', Color::White, Color::Black, Attrs::default());
+        let output = renderer.render_dirty(&grid);
+        assert_eq!(output, b"\x1b[1;1HA\x1b[4CB");
+    }
+
+    #[test]
+    fn test_render_dirty_after_composite() {
+        use crate::core::WindowManager;
+
+        let mut wm = WindowManager::new(80, 24);
+        wm.create_window("main", 10, 5, 20, 6);
+        wm.composite();
+
+        let mut renderer = AnsiIbmRenderer::standard();
+        renderer.render_full(&wm.display);
+
+        // Re-compositing an unchanged scene sends nothing
+        wm.composite();
+        assert!(renderer.render_dirty(&wm.display).is_empty());
+
+        // A two-character edit inside the window sends only those cells
+        wm.get_mut("main").unwrap().print(2, 1, "Hi", Color::White, None);
+        wm.composite();
+        let output = renderer.render_dirty(&wm.display);
+        assert_eq!(output, b"\x1b[8;14HHi");
+        assert_eq!(output.len(), 9);
+    }
+
+    #[test]
+    fn test_invalidate_forces_full_redraw() {
+        let mut renderer = AnsiIbmRenderer::new(10, 5);
+        let grid = Grid::new(10, 5);
+        renderer.render_full(&grid);
+        renderer.invalidate();
+
+        let output = renderer.render_dirty(&grid);
+        assert!(output.len() > 50);
+    }
+
+    #[test]
+    fn test_render_cp437_bytes() {
+        let mut grid = Grid::new(3, 1);
+        grid.write_str(0, 0, "╔═◢", Color::White, Color::Black, Attrs::default());
+
+        let mut renderer = AnsiIbmRenderer::new(3, 1);
+        renderer.set_charset(Charset::Cp437);
+        let output = renderer.render_full(&grid);
+        assert!(output.ends_with(&[0xC9, 0xCD, 0xD9]));
+    }
 }
@@ -0,0 +1,165 @@
+//! 7-bit ASCII Renderer
+//!
+//! Renders to terminals that can only display 7-bit ASCII.
+//! - ANSI cursor positioning and 16 colors
+//! - Box drawing, shading blocks and symbols fall back to ASCII lookalikes
+
+use crate::core::Grid;
+use super::{AnsiIbmRenderer, Charset, ColorDepth, CursorState, Glyph, MouseMode, Renderer};
+
+/// Map a character to its closest 7-bit ASCII lookalike
+pub fn ascii_fallback(ch: char) -> char {
+    if ch.is_ascii() {
+        return ch;
+    }
+    match ch {
+        // Box drawing: horizontals, verticals, diagonals; everything else is a corner/tee/cross
+        '─' | '━' | '═' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '╴' | '╶' | '╸' | '╺' => '-',
+        '│' | '┃' | '║' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '╵' | '╷' | '╹' | '╻' => '|',
+        '╱' => '/',
+        '╲' => '\\',
+        '╳' => 'X',
+        '\u{2500}'..='\u{257f}' => '+',
+
+        // Shading and block elements
+        '░' => '.',
+        '▒' => ':',
+        '▓' | '█' | '▀' | '▄' | '▌' | '▐' | '■' => '#',
+
+        // Triangles (◢ is the window resize handle)
+        '◢' | '◤' => '/',
+        '◣' | '◥' => '\\',
+        '▲' => '^',
+        '▼' => 'v',
+        '◀' => '<',
+        '▶' => '>',
+
+        // Arrows and common symbols
+        '←' => '<',
+        '→' => '>',
+        '↑' => '^',
+        '↓' => 'v',
+        '•' | '●' | '◆' | '♦' => '*',
+        '·' | '…' => '.',
+        '°' | '○' | '◦' => 'o',
+        '×' => 'x',
+        '÷' => '/',
+        '±' => '+',
+        '«' => '<',
+        '»' => '>',
+        '\u{a0}' => ' ',
+
+        _ => '?',
+    }
+}
+
+/// Glyph mapping for 7-bit ASCII terminals
+fn ascii_glyph(ch: char) -> Glyph {
+    Glyph::Text(ascii_fallback(ch))
+}
+
+/// 7-bit ASCII Renderer
+pub struct AsciiRenderer {
+    inner: AnsiIbmRenderer,
+}
+
+impl AsciiRenderer {
+    /// Create a new renderer with specified dimensions
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let mut inner = AnsiIbmRenderer::new(cols, rows);
+        inner.glyph_map = ascii_glyph;
+        Self { inner }
+    }
+
+    /// Create a standard 80x24 renderer
+    pub fn standard() -> Self {
+        Self::new(80, 24)
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn name(&self) -> &str {
+        "ascii"
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        self.inner.dimensions()
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.inner.resize(cols, rows);
+    }
+
+    fn init(&mut self) -> Vec<u8> {
+        self.inner.init()
+    }
+
+    fn shutdown(&self) -> Vec<u8> {
+        self.inner.shutdown()
+    }
+
+    fn clear(&self) -> Vec<u8> {
+        self.inner.clear()
+    }
+
+    fn render_full(&mut self, grid: &Grid) -> Vec<u8> {
+        self.inner.render_full(grid)
+    }
+
+    fn set_color_depth(&mut self, depth: ColorDepth) {
+        // Extended colors need escape sequences a 7-bit terminal won't know
+        let depth = match depth {
+            ColorDepth::Ansi8 => ColorDepth::Ansi8,
+            _ => ColorDepth::Ansi16,
+        };
+        self.inner.set_color_depth(depth);
+    }
+
+    fn set_charset(&mut self, charset: Charset) {
+        self.inner.set_charset(charset);
+    }
+
+    fn set_hyperlinks(&mut self, _enabled: bool) {
+        // Plain terminals would print the escape sequences
+    }
+
+    fn invalidate(&mut self) {
+        self.inner.invalidate();
+    }
+
+    fn render_dirty(&mut self, grid: &Grid) -> Vec<u8> {
+        self.inner.render_dirty(grid)
+    }
+
+    fn enable_mouse(&self, mode: MouseMode) -> Vec<u8> {
+        self.inner.enable_mouse(mode)
+    }
+
+    fn disable_mouse(&self) -> Vec<u8> {
+        self.inner.disable_mouse()
+    }
+
+    fn set_cursor(&mut self, cursor: Option<CursorState>) -> Vec<u8> {
+        self.inner.set_cursor(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{box_styles, Color};
+
+    #[test]
+    fn test_ascii_box_drawing() {
+        let mut grid = Grid::new(4, 3);
+        grid.draw_box(0, 0, 4, 3, &box_styles::DOUBLE, Color::White, Color::Black);
+        grid.set_char(3, 2, '◢');
+
+        let mut renderer = AsciiRenderer::new(4, 3);
+        let output = String::from_utf8(renderer.render_full(&grid)).unwrap();
+        assert!(output.contains("+--+"));
+        assert!(output.contains("|  |"));
+        assert!(output.contains("+--/"));
+        assert!(output.is_ascii());
+    }
+}
@@ -0,0 +1,334 @@
+//! Structured Cell Renderer
+//!
+//! For front ends that draw natively (DOM, canvas) rather than parsing ANSI.
+//! Output is newline-delimited JSON. Each render is a `cells` message holding
+//! runs of same-styled cells that changed since the last frame sent:
+//!
+//! ```json
+//! {"type":"cells","full":false,"cols":80,"rows":24,"runs":[{"x":10,"y":5,"text":"Hi","fg":15,"bg":0}]}
+//! ```
+//!
+//! `attrs` is omitted from runs with no attributes set. A double-width
+//! character is a run of its own with `"wide": true`, covering two cells.
+//! Runs of hyperlinked text (from terminal windows) carry the target in `link`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{Attrs, Color, Grid};
+use super::{Charset, ColorDepth, CursorState, MouseMode, Renderer};
+
+/// A run of adjacent cells on one row sharing colors and attributes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellRun {
+    pub x: usize,
+    pub y: usize,
+    /// One character per cell
+    pub text: String,
+    pub fg: Color,
+    pub bg: Color,
+    #[serde(default, skip_serializing_if = "no_attrs")]
+    pub attrs: Attrs,
+    /// The text is one double-width character taking two cells
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub wide: bool,
+    /// URI the text links to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+fn no_attrs(attrs: &Attrs) -> bool {
+    !attrs.any()
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// Messages sent to a cells front end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CellMessage {
+    /// Display initialized: clear to white on black
+    Init { cols: usize, rows: usize },
+    /// Cell updates (`full` means every cell is included)
+    Cells {
+        full: bool,
+        cols: usize,
+        rows: usize,
+        runs: Vec<CellRun>,
+    },
+    /// Clear the display
+    Clear,
+    /// Display shut down
+    Shutdown,
+    /// Mouse events the front end should report (as xterm sequences):
+    /// "none", "normal", "button", "any" or "sgr"
+    Mouse { mode: String },
+    /// Show a cursor at a cell ("blinking_block", "steady_bar"...), or hide it
+    /// (`visible` false, without position or style)
+    Cursor {
+        visible: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        x: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        y: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        style: Option<String>,
+    },
+}
+
+impl CellMessage {
+    /// Serialize as one line of JSON
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = serde_json::to_vec(self).unwrap_or_default();
+        output.push(b'\n');
+        output
+    }
+}
+
+/// Group the cells at these positions (in row-major order) into runs
+fn collect_runs(grid: &Grid, positions: impl Iterator<Item = (usize, usize)>) -> Vec<CellRun> {
+    let mut runs: Vec<CellRun> = Vec::new();
+    let mut next: Option<(usize, usize)> = None;
+
+    // Changes to the right half of a wide character resend the whole character
+    let mut origins: Vec<(usize, usize)> = positions.map(|(x, y)| grid.glyph_origin(x, y)).collect();
+    origins.dedup();
+
+    for (x, y) in origins {
+        let (Some(cell), Some((ch, span))) = (grid.get(x, y), grid.glyph(x, y)) else {
+            continue;
+        };
+        // Sanitize control characters like the ANSI renderer does
+        let ch = if ch < ' ' || ch == '\x7f' { ' ' } else { ch };
+        let wide = span == 2;
+        match runs.last_mut() {
+            Some(run) if next == Some((x, y)) && !wide && !run.wide
+                && run.fg == cell.fg && run.bg == cell.bg && run.attrs == cell.attrs
+                && run.link.as_deref() == cell.link.as_deref() => {
+                run.text.push(ch);
+            }
+            _ => runs.push(CellRun {
+                x,
+                y,
+                text: ch.to_string(),
+                fg: cell.fg,
+                bg: cell.bg,
+                attrs: cell.attrs,
+                wide,
+                link: cell.link.as_deref().map(String::from),
+            }),
+        }
+        next = Some((x + span, y));
+    }
+
+    runs
+}
+
+/// Structured Cell Renderer
+pub struct CellsRenderer {
+    /// Display dimensions
+    pub cols: usize,
+    pub rows: usize,
+    /// Last frame sent to the client (front buffer)
+    front: Grid,
+    /// Whether the front buffer reflects what the client is showing
+    front_valid: bool,
+}
+
+impl CellsRenderer {
+    /// Create a new renderer with specified dimensions
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            front: Grid::new(cols, rows),
+            front_valid: false,
+        }
+    }
+}
+
+impl Renderer for CellsRenderer {
+    fn name(&self) -> &str {
+        "cells"
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.cols = cols;
+        self.rows = rows;
+        self.front = Grid::new(cols, rows);
+        self.invalidate();
+    }
+
+    fn init(&mut self) -> Vec<u8> {
+        self.invalidate();
+        CellMessage::Init { cols: self.cols, rows: self.rows }.to_bytes()
+    }
+
+    fn shutdown(&self) -> Vec<u8> {
+        CellMessage::Shutdown.to_bytes()
+    }
+
+    fn clear(&self) -> Vec<u8> {
+        CellMessage::Clear.to_bytes()
+    }
+
+    fn render_full(&mut self, grid: &Grid) -> Vec<u8> {
+        let (cols, rows) = (self.cols, self.rows);
+        let runs = collect_runs(grid, grid.iter().map(|(x, y, _)| (x, y)).filter(|(x, y)| *x < cols && *y < rows));
+
+        self.front = grid.clone();
+        self.front_valid = true;
+
+        CellMessage::Cells { full: true, cols, rows, runs }.to_bytes()
+    }
+
+    fn set_color_depth(&mut self, _depth: ColorDepth) {
+        // Front ends draw any color; colors are sent as-is
+    }
+
+    fn set_charset(&mut self, _charset: Charset) {
+        // JSON is always UTF-8
+    }
+
+    fn set_hyperlinks(&mut self, _enabled: bool) {
+        // Front ends get links in runs and decide how to show them
+    }
+
+    fn invalidate(&mut self) {
+        self.front_valid = false;
+    }
+
+    fn render_dirty(&mut self, grid: &Grid) -> Vec<u8> {
+        // Without a valid front buffer we can't know what the client shows
+        if !self.front_valid || self.front.cols != grid.cols || self.front.rows != grid.rows {
+            return self.render_full(grid);
+        }
+
+        let (cols, rows) = (self.cols, self.rows);
+        let runs = collect_runs(grid, grid.iter_changed(&self.front).map(|(x, y, _)| (x, y)).filter(|(x, y)| *x < cols && *y < rows));
+        if runs.is_empty() {
+            return Vec::new();
+        }
+
+        self.front = grid.clone();
+
+        CellMessage::Cells { full: false, cols, rows, runs }.to_bytes()
+    }
+
+    fn enable_mouse(&self, mode: MouseMode) -> Vec<u8> {
+        CellMessage::Mouse { mode: mode.name().to_string() }.to_bytes()
+    }
+
+    fn disable_mouse(&self) -> Vec<u8> {
+        self.enable_mouse(MouseMode::None)
+    }
+
+    fn set_cursor(&mut self, cursor: Option<CursorState>) -> Vec<u8> {
+        CellMessage::Cursor {
+            visible: cursor.is_some(),
+            x: cursor.map(|c| c.x),
+            y: cursor.map(|c| c.y),
+            style: cursor.map(|c| c.style.name().to_string()),
+        }.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Cell;
+    use std::sync::Arc;
+
+    fn parse(output: &[u8]) -> CellMessage {
+        assert_eq!(output.last(), Some(&b'\n'));
+        serde_json::from_slice(output).unwrap()
+    }
+
+    #[test]
+    fn test_cells_full_frame_runs() {
+        let mut grid = Grid::new(4, 2);
+        grid.write_str(0, 0, "ab", Color::Red, Color::Black, Attrs::default());
+
+        let mut renderer = CellsRenderer::new(4, 2);
+        match parse(&renderer.render_full(&grid)) {
+            CellMessage::Cells { full, cols, rows, runs } => {
+                assert!(full);
+                assert_eq!((cols, rows), (4, 2));
+                // "ab" in red, rest of row 0, then row 1
+                assert_eq!(runs.len(), 3);
+                assert_eq!(runs[0].text, "ab");
+                assert_eq!(runs[0].fg, Color::Red);
+                assert_eq!((runs[1].x, runs[1].y, runs[1].text.as_str()), (2, 0, "  "));
+                assert_eq!((runs[2].x, runs[2].y, runs[2].text.as_str()), (0, 1, "    "));
+            }
+            other => panic!("Wrong message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cells_dirty_diff() {
+        let mut grid = Grid::new(10, 3);
+        let mut renderer = CellsRenderer::new(10, 3);
+        renderer.render_full(&grid);
+
+        // Unchanged frame sends nothing
+        assert!(renderer.render_dirty(&grid).is_empty());
+
+        grid.write_str(2, 1, "Hi", Color::White, Color::Black, Attrs::default().bold());
+        grid.set(9, 2, '◢', Color::White, Color::Black, Attrs::default());
+        let output = renderer.render_dirty(&grid);
+        let json = String::from_utf8(output.clone()).unwrap();
+        assert!(json.contains(r#""attrs":{"bold":true"#));
+        match parse(&output) {
+            CellMessage::Cells { full, runs, .. } => {
+                assert!(!full);
+                assert_eq!(runs.len(), 2);
+                assert_eq!((runs[0].x, runs[0].y, runs[0].text.as_str()), (2, 1, "Hi"));
+                assert_eq!((runs[1].x, runs[1].y, runs[1].text.as_str()), (9, 2, "◢"));
+                assert!(!runs[1].attrs.any());
+            }
+            other => panic!("Wrong message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cells_wide_chars() {
+        let mut grid = Grid::new(6, 1);
+        let mut renderer = CellsRenderer::new(6, 1);
+        renderer.render_full(&grid);
+
+        grid.write_str(0, 0, "a漢b", Color::White, Color::Black, Attrs::default());
+        match parse(&renderer.render_dirty(&grid)) {
+            CellMessage::Cells { runs, .. } => {
+                assert_eq!(runs.len(), 3);
+                assert_eq!((runs[0].text.as_str(), runs[0].wide), ("a", false));
+                assert_eq!((runs[1].x, runs[1].text.as_str(), runs[1].wide), (1, "漢", true));
+                assert_eq!((runs[2].x, runs[2].text.as_str()), (3, "b"));
+            }
+            other => panic!("Wrong message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cells_links() {
+        let mut grid = Grid::new(4, 1);
+        grid.write_str(0, 0, "abcd", Color::White, Color::Black, Attrs::default());
+        let link: Arc<str> = Arc::from("https://example.com");
+        for x in 1..3 {
+            grid.set_cell(x, 0, &Cell { link: Some(link.clone()), ..grid.get(x, 0).unwrap().clone() });
+        }
+        let mut renderer = CellsRenderer::new(4, 1);
+        match parse(&renderer.render_full(&grid)) {
+            CellMessage::Cells { runs, .. } => {
+                let runs: Vec<_> = runs.iter().map(|r| (r.text.as_str(), r.link.as_deref())).collect();
+                assert_eq!(runs, vec![("a", None), ("bc", Some("https://example.com")), ("d", None)]);
+            }
+            other => panic!("Wrong message: {:?}", other),
+        }
+    }
+}
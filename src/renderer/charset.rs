@@ -0,0 +1,301 @@
+//! Character Set Translation
+//!
+//! Maps cell characters to the byte encoding a client's terminal speaks.
+//! - `utf-8`: modern terminals (default)
+//! - `cp437`: IBM PC / DOS terminals (SyncTERM, NetRunner, ANSI BBS clients)
+//! - `petscii`: Commodore 64/128 (lowercase/uppercase character set)
+//! - `atascii`: Atari 8-bit
+//!
+//! Characters missing from the target set fall back to a similar glyph
+//! (e.g. rounded corners to square ones, `◢` to a quadrant block), then to
+//! an ASCII lookalike, and finally to `?`. Only cell text is translated:
+//! escape sequences are always sent as ASCII, so PETSCII and ATASCII (whose
+//! terminals have control codes of their own) only go with renderers that
+//! send no escape sequences.
+
+use super::ascii::ascii_fallback;
+
+/// Upper half (0x80-0xFF) of code page 437
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Target character encoding for a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// UTF-8 (every character is sent unchanged)
+    #[default]
+    Utf8,
+    /// IBM PC code page 437
+    Cp437,
+    /// Commodore PETSCII (lowercase/uppercase mode)
+    Petscii,
+    /// Atari ATASCII
+    Atascii,
+}
+
+impl Charset {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "cp437" | "ibm437" | "dos" => Charset::Cp437,
+            "petscii" | "c64" | "commodore" => Charset::Petscii,
+            "atascii" | "atari" => Charset::Atascii,
+            _ => Charset::Utf8,
+        }
+    }
+
+    /// Charset name as used in the protocol
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Cp437 => "cp437",
+            Charset::Petscii => "petscii",
+            Charset::Atascii => "atascii",
+        }
+    }
+
+    /// Whether terminals using this set understand ANSI escape sequences
+    pub fn speaks_ansi(&self) -> bool {
+        matches!(self, Charset::Utf8 | Charset::Cp437)
+    }
+
+    /// Encode a character, appending its bytes to `out`
+    pub fn encode(&self, ch: char, out: &mut Vec<u8>) {
+        if *self == Charset::Utf8 {
+            let mut buf = [0u8; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            return;
+        }
+
+        let byte = self.encode_direct(ch)
+            .or_else(|| fallbacks(ch).iter().find_map(|&alt| self.encode_direct(alt)))
+            .or_else(|| self.encode_direct(ascii_fallback(ch)))
+            .unwrap_or(b'?');
+        out.push(byte);
+    }
+
+    /// Encode a string of text, appending its bytes to `out`
+    pub fn encode_str(&self, text: &str, out: &mut Vec<u8>) {
+        for ch in text.chars() {
+            self.encode(ch, out);
+        }
+    }
+
+    /// Byte for a character the target set has natively
+    fn encode_direct(&self, ch: char) -> Option<u8> {
+        match self {
+            Charset::Utf8 => ch.is_ascii().then_some(ch as u8),
+            Charset::Cp437 => cp437(ch),
+            Charset::Petscii => petscii(ch),
+            Charset::Atascii => atascii(ch),
+        }
+    }
+}
+
+/// Similar glyphs to try, in order, when a character is missing from the target set
+fn fallbacks(ch: char) -> &'static [char] {
+    match ch {
+        // Rounded and mixed single/double corners become square ones;
+        // heavy lines prefer double lines (closer in weight) where available
+        '╭' | '╓' | '╒' => &['┌'],
+        '╮' | '╖' | '╕' => &['┐'],
+        '╰' | '╙' | '╘' => &['└'],
+        '╯' | '╜' | '╛' => &['┘'],
+        '┏' => &['╔', '┌'],
+        '┓' => &['╗', '┐'],
+        '┗' => &['╚', '└'],
+        '┛' => &['╝', '┘'],
+        '━' => &['═', '─'],
+        '┃' => &['║', '│'],
+        '┣' => &['╠', '├'],
+        '┫' => &['╣', '┤'],
+        '┳' => &['╦', '┬'],
+        '┻' => &['╩', '┴'],
+        '╋' => &['╬', '┼'],
+        '╔' => &['┌'],
+        '╗' => &['┐'],
+        '╚' => &['└'],
+        '╝' => &['┘'],
+        '═' => &['─'],
+        '║' => &['│'],
+        '╠' | '╞' | '╟' => &['├'],
+        '╣' | '╡' | '╢' => &['┤'],
+        '╦' | '╤' | '╥' => &['┬'],
+        '╩' | '╧' | '╨' => &['┴'],
+        '╬' | '╪' | '╫' => &['┼'],
+
+        // Shading and blocks
+        '█' => &['▓', '▒'],
+        '▓' | '░' => &['▒'],
+        '▀' => &['▔'],
+        '▐' => &['▕'],
+        '■' => &['█', '▓', '▒'],
+
+        // Triangles (◢ is the window resize handle): quadrant block, else a corner
+        '◢' => &['▗', '┘'],
+        '◣' => &['▖', '└'],
+        '◤' => &['▘', '┌'],
+        '◥' => &['▝', '┐'],
+
+        // Symbols
+        '•' | '●' => &['∙', '·'],
+        '·' => &['∙'],
+        '∙' => &['·'],
+
+        // ASCII punctuation some 8-bit sets lack
+        '|' => &['│'],
+        '_' => &['▁'],
+        '^' => &['↑'],
+
+        _ => &[],
+    }
+}
+
+/// Character for a code page 437 byte (the low half is ASCII)
+pub fn cp437_char(byte: u8) -> char {
+    if byte < 0x80 {
+        byte as char
+    } else {
+        CP437_HIGH[(byte - 0x80) as usize]
+    }
+}
+
+/// Code page 437
+fn cp437(ch: char) -> Option<u8> {
+    match ch {
+        ' '..='~' => Some(ch as u8),
+        _ => CP437_HIGH.iter().position(|&c| c == ch).map(|i| 0x80 + i as u8),
+    }
+}
+
+/// Commodore PETSCII, lowercase/uppercase mode (the usual BBS mode)
+fn petscii(ch: char) -> Option<u8> {
+    let byte = match ch {
+        ' '..='@' => ch as u8,
+        'a'..='z' => ch as u8 - b'a' + 0x41,
+        'A'..='Z' => ch as u8 - b'A' + 0xC1,
+        '[' => 0x5B,
+        '£' => 0x5C,
+        ']' => 0x5D,
+        '↑' => 0x5E,
+        '←' => 0x5F,
+        '\u{a0}' => 0xA0,
+        '▌' => 0xA1,
+        '▄' => 0xA2,
+        '▔' => 0xA3,
+        '▁' => 0xA4,
+        '▏' => 0xA5,
+        '▒' => 0xA6,
+        '▕' => 0xA7,
+        '├' => 0xAB,
+        '▗' => 0xAC,
+        '└' => 0xAD,
+        '┐' => 0xAE,
+        '▂' => 0xAF,
+        '┌' => 0xB0,
+        '┴' => 0xB1,
+        '┬' => 0xB2,
+        '┤' => 0xB3,
+        '▎' => 0xB4,
+        '▍' => 0xB5,
+        '▃' => 0xB9,
+        '▖' => 0xBB,
+        '▝' => 0xBC,
+        '┘' => 0xBD,
+        '▘' => 0xBE,
+        '▚' => 0xBF,
+        '─' => 0xC0,
+        '┼' => 0xDB,
+        '│' => 0xDD,
+        _ => return None,
+    };
+    Some(byte)
+}
+
+/// Atari ATASCII
+fn atascii(ch: char) -> Option<u8> {
+    let byte = match ch {
+        ' '..='_' | 'a'..='z' | '|' => ch as u8,
+        '♥' => 0x00,
+        '├' => 0x01,
+        '▕' => 0x02,
+        '┘' => 0x03,
+        '┤' => 0x04,
+        '┐' => 0x05,
+        '╱' => 0x06,
+        '╲' => 0x07,
+        '◢' => 0x08,
+        '▗' => 0x09,
+        '◣' => 0x0A,
+        '▝' => 0x0B,
+        '▘' => 0x0C,
+        '▔' => 0x0D,
+        '▁' => 0x0E,
+        '▖' => 0x0F,
+        '♣' => 0x10,
+        '┌' => 0x11,
+        '─' => 0x12,
+        '┼' => 0x13,
+        '●' => 0x14,
+        '▄' => 0x15,
+        '▎' => 0x16,
+        '┬' => 0x17,
+        '┴' => 0x18,
+        '▌' => 0x19,
+        '└' => 0x1A,
+        '♦' => 0x60,
+        '♠' => 0x7B,
+        '│' => 0x7C,
+        // Inverse-video space
+        '█' => 0xA0,
+        _ => return None,
+    };
+    Some(byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(charset: Charset, text: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        charset.encode_str(text, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_cp437_box_drawing() {
+        assert_eq!(encode(Charset::Cp437, "╔═╗│█"), vec![0xC9, 0xCD, 0xBB, 0xB3, 0xDB]);
+        assert_eq!(encode(Charset::Cp437, "Hi"), b"Hi".to_vec());
+        // Rounded corners fall back to square ones, ◢ to a corner
+        assert_eq!(encode(Charset::Cp437, "╭◢"), vec![0xDA, 0xD9]);
+        // Nothing similar: ASCII lookalike, else '?'
+        assert_eq!(encode(Charset::Cp437, "→✓"), b">?".to_vec());
+    }
+
+    #[test]
+    fn test_petscii_text_and_fallbacks() {
+        assert_eq!(encode(Charset::Petscii, "Hello!"), vec![0xC8, 0x45, 0x4C, 0x4C, 0x4F, 0x21]);
+        // Double lines fall back to single, ◢ to the lower-right quadrant
+        assert_eq!(encode(Charset::Petscii, "╔═◢"), vec![0xB0, 0xC0, 0xAC]);
+    }
+
+    #[test]
+    fn test_atascii_graphics() {
+        assert_eq!(encode(Charset::Atascii, "┌─┐◢"), vec![0x11, 0x12, 0x05, 0x08]);
+        assert_eq!(encode(Charset::Atascii, "│a"), vec![0x7C, b'a']);
+    }
+
+    #[test]
+    fn test_utf8_passthrough() {
+        assert_eq!(encode(Charset::Utf8, "╔◢"), "╔◢".as_bytes().to_vec());
+    }
+}
@@ -1,10 +1,25 @@
 //! APU Renderer Module
 //!
-//! Renderers convert the cell grid to terminal output.
+//! Renderers convert the cell grid to terminal output (raw bytes, so
+//! non-UTF-8 character sets are possible; see `charset`).
+//!
+//! Available renderers (selected per session by name):
+//! - `ansi-ibm`: IBM PC ANSI, Unicode glyphs, 16/256/truecolor
+//! - `vt100`: 8 colors, DEC Special Graphics line drawing
+//! - `ascii`: 7-bit ASCII glyphs only
+//! - `cells`: newline-delimited JSON cell runs, for DOM/canvas front ends
 
 pub mod ansi_ibm;
+pub mod ascii;
+pub mod cells;
+pub mod charset;
+pub mod vt100;
 
-pub use ansi_ibm::AnsiIbmRenderer;
+pub use ansi_ibm::{AnsiIbmRenderer, Glyph};
+pub use ascii::AsciiRenderer;
+pub use cells::{CellMessage, CellRun, CellsRenderer};
+pub use charset::Charset;
+pub use vt100::Vt100Renderer;
 
 /// Mouse tracking mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +37,7 @@ pub enum MouseMode {
 }
 
 impl MouseMode {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "normal" => MouseMode::Normal,
@@ -32,33 +48,181 @@ impl MouseMode {
             _ => MouseMode::Sgr, // Default to SGR
         }
     }
+
+    /// Mode name as used in the protocol
+    pub fn name(&self) -> &'static str {
+        match self {
+            MouseMode::None => "none",
+            MouseMode::Normal => "normal",
+            MouseMode::Button => "button",
+            MouseMode::Any => "any",
+            MouseMode::Sgr => "sgr",
+        }
+    }
+}
+
+/// Cursor shape (DECSCUSR `ESC [ n SP q`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorStyle {
+    #[default]
+    BlinkingBlock,
+    SteadyBlock,
+    BlinkingUnderline,
+    SteadyUnderline,
+    BlinkingBar,
+    SteadyBar,
+}
+
+impl CursorStyle {
+    /// Style for a DECSCUSR parameter (0 and 1 are both a blinking block)
+    pub fn from_param(n: usize) -> Option<Self> {
+        match n {
+            0 | 1 => Some(CursorStyle::BlinkingBlock),
+            2 => Some(CursorStyle::SteadyBlock),
+            3 => Some(CursorStyle::BlinkingUnderline),
+            4 => Some(CursorStyle::SteadyUnderline),
+            5 => Some(CursorStyle::BlinkingBar),
+            6 => Some(CursorStyle::SteadyBar),
+            _ => None,
+        }
+    }
+
+    /// DECSCUSR parameter for this style
+    pub fn param(&self) -> usize {
+        match self {
+            CursorStyle::BlinkingBlock => 1,
+            CursorStyle::SteadyBlock => 2,
+            CursorStyle::BlinkingUnderline => 3,
+            CursorStyle::SteadyUnderline => 4,
+            CursorStyle::BlinkingBar => 5,
+            CursorStyle::SteadyBar => 6,
+        }
+    }
+
+    /// Style name as used in the protocol
+    pub fn name(&self) -> &'static str {
+        match self {
+            CursorStyle::BlinkingBlock => "blinking_block",
+            CursorStyle::SteadyBlock => "steady_block",
+            CursorStyle::BlinkingUnderline => "blinking_underline",
+            CursorStyle::SteadyUnderline => "steady_underline",
+            CursorStyle::BlinkingBar => "blinking_bar",
+            CursorStyle::SteadyBar => "steady_bar",
+        }
+    }
+}
+
+/// Where the client's cursor should be shown (display coordinates)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorState {
+    pub x: usize,
+    pub y: usize,
+    pub style: CursorStyle,
+}
+
+/// Color depth a client can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorDepth {
+    /// 8 ANSI colors, no bright variants (bright foregrounds are shown bold)
+    Ansi8,
+    /// Standard 16 ANSI colors (extended colors are downsampled)
+    #[default]
+    Ansi16,
+    /// xterm 256-color palette (truecolor is downsampled)
+    Indexed256,
+    /// 24-bit RGB
+    TrueColor,
+}
+
+impl ColorDepth {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "8" => ColorDepth::Ansi8,
+            "256" | "indexed" => ColorDepth::Indexed256,
+            "truecolor" | "24bit" | "rgb" => ColorDepth::TrueColor,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// Reduce a color to something this depth can display
+    pub fn reduce(&self, color: crate::core::Color) -> crate::core::Color {
+        match self {
+            ColorDepth::Ansi8 => crate::core::Color::from(color.to_ansi16().index().unwrap_or(7) % 8),
+            ColorDepth::Ansi16 => color.to_ansi16(),
+            ColorDepth::Indexed256 => color.to_indexed(),
+            ColorDepth::TrueColor => color,
+        }
+    }
+}
+
+/// Create a renderer by name ("ansi-ibm"/"ansi"/"xterm", "vt100", "ascii", "cells")
+pub fn create_renderer(name: &str, cols: usize, rows: usize) -> Option<Box<dyn Renderer>> {
+    match name.to_lowercase().as_str() {
+        "ansi-ibm" | "ansi" | "xterm" => Some(Box::new(AnsiIbmRenderer::new(cols, rows))),
+        "vt100" => Some(Box::new(Vt100Renderer::new(cols, rows))),
+        "ascii" => Some(Box::new(AsciiRenderer::new(cols, rows))),
+        "cells" => Some(Box::new(CellsRenderer::new(cols, rows))),
+        _ => None,
+    }
+}
+
+/// Pick the best renderer name for the terminal types a client reported
+pub fn renderer_for_terminal_types(terminal_types: &[String]) -> &'static str {
+    let types: Vec<String> = terminal_types.iter().map(|t| t.to_lowercase()).collect();
+
+    if types.iter().any(|t| t.contains("ansi") || t.contains("xterm") || t.contains("color") || t.starts_with("mtts ")) {
+        "ansi-ibm"
+    } else if types.iter().any(|t| t.starts_with("vt1") || t.starts_with("vt2")) {
+        "vt100"
+    } else if types.iter().any(|t| t == "dumb") {
+        "ascii"
+    } else {
+        "ansi-ibm"
+    }
 }
 
 /// Trait for renderers
-pub trait Renderer {
+pub trait Renderer: Send + Sync {
     /// Renderer name
     fn name(&self) -> &str;
 
     /// Display dimensions
     fn dimensions(&self) -> (usize, usize);
 
+    /// Change display dimensions (the next render redraws everything)
+    fn resize(&mut self, cols: usize, rows: usize);
+
     /// Initialize sequence
-    fn init(&mut self) -> String;
+    fn init(&mut self) -> Vec<u8>;
 
     /// Shutdown sequence
-    fn shutdown(&self) -> String;
+    fn shutdown(&self) -> Vec<u8>;
 
     /// Clear screen
-    fn clear(&self) -> String;
+    fn clear(&self) -> Vec<u8>;
 
     /// Render entire grid
-    fn render_full(&mut self, grid: &crate::core::Grid) -> String;
+    fn render_full(&mut self, grid: &crate::core::Grid) -> Vec<u8>;
 
-    /// Render only dirty cells
-    fn render_dirty(&mut self, grid: &crate::core::Grid) -> String;
+    /// Set the colors the client can display (renderers may clamp this)
+    fn set_color_depth(&mut self, depth: ColorDepth);
+
+    /// Set the character set cell text is encoded in
+    fn set_charset(&mut self, charset: Charset);
+
+    /// Send cell links as hyperlinks the client can open (if the renderer can)
+    fn set_hyperlinks(&mut self, enabled: bool);
+
+    /// Forget the last frame sent, so the next render redraws everything
+    /// (call when the client's screen is unknown, e.g. a resumed session's new connection)
+    fn invalidate(&mut self);
+
+    /// Render only cells that changed since the last frame sent
+    fn render_dirty(&mut self, grid: &crate::core::Grid) -> Vec<u8>;
 
     /// Auto-choose render method
-    fn render(&mut self, grid: &crate::core::Grid, force_full: bool) -> String {
+    fn render(&mut self, grid: &crate::core::Grid, force_full: bool) -> Vec<u8> {
         if force_full {
             self.render_full(grid)
         } else {
@@ -67,8 +231,11 @@ pub trait Renderer {
     }
 
     /// Enable mouse tracking
-    fn enable_mouse(&self, mode: MouseMode) -> String;
+    fn enable_mouse(&self, mode: MouseMode) -> Vec<u8>;
 
     /// Disable mouse tracking
-    fn disable_mouse(&self) -> String;
+    fn disable_mouse(&self) -> Vec<u8>;
+
+    /// Show the client's cursor at a display cell, or hide it (None)
+    fn set_cursor(&mut self, cursor: Option<CursorState>) -> Vec<u8>;
 }
@@ -0,0 +1,174 @@
+//! DEC VT100 Renderer
+//!
+//! Renders to VT100-compatible terminals.
+//! - 8 colors (no bright variants; bright foregrounds are shown bold)
+//! - Line drawing via the DEC Special Graphics set in G1, selected with SO/SI
+//! - Other non-ASCII characters fall back to ASCII lookalikes
+
+use crate::core::Grid;
+use super::ascii::ascii_fallback;
+use super::{AnsiIbmRenderer, Charset, ColorDepth, CursorState, CursorStyle, Glyph, MouseMode, Renderer};
+
+/// Designate DEC Special Graphics as G1, then make sure G0 is active
+const DESIGNATE_G1_GRAPHICS: &str = "\x1b)0\x0f";
+
+/// Map a character to its DEC Special Graphics equivalent, if there is one
+pub fn dec_graphics(ch: char) -> Option<char> {
+    let dec = match ch {
+        '┌' | '┏' | '╔' | '╭' => 'l',
+        '┐' | '┓' | '╗' | '╮' => 'k',
+        '└' | '┗' | '╚' | '╰' => 'm',
+        '┘' | '┛' | '╝' | '╯' => 'j',
+        '─' | '━' | '═' => 'q',
+        '│' | '┃' | '║' => 'x',
+        '├' | '┣' | '╠' => 't',
+        '┤' | '┫' | '╣' => 'u',
+        '┬' | '┳' | '╦' => 'w',
+        '┴' | '┻' | '╩' => 'v',
+        '┼' | '╋' | '╬' => 'n',
+        '░' | '▒' | '▓' => 'a',
+        '◆' | '♦' => '`',
+        '°' => 'f',
+        '±' => 'g',
+        '≤' => 'y',
+        '≥' => 'z',
+        'π' => '{',
+        '≠' => '|',
+        '£' => '}',
+        '·' => '~',
+        _ => return None,
+    };
+    Some(dec)
+}
+
+/// Glyph mapping for VT100 terminals
+fn vt100_glyph(ch: char) -> Glyph {
+    match dec_graphics(ch) {
+        Some(dec) => Glyph::LineDrawing(dec),
+        None => Glyph::Text(ascii_fallback(ch)),
+    }
+}
+
+/// DEC VT100 Renderer
+pub struct Vt100Renderer {
+    inner: AnsiIbmRenderer,
+}
+
+impl Vt100Renderer {
+    /// Create a new renderer with specified dimensions
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let mut inner = AnsiIbmRenderer::new(cols, rows);
+        inner.color_depth = ColorDepth::Ansi8;
+        inner.glyph_map = vt100_glyph;
+        Self { inner }
+    }
+
+    /// Create a standard 80x24 renderer
+    pub fn standard() -> Self {
+        Self::new(80, 24)
+    }
+}
+
+impl Renderer for Vt100Renderer {
+    fn name(&self) -> &str {
+        "vt100"
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        self.inner.dimensions()
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        self.inner.resize(cols, rows);
+    }
+
+    fn init(&mut self) -> Vec<u8> {
+        let mut output = DESIGNATE_G1_GRAPHICS.as_bytes().to_vec();
+        output.extend(self.inner.init());
+        output
+    }
+
+    fn shutdown(&self) -> Vec<u8> {
+        let mut output = vec![0x0f];
+        output.extend(self.inner.shutdown());
+        output
+    }
+
+    fn clear(&self) -> Vec<u8> {
+        self.inner.clear()
+    }
+
+    fn render_full(&mut self, grid: &Grid) -> Vec<u8> {
+        self.inner.render_full(grid)
+    }
+
+    fn set_color_depth(&mut self, _depth: ColorDepth) {
+        // VT100 never gets bright or extended colors
+        self.inner.set_color_depth(ColorDepth::Ansi8);
+    }
+
+    fn set_charset(&mut self, charset: Charset) {
+        self.inner.set_charset(charset);
+    }
+
+    fn set_hyperlinks(&mut self, _enabled: bool) {
+        // A VT100 would print the escape sequences
+    }
+
+    fn invalidate(&mut self) {
+        self.inner.invalidate();
+    }
+
+    fn render_dirty(&mut self, grid: &Grid) -> Vec<u8> {
+        self.inner.render_dirty(grid)
+    }
+
+    fn enable_mouse(&self, mode: MouseMode) -> Vec<u8> {
+        self.inner.enable_mouse(mode)
+    }
+
+    fn disable_mouse(&self) -> Vec<u8> {
+        self.inner.disable_mouse()
+    }
+
+    fn set_cursor(&mut self, cursor: Option<CursorState>) -> Vec<u8> {
+        // A VT100 has one cursor shape; position and visibility still apply
+        self.inner.set_cursor(cursor.map(|c| CursorState { style: CursorStyle::default(), ..c }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{box_styles, Attrs, Color};
+
+    #[test]
+    fn test_vt100_init_designates_graphics() {
+        let mut renderer = Vt100Renderer::standard();
+        assert!(renderer.init().starts_with(b"\x1b)0\x0f"));
+    }
+
+    #[test]
+    fn test_vt100_line_drawing_uses_shift_out() {
+        let mut grid = Grid::new(3, 3);
+        grid.draw_box(0, 0, 3, 3, &box_styles::SINGLE, Color::White, Color::Black);
+        grid.set_char(1, 1, 'A');
+
+        let mut renderer = Vt100Renderer::new(3, 3);
+        let output = String::from_utf8(renderer.render_full(&grid)).unwrap();
+        // Top row: SO, then lqk in the graphics set
+        assert!(output.contains("\x0elqk"));
+        // Text inside the box shifts back in
+        assert!(output.contains("x\x0fA\x0ex"));
+    }
+
+    #[test]
+    fn test_vt100_bright_colors_become_bold() {
+        let mut grid = Grid::new(1, 1);
+        grid.set(0, 0, 'X', Color::BrightRed, Color::BrightBlue, Attrs::default());
+
+        let mut renderer = Vt100Renderer::new(1, 1);
+        let output = String::from_utf8(renderer.render_full(&grid)).unwrap();
+        assert!(output.contains("\x1b[1;31;44mX"));
+    }
+}
@@ -5,104 +5,132 @@
 //! Client input is parsed and forwarded to games.
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
-use log::{info, error, debug};
-
-use crate::core::{Attrs, Color, WindowManager, InteractionState, DragState, ResizeState, TitleBarClick};
-use crate::input::{InputParser, InputEvent, MouseButton, MouseEvent as MouseEventType};
-use crate::protocol::{Command, Response, parse_targeted_command, serialize_response, SessionInfo};
-use crate::renderer::{AnsiIbmRenderer, MouseMode, Renderer};
-use crate::terminal::{Terminal, TerminalType};
-
-// Telnet protocol constants
-const IAC: u8 = 255;   // Interpret As Command
-const WILL: u8 = 251;
-const WONT: u8 = 252;
-const DO: u8 = 253;
-const DONT: u8 = 254;
-const SB: u8 = 250;    // Subnegotiation Begin
-const SE: u8 = 240;    // Subnegotiation End
-
-// Telnet options
-const ECHO: u8 = 1;
-const SUPPRESS_GO_AHEAD: u8 = 3;
-const LINEMODE: u8 = 34;
-
-/// Telnet negotiation to enable raw mode (character-at-a-time, no local echo)
-fn telnet_raw_mode() -> Vec<u8> {
-    vec![
-        IAC, WILL, ECHO,              // Server will echo (client should not)
-        IAC, WILL, SUPPRESS_GO_AHEAD, // No line buffering
-        IAC, DO, SUPPRESS_GO_AHEAD,   // Client should not buffer
-        IAC, DONT, LINEMODE,          // Disable line mode
-    ]
+use log::{info, error, debug, warn};
+use serde_json::Value;
+
+use crate::auth::{authenticate, constant_time_eq, AuthToken, SessionScope};
+use crate::core::{Attrs, Color, Grid, Window, WindowManager, InteractionState, DragState, ResizeState, TitleBarClick};
+use crate::input::{InputParser, InputEvent, Key, Modifiers, MouseButton, MouseEvent as MouseEventType};
+use crate::recording::{RecordFormats, Recording};
+use crate::protocol::{Command, Response, parse_request_id, parse_targeted_command, serialize_reply, serialize_response, SessionInfo};
+use crate::renderer::{create_renderer, renderer_for_terminal_types, AnsiIbmRenderer, Charset, ColorDepth, CursorState, MouseMode, Renderer};
+use crate::terminal::{MouseTracking, Terminal, TerminalEncoding, TerminalModes, TerminalType};
+use crate::binary;
+use crate::telnet::{self, escape_iac, ClientTelnet, ClientTelnetEvent, MTTS_256_COLORS, MTTS_TRUECOLOR};
+use crate::transport::{self, Endpoint, Transport};
+use crate::websocket;
+
+/// How a player client is connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientProtocol {
+    /// Raw TCP speaking telnet
+    Telnet,
+    /// WebSocket (already unframed; no telnet negotiation)
+    WebSocket,
 }
 
-/// Filter telnet IAC sequences from input data
-fn filter_telnet_commands(data: &[u8]) -> Vec<u8> {
-    let mut filtered = Vec::new();
-    let mut i = 0;
-    while i < data.len() {
-        if data[i] == IAC {
-            // Skip IAC sequences
-            if i + 1 < data.len() {
-                match data[i + 1] {
-                    WILL | WONT | DO | DONT => {
-                        // 3-byte sequence: IAC + command + option
-                        i += 3;
-                        continue;
-                    }
-                    SB => {
-                        // Subnegotiation - skip until IAC SE
-                        i += 2;
-                        while i < data.len() {
-                            if data[i] == IAC && i + 1 < data.len() && data[i + 1] == SE {
-                                i += 2;
-                                break;
-                            }
-                            i += 1;
-                        }
-                        continue;
-                    }
-                    IAC => {
-                        // Escaped IAC (255 255) = literal 255
-                        filtered.push(255);
-                        i += 2;
-                        continue;
-                    }
-                    _ => {
-                        // Other 2-byte command
-                        i += 2;
-                        continue;
-                    }
-                }
+/// Client terminal capabilities learned from telnet negotiation
+#[derive(Debug, Clone, Default)]
+pub struct ClientCapabilities {
+    /// Terminal types reported via TTYPE, in the order received
+    pub terminal_types: Vec<String>,
+    /// MTTS capability bits, if the client supports MTTS
+    pub mtts: Option<u32>,
+}
+
+impl ClientCapabilities {
+    /// Primary terminal type (the first one the client reported)
+    pub fn terminal_type(&self) -> Option<&str> {
+        self.terminal_types.first().map(String::as_str)
+    }
+
+    /// Record a reported terminal type, decoding MTTS bits when present
+    pub fn add_terminal_type(&mut self, name: String) {
+        if let Some(bits) = name.strip_prefix("MTTS ") {
+            self.mtts = bits.trim().parse().ok();
+        }
+        self.terminal_types.push(name);
+    }
+
+    /// Best color depth the client claims to support
+    pub fn color_depth(&self) -> ColorDepth {
+        if let Some(bits) = self.mtts {
+            if bits & MTTS_TRUECOLOR != 0 {
+                return ColorDepth::TrueColor;
+            }
+            if bits & MTTS_256_COLORS != 0 {
+                return ColorDepth::Indexed256;
+            }
+        }
+        let types = self.terminal_types.iter().map(|t| t.to_lowercase());
+        let mut depth = ColorDepth::Ansi16;
+        for t in types {
+            if t.contains("truecolor") || t.contains("direct") {
+                return ColorDepth::TrueColor;
+            }
+            if t.contains("256") {
+                depth = ColorDepth::Indexed256;
             }
         }
-        filtered.push(data[i]);
-        i += 1;
+        depth
+    }
+
+    /// Whether the client can open OSC 8 hyperlinks (modern terminals
+    /// report themselves as some flavour of xterm)
+    pub fn hyperlinks(&self) -> bool {
+        self.terminal_types.iter().map(|t| t.to_lowercase())
+            .any(|t| t.contains("xterm") || t.contains("kitty") || t.contains("wezterm") || t.contains("foot"))
     }
-    filtered
 }
 
-/// Telnet protocol state machine for parsing incoming data
-#[derive(Clone, Copy, PartialEq)]
-enum TelnetState {
-    Normal,
-    Iac,
-    Option,
-    Subneg,
-    SubnegIac,
+/// An event for game connections
+#[derive(Debug, Clone)]
+pub struct GameEvent {
+    /// Game connection to deliver to (None: every game)
+    pub game: Option<String>,
+    pub response: Response,
+}
+
+impl GameEvent {
+    pub fn new(game: Option<String>, response: Response) -> Self {
+        Self { game, response }
+    }
+}
+
+/// Connected games, for attaching sessions to them
+#[derive(Debug, Default)]
+pub struct GameRegistry {
+    /// Game connection ID -> registered name
+    pub names: HashMap<String, String>,
+    /// Game new sessions are attached to
+    pub lobby: Option<String>,
+}
+
+impl GameRegistry {
+    /// Connection ID of the game registered under a name
+    pub fn find(&self, name: &str) -> Option<&str> {
+        self.names.iter().find(|(_, n)| *n == name).map(|(id, _)| id.as_str())
+    }
 }
 
+/// The game a session is attached to, shared with tasks that report on its behalf
+pub type SharedGame = Arc<std::sync::RwLock<Option<String>>>;
+
 /// Handle to an active terminal connection
 pub struct TerminalHandle {
     /// Terminal emulator state (shared with connection task)
     pub terminal: Arc<RwLock<Terminal>>,
     /// Channel to send data to the remote server
     pub input_tx: mpsc::Sender<Vec<u8>>,
+    /// Channel to report a new window size to the remote server (NAWS)
+    pub resize_tx: mpsc::Sender<(u16, u16)>,
     /// Handle to abort the connection task
     pub abort_handle: tokio::task::AbortHandle,
     /// Remote host
@@ -113,8 +141,96 @@ pub struct TerminalHandle {
     pub local_echo: bool,
     /// Line ending mode: "cr" (default) or "crlf"
     pub line_ending: String,
+    /// Raw log / asciicast of the remote's output (shared with connection task)
+    pub recording: Arc<std::sync::Mutex<Recording>>,
+}
+
+/// A window from another session shown live in this one (see `ShareWindow`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowMirror {
+    /// Session that owns the window
+    pub source: String,
+    /// Window ID in the source session
+    pub window_id: String,
+    /// Window ID of the mirror in this session (`<window_id>@<source>`)
+    pub mirror_id: String,
+    /// Position requested by the game (defaults to the source window's position)
+    pub x: Option<usize>,
+    pub y: Option<usize>,
+    /// Send input on the mirror to the game as the source session's input
+    pub route_input: bool,
+    /// Source window position when last copied (for translating mouse input)
+    pub source_x: usize,
+    pub source_y: usize,
+}
+
+impl WindowMirror {
+    pub fn new(source: String, window_id: String, x: Option<usize>, y: Option<usize>, route_input: bool) -> Self {
+        Self {
+            mirror_id: format!("{}@{}", window_id, source),
+            source,
+            window_id,
+            x,
+            y,
+            route_input,
+            source_x: 0,
+            source_y: 0,
+        }
+    }
+}
+
+/// What a debug console command asks the connection to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleAction {
+    None,
+    /// Ask the game to redraw everything
+    Reset,
+    /// Disconnect
+    Close,
+    /// Switch this connection to the detached session with this resume token
+    Resume(String),
+}
+
+/// Random token for resuming a session (128 bits from the OS, hex)
+/// Whoever has it can take the session over, so it must be unguessable:
+/// without the OS random source there's no token, and no resuming
+fn new_resume_token() -> Option<String> {
+    let mut bytes = [0u8; 16];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        error!("Can't make a resume token: {}", e);
+        return None;
+    }
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// What a client asked for when connecting
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// Renderer the client can read (e.g. "cells"), overriding the game's choice
+    pub renderer: Option<String>,
+    /// Token of a detached session to resume
+    pub resume_token: Option<String>,
+}
+
+impl ClientOptions {
+    /// Options from a WebSocket request path: `/cells` for the cells renderer,
+    /// `?resume=<token>` to resume a session
+    pub fn from_path(path: &str) -> Self {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        Self {
+            renderer: (path.trim_end_matches('/') == "/cells").then(|| "cells".to_string()),
+            resume_token: query.split('&')
+                .find_map(|param| param.strip_prefix("resume="))
+                .filter(|token| !token.is_empty())
+                .map(String::from),
+        }
+    }
 }
 
+/// Largest display a client can ask for
+const MAX_CLIENT_COLS: usize = 500;
+const MAX_CLIENT_ROWS: usize = 300;
+
 /// A client session (player connection)
 pub struct ClientSession {
     /// Session ID
@@ -124,11 +240,11 @@ pub struct ClientSession {
     /// Connection timestamp (Unix epoch)
     pub connected_at: u64,
     /// Output sender
-    output_tx: mpsc::Sender<String>,
+    output_tx: mpsc::Sender<Vec<u8>>,
     /// Window manager for this session
     pub windows: WindowManager,
-    /// Renderer
-    renderer: AnsiIbmRenderer,
+    /// Renderer, chosen from the client's terminal type unless the game picked one
+    renderer: Box<dyn Renderer>,
     /// Interaction state for window chrome handling
     pub interaction: InteractionState,
     /// Sessions that are sharing their display with this session
@@ -137,6 +253,22 @@ pub struct ClientSession {
     /// Sessions we are sharing our display to
     /// (they see our screen)
     pub display_shares_to: Vec<String>,
+    /// Watching a shared display read-only (our input is dropped)
+    pub spectator: bool,
+    /// Scale shared displays to our size instead of cropping them
+    pub scale_shared_display: bool,
+    /// Windows from other sessions mirrored into this one
+    pub window_mirrors: Vec<WindowMirror>,
+    /// Game connection this session is attached to (None: every game)
+    /// Shared with the session's terminal tasks, so their events follow a handoff
+    game: SharedGame,
+    /// Secret a client presents to resume this session after a dropped connection
+    /// (None: the session can't be resumed)
+    pub resume_token: Option<String>,
+    /// When the connection was lost, if the session is waiting to be resumed
+    pub detached_at: Option<Instant>,
+    /// Mouse tracking the game enabled (restored when the session is resumed)
+    pub mouse_mode: MouseMode,
     /// Debug console state
     pub console_open: bool,
     /// Debug console input buffer
@@ -145,10 +277,27 @@ pub struct ClientSession {
     pub terminals: HashMap<String, TerminalHandle>,
     /// Currently focused window (for terminal input routing)
     pub focused_window: Option<String>,
+    /// Terminal capabilities reported by the client
+    pub capabilities: ClientCapabilities,
+    /// Color depth requested by the game via `init` (overrides detection)
+    pub color_depth: Option<ColorDepth>,
+    /// Renderer requested by the game via `init` (overrides detection)
+    pub renderer_name: Option<String>,
+    /// Renderer the client asked for when connecting (e.g. WebSocket path `/cells`)
+    /// Overrides the game's choice, since the client can only read that format
+    pub client_renderer: Option<String>,
+    /// Character set the client's terminal speaks (set via `init`)
+    pub charset: Charset,
+    /// Cursor the client is currently showing (a focused terminal's)
+    shown_cursor: Option<CursorState>,
+    /// Shared display last shown, fitted to our size (the debug console is drawn over it)
+    shared_frame: Option<Grid>,
+    /// Raw log / asciicast of everything sent to the client (see `--record-sessions`)
+    recording: std::sync::Mutex<Recording>,
 }
 
 impl ClientSession {
-    pub fn new(id: String, address: String, output_tx: mpsc::Sender<String>, cols: usize, rows: usize) -> Self {
+    pub fn new(id: String, address: String, output_tx: mpsc::Sender<Vec<u8>>, cols: usize, rows: usize) -> Self {
         use std::time::{SystemTime, UNIX_EPOCH};
         let connected_at = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -161,15 +310,77 @@ impl ClientSession {
             connected_at,
             output_tx,
             windows: WindowManager::new(cols, rows),
-            renderer: AnsiIbmRenderer::new(cols, rows),
+            renderer: Box::new(AnsiIbmRenderer::new(cols, rows)),
             interaction: InteractionState::default(),
             display_shares_from: Vec::new(),
             display_shares_to: Vec::new(),
+            spectator: false,
+            scale_shared_display: false,
+            window_mirrors: Vec::new(),
+            game: SharedGame::default(),
+            resume_token: new_resume_token(),
+            detached_at: None,
+            mouse_mode: MouseMode::None,
             console_open: false,
             console_input: String::new(),
             terminals: HashMap::new(),
             focused_window: None,
+            capabilities: ClientCapabilities::default(),
+            color_depth: None,
+            renderer_name: None,
+            client_renderer: None,
+            charset: Charset::default(),
+            shown_cursor: None,
+            shared_frame: None,
+            recording: std::sync::Mutex::new(Recording::default()),
+        }
+    }
+
+    /// Record everything sent to the client, in files named after the session
+    /// and when it started (no asciicast unless the output is terminal text)
+    pub fn start_recording(&mut self, dir: &Path, formats: RecordFormats) -> std::io::Result<()> {
+        let name = format!("{}-{}", self.id, self.connected_at);
+        let text = self.output_is_text();
+        let recording = self.recording.get_mut().unwrap_or_else(|e| e.into_inner());
+        if formats.log {
+            recording.start_log(&dir.join(format!("{}.log", name)))?;
+        }
+        if formats.cast && !text {
+            warn!("Not recording an asciicast of session {}: {} output isn't terminal text", self.id, self.renderer.name());
+        } else if formats.cast {
+            recording.start_cast(&dir.join(format!("{}.cast", name)), self.windows.cols, self.windows.rows, &self.id)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the client is sent terminal text an asciicast can hold
+    /// (not cells JSON, nor a character set with its own control codes)
+    fn output_is_text(&self) -> bool {
+        self.renderer.name() != "cells" && self.charset.speaks_ansi()
+    }
+
+    /// Take the session's recordings (its own and its terminals'), leaving it
+    /// unrecorded
+    fn take_recordings(&mut self) -> Vec<Recording> {
+        let own = std::mem::take(self.recording.get_mut().unwrap_or_else(|e| e.into_inner()));
+        let terminals = self.terminals.values().map(|handle| std::mem::take(&mut *handle.recording.lock().unwrap_or_else(|e| e.into_inner())));
+        std::iter::once(own).chain(terminals).filter(Recording::is_active).collect()
+    }
+
+    /// Send rendered output to the client (and the recording, if any)
+    async fn send_output(&self, output: Vec<u8>) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
+        if let Ok(mut recording) = self.recording.lock() {
+            if recording.is_active() {
+                recording.log(&output);
+                if recording.is_casting() && !self.output_is_text() {
+                    warn!("Stopping session {}'s asciicast: {} output isn't terminal text", self.id, self.renderer.name());
+                    recording.stop_cast();
+                }
+                let encoding = if self.charset == Charset::Cp437 { TerminalEncoding::Cp437 } else { TerminalEncoding::Utf8 };
+                recording.output(&output, encoding);
+            }
         }
+        self.output_tx.send(output).await
     }
 
     /// Get session info for protocol responses
@@ -178,33 +389,98 @@ impl ClientSession {
             id: self.id.clone(),
             address: self.address.clone(),
             connected_at: self.connected_at,
+            cols: self.windows.cols,
+            rows: self.windows.rows,
+            terminal_type: self.capabilities.terminal_type().map(String::from),
+            resume_token: self.resume_token.clone(),
+            detached: self.detached_at.is_some(),
+        }
+    }
+
+    /// Name of the renderer to use: the client's, the game's choice
+    /// (`requested`), or the best match for the client
+    fn renderer_name_for<'a>(&'a self, requested: Option<&'a str>) -> &'a str {
+        self.client_renderer.as_deref()
+            .or(requested)
+            .unwrap_or_else(|| renderer_for_terminal_types(&self.capabilities.terminal_types))
+    }
+
+    /// Build the renderer for this session: the game's choice, or the best match for the client
+    fn select_renderer(&self, cols: usize, rows: usize) -> Box<dyn Renderer> {
+        let name = self.renderer_name_for(self.renderer_name.as_deref());
+        let mut renderer = create_renderer(name, cols, rows)
+            .unwrap_or_else(|| Box::new(AnsiIbmRenderer::new(cols, rows)));
+        renderer.set_color_depth(self.color_depth.unwrap_or_else(|| self.capabilities.color_depth()));
+        renderer.set_charset(self.charset);
+        renderer.set_hyperlinks(self.capabilities.hyperlinks());
+        renderer
+    }
+
+    /// Record a terminal type reported by the client, switching renderer or color depth to match
+    pub async fn add_terminal_type(&mut self, name: String) {
+        self.capabilities.add_terminal_type(name);
+        let renderer = self.select_renderer(self.windows.cols, self.windows.rows);
+        if renderer.name() != self.renderer.name() {
+            // Re-initialize the client's terminal with the new renderer and redraw
+            self.renderer = renderer;
+            self.shown_cursor = None;
+            let mut output = self.renderer.init();
+            output.extend(self.render_display(true).await);
+            let _ = self.send_output(output).await;
+        } else {
+            if self.color_depth.is_none() {
+                self.renderer.set_color_depth(self.capabilities.color_depth());
+            }
+            self.renderer.set_hyperlinks(self.capabilities.hyperlinks());
+        }
+    }
+
+    /// Resize the session's display (e.g. when the client reports a new window size)
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.windows.resize(cols, rows);
+        self.renderer.resize(cols, rows);
+        if let Ok(recording) = self.recording.get_mut() {
+            recording.resize(cols, rows);
         }
     }
 
+    /// Resize to the window size the client reported (NAWS), within limits
+    /// Returns the new size, or None if it was empty or unchanged
+    pub fn resize_to_client(&mut self, cols: u16, rows: u16) -> Option<(usize, usize)> {
+        // The client picks these numbers, and the display is allocated from them
+        let (cols, rows) = ((cols as usize).min(MAX_CLIENT_COLS), (rows as usize).min(MAX_CLIENT_ROWS));
+        if cols == 0 || rows == 0 || (cols, rows) == (self.windows.cols, self.windows.rows) {
+            return None;
+        }
+        self.resize(cols, rows);
+        Some((cols, rows))
+    }
+
     /// Initialize display
-    pub async fn init(&mut self) -> Result<(), mpsc::error::SendError<String>> {
+    pub async fn init(&mut self) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
+        self.shown_cursor = None;
         let output = self.renderer.init();
-        self.output_tx.send(output).await
+        self.send_output(output).await
     }
 
     /// Shutdown display
-    pub async fn shutdown(&self) -> Result<(), mpsc::error::SendError<String>> {
+    pub async fn shutdown(&self) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
         let output = self.renderer.shutdown();
-        self.output_tx.send(output).await
+        self.send_output(output).await
     }
 
     /// Enable mouse tracking
-    pub async fn enable_mouse(&self, mode: MouseMode) -> Result<(), mpsc::error::SendError<String>> {
+    pub async fn enable_mouse(&self, mode: MouseMode) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
         info!("Enabling mouse mode {:?} for session {}", mode, self.id);
         let output = self.renderer.enable_mouse(mode);
-        self.output_tx.send(output).await
+        self.send_output(output).await
     }
 
     /// Disable mouse tracking
-    pub async fn disable_mouse(&self) -> Result<(), mpsc::error::SendError<String>> {
+    pub async fn disable_mouse(&self) -> Result<(), mpsc::error::SendError<Vec<u8>>> {
         info!("Disabling mouse mode for session {}", self.id);
         let output = self.renderer.disable_mouse();
-        self.output_tx.send(output).await
+        self.send_output(output).await
     }
 
     /// Handle a mouse event and return any window events that should be emitted
@@ -233,7 +509,7 @@ impl ClientSession {
                             // Check close button on topmost window only
                             if win.hit_close_button(x, y) {
                                 debug!("Close button hit for window: {}", top_id);
-                                events.push(Response::WindowCloseRequested { id: top_id });
+                                events.push(Response::WindowCloseRequested { session: self.id.clone(), id: top_id });
                                 forward_to_game = false;
                                 return (events, forward_to_game);
                             }
@@ -268,7 +544,7 @@ impl ClientSession {
 
                                 if is_double_click {
                                     // Double-click on title bar - maximize/restore
-                                    events.push(Response::WindowMaximizeRequested { id: top_id.clone() });
+                                    events.push(Response::WindowMaximizeRequested { session: self.id.clone(), id: top_id.clone() });
                                     self.interaction.last_title_bar_click = None;
                                     self.windows.bring_to_front(&top_id);
                                     forward_to_game = false;
@@ -304,7 +580,7 @@ impl ClientSession {
                         // Click on window content - bring to front and forward to game
                         self.windows.bring_to_front(&top_id);
                         self.focused_window = Some(top_id.clone());
-                        events.push(Response::WindowFocused { id: top_id });
+                        events.push(Response::WindowFocused { session: self.id.clone(), id: top_id });
                     }
                 }
             }
@@ -314,6 +590,7 @@ impl ClientSession {
                 if let Some(drag) = self.interaction.dragging.take() {
                     if let Some(win) = self.windows.get(&drag.window_id) {
                         events.push(Response::WindowMoved {
+                            session: self.id.clone(),
                             id: drag.window_id.clone(),
                             x: win.x,
                             y: win.y,
@@ -326,6 +603,7 @@ impl ClientSession {
                 if let Some(resize) = self.interaction.resizing.take() {
                     if let Some(win) = self.windows.get(&resize.window_id) {
                         events.push(Response::WindowResized {
+                            session: self.id.clone(),
                             id: resize.window_id.clone(),
                             width: win.width,
                             height: win.height,
@@ -430,14 +708,183 @@ impl ClientSession {
     /// Auto-flush display if windows are dirty (for live drag/resize feedback)
     pub async fn auto_flush(&mut self) {
         if self.windows.is_dirty() {
-            self.windows.composite();
-            let output = self.renderer.render(&self.windows.display, false);
-            self.windows.display.mark_all_clean();
-            self.windows.mark_all_clean();
-            let _ = self.output_tx.send(output).await;
+            self.redraw().await;
+        }
+    }
+
+    /// Composite and send whatever changed since the last frame
+    pub async fn redraw(&mut self) {
+        if self.viewing_shared_display() {
+            return;
+        }
+        self.windows.composite();
+        let output = self.render_display(false).await;
+        self.windows.display.mark_all_clean();
+        self.windows.mark_all_clean();
+        let _ = self.send_output(output).await;
+    }
+
+    /// Render the composited display, then show the focused terminal's cursor (or hide it)
+    async fn render_display(&mut self, force_full: bool) -> Vec<u8> {
+        let frame = self.renderer.render(&self.windows.display, force_full);
+        let cursor = self.terminal_cursor().await;
+        let mut output = Vec::new();
+        // Hide the cursor while drawing so it doesn't flicker across the screen
+        if !frame.is_empty() && self.shown_cursor.take().is_some() {
+            output.extend(self.renderer.set_cursor(None));
+        }
+        output.extend(frame);
+        if cursor != self.shown_cursor {
+            output.extend(self.renderer.set_cursor(cursor));
+            self.shown_cursor = cursor;
+        }
+        output
+    }
+
+    /// Where the focused terminal's cursor is on the display, if it should be shown:
+    /// not while the remote program hides it (`?25l`), the view is scrolled back,
+    /// or another window covers it
+    async fn terminal_cursor(&self) -> Option<CursorState> {
+        let id = self.focused_window.as_deref()?;
+        let handle = self.terminals.get(id)?;
+        let win = self.windows.get(id)?;
+        let terminal = handle.terminal.read().await;
+        if !terminal.modes.cursor_visible || terminal.scroll_offset > 0 {
+            return None;
+        }
+        if terminal.cursor_x >= win.inner_width() || terminal.cursor_y >= win.inner_height() {
+            return None;
+        }
+        let (ox, oy) = win.content_offset();
+        let (x, y) = (win.x + ox + terminal.cursor_x, win.y + oy + terminal.cursor_y);
+        if x >= self.windows.cols || y >= self.windows.rows || self.windows.window_at(x, y) != Some(id) {
+            return None;
+        }
+        Some(CursorState { x, y, style: terminal.cursor_style })
+    }
+
+    /// Game connection this session is attached to (None: every game)
+    pub fn game(&self) -> Option<String> {
+        owner(&self.game)
+    }
+
+    /// Attach the session to another game, returning the one it was attached to
+    pub fn set_game(&mut self, game: Option<String>) -> Option<String> {
+        std::mem::replace(&mut *self.game.write().unwrap_or_else(|e| e.into_inner()), game)
+    }
+
+    /// Whether a game may drive this session (it's attached to that game, or to none)
+    pub fn available_to(&self, game: &str) -> bool {
+        self.game().is_none_or(|g| g == game)
+    }
+
+    /// Whether we're showing another session's display instead of our own
+    pub fn viewing_shared_display(&self) -> bool {
+        !self.display_shares_from.is_empty()
+    }
+
+    /// Show another session's display, fitted to our size
+    pub async fn show_shared_display(&mut self, display: &Grid) {
+        let fitted = display.fit_to(self.windows.display.cols, self.windows.display.rows, self.scale_shared_display);
+        let output = self.renderer.render(&fitted, false);
+        self.shared_frame = Some(fitted);
+        if !output.is_empty() {
+            let _ = self.send_output(output).await;
+        }
+    }
+
+    /// Stop watching a session's display; when it was the last one, ours comes back
+    pub async fn stop_viewing_display(&mut self, source: &str) {
+        if !self.display_shares_from.iter().any(|s| s == source) {
+            return;
+        }
+        self.display_shares_from.retain(|s| s != source);
+        if !self.viewing_shared_display() {
+            self.spectator = false;
+            self.shared_frame = None;
+            self.renderer.invalidate();
+            self.redraw().await;
+        }
+    }
+
+    /// Refresh a mirrored window from the source session's copy
+    /// (`None` if the source window no longer exists: the mirror is hidden
+    /// until it does). The mirror keeps its own position and stacking once placed.
+    pub fn update_window_mirror(&mut self, mirror_id: &str, source_window: Option<Window>) {
+        let Some(mirror) = self.window_mirrors.iter_mut().find(|m| m.mirror_id == mirror_id) else {
+            return;
+        };
+        let Some(mut win) = source_window else {
+            self.windows.remove(mirror_id);
+            return;
+        };
+
+        mirror.source_x = win.x;
+        mirror.source_y = win.y;
+        let (requested_x, requested_y) = (mirror.x, mirror.y);
+
+        let is_new = match self.windows.get(mirror_id) {
+            Some(existing) => {
+                win.x = existing.x;
+                win.y = existing.y;
+                win.z_index = existing.z_index;
+                false
+            }
+            None => {
+                win.x = requested_x.unwrap_or(win.x);
+                win.y = requested_y.unwrap_or(win.y);
+                true
+            }
+        };
+        win.id = mirror_id.to_string();
+        // Size and lifetime belong to the source session
+        win.closable = false;
+        win.resizable = false;
+        win.dirty = true;
+
+        let (x, y, width, height) = (win.x, win.y, win.width, win.height);
+        *self.windows.create_window(mirror_id.to_string(), x, y, width, height) = win;
+        if is_new {
+            self.windows.bring_to_front(mirror_id);
         }
     }
 
+    /// Stop mirroring windows from `source` (only `window_id`, if given)
+    /// Returns whether any mirror was removed
+    pub fn remove_window_mirrors(&mut self, source: &str, window_id: Option<&str>) -> bool {
+        let (removed, kept): (Vec<WindowMirror>, Vec<WindowMirror>) = std::mem::take(&mut self.window_mirrors)
+            .into_iter()
+            .partition(|m| m.source == source && window_id.is_none_or(|id| m.window_id == id));
+        self.window_mirrors = kept;
+
+        for mirror in &removed {
+            self.windows.remove(&mirror.mirror_id);
+            if self.focused_window.as_deref() == Some(mirror.mirror_id.as_str()) {
+                self.focused_window = None;
+            }
+        }
+        !removed.is_empty()
+    }
+
+    /// If an input event belongs to a mirrored window that routes input,
+    /// the source session and the event in the source window's coordinates
+    /// (mouse events go by the window under the pointer, keys by focus)
+    pub fn mirror_input(&self, event: &InputEvent) -> Option<(String, InputEvent)> {
+        let window_id = match event {
+            InputEvent::Mouse { x, y, .. } => self.windows.window_at(*x as usize, *y as usize)?,
+            _ => self.focused_window.as_deref()?,
+        };
+        let mirror = self.window_mirrors.iter().find(|m| m.mirror_id == window_id && m.route_input)?;
+
+        let mut event = event.clone();
+        if let InputEvent::Mouse { x, y, .. } = &mut event {
+            let win = self.windows.get(window_id)?;
+            *x = (*x as usize - win.x + mirror.source_x) as u16;
+            *y = (*y as usize - win.y + mirror.source_y) as u16;
+        }
+        Some((mirror.source.clone(), event))
+    }
+
     /// Toggle debug console
     pub fn toggle_console(&mut self) {
         self.console_open = !self.console_open;
@@ -451,75 +898,107 @@ impl ClientSession {
         ch == '\x1C'  // Ctrl+\
     }
 
-    /// Draw the debug console overlay
-    pub async fn draw_console(&self) {
+    /// Draw the debug console overlay on top of what the client is showing
+    /// (through the renderer, so it reaches every kind of client; the next
+    /// frame sent covers it up again)
+    pub async fn draw_console(&mut self) {
         if !self.console_open {
             return;
         }
 
-        // Draw console box at top of screen (60 chars wide, 3 rows tall)
-        let width = 60;
-        let x = (self.windows.cols.saturating_sub(width)) / 2;
-
-        // Use ANSI escape codes to draw directly
-        let mut output = String::new();
-
-        // Position cursor and draw box
-        output.push_str(&format!("\x1b[1;{}H", x + 1)); // Row 1
-        output.push_str("\x1b[0;30;47m"); // Black on white
-        output.push_str("╔");
-        output.push_str(&"═".repeat(width - 2));
-        output.push_str("╗");
-
-        output.push_str(&format!("\x1b[2;{}H", x + 1)); // Row 2
-        output.push_str("║ APU Console (Ctrl+\\ close) > ");
-        let input_display = if self.console_input.len() > 25 {
-            &self.console_input[self.console_input.len() - 25..]
-        } else {
-            &self.console_input
+        let mut frame = match &self.shared_frame {
+            Some(shared) if self.viewing_shared_display() => shared.clone(),
+            _ => self.windows.display.clone(),
         };
-        output.push_str(input_display);
-        output.push_str("█"); // Cursor
-        let padding = width - 33 - input_display.len().min(25);
-        output.push_str(&" ".repeat(padding));
-        output.push_str("║");
-
-        output.push_str(&format!("\x1b[3;{}H", x + 1)); // Row 3
-        output.push_str("╚");
-        output.push_str(&"═".repeat(width - 2));
-        output.push_str("╝");
 
-        output.push_str("\x1b[0m"); // Reset colors
-
-        let _ = self.output_tx.send(output).await;
+        // Console box at the top of the screen (60 chars wide, 3 rows tall), black on white
+        let width = 60;
+        let x = (self.windows.cols.saturating_sub(width)) / 2;
+        let (fg, bg, attrs) = (Color::Black, Color::White, Attrs::default());
+        frame.write_str(x, 0, &format!("╔{}╗", "═".repeat(width - 2)), fg, bg, attrs);
+
+        // Input line, showing the end of what's been typed and a cursor
+        let typed = self.console_input.chars().count();
+        let input_display: String = self.console_input.chars().skip(typed.saturating_sub(25)).collect();
+        let padding = width - 33 - typed.min(25);
+        frame.write_str(x, 1, &format!("║ APU Console (Ctrl+\\ close) > {}█{}║", input_display, " ".repeat(padding)), fg, bg, attrs);
+
+        // Bottom border shows the token for resuming this session from another connection
+        let resume = self.resume_token.as_ref().map(|token| format!(" resume {} ", token)).unwrap_or_default();
+        frame.write_str(x, 2, &format!("╚{}{}╝", resume, "═".repeat(width - 2 - resume.len())), fg, bg, attrs);
+
+        let mut output = Vec::new();
+        if self.shown_cursor.take().is_some() {
+            output.extend(self.renderer.set_cursor(None));
+        }
+        output.extend(self.renderer.render(&frame, false));
+        let _ = self.send_output(output).await;
     }
 
-    /// Process a console command, returns (should_reset, should_close)
-    pub fn process_console_command(&mut self) -> (bool, bool) {
-        let cmd = self.console_input.trim().to_lowercase();
-        self.console_input.clear();
+    /// Process a console command
+    pub fn process_console_command(&mut self) -> ConsoleAction {
+        let input = std::mem::take(&mut self.console_input);
+        let mut words = input.split_whitespace();
+        let cmd = words.next().unwrap_or("").to_lowercase();
 
         match cmd.as_str() {
-            "reset" => (true, false),
-            "close" => (false, true),
+            "reset" => ConsoleAction::Reset,
+            "close" => ConsoleAction::Close,
+            "resume" => match words.next() {
+                Some(token) => ConsoleAction::Resume(token.to_string()),
+                None => ConsoleAction::None,
+            },
             "help" => {
                 // Just clear for now, could show help
-                (false, false)
+                ConsoleAction::None
             }
-            _ => (false, false)
+            _ => ConsoleAction::None
+        }
+    }
+
+    /// Bring a resumed session's new connection up to date:
+    /// terminal setup, mouse tracking and a full redraw
+    pub async fn restore_display(&mut self) {
+        let _ = self.init().await;
+        if self.mouse_mode != MouseMode::None {
+            let output = self.renderer.enable_mouse(self.mouse_mode);
+            let _ = self.send_output(output).await;
         }
+        self.renderer.invalidate();
+        self.redraw().await;
     }
 
     /// Process a command and return response
     pub async fn process_command(&mut self, cmd: Command) -> Response {
         match cmd {
-            Command::Init { cols, rows } => {
-                let cols = cols.unwrap_or(80);
-                let rows = rows.unwrap_or(24);
+            Command::Init { cols, rows, color_depth, renderer, charset } => {
+                if let Some(name) = &renderer {
+                    if create_renderer(name, 1, 1).is_none() {
+                        return Response::Error {
+                            message: format!("Unknown renderer: {}", name),
+                        };
+                    }
+                }
+                // Only the cells renderer sends no escape sequences, which
+                // PETSCII and ATASCII terminals don't understand
+                let charset = charset.map(|c| Charset::from_str(&c)).unwrap_or_default();
+                let name = self.renderer_name_for(renderer.as_deref());
+                if !charset.speaks_ansi() && !name.eq_ignore_ascii_case("cells") {
+                    return Response::Error {
+                        message: format!("Charset {} can't be used with the {} renderer", charset.name(), name),
+                    };
+                }
+                // Default to the size the client negotiated
+                let cols = cols.unwrap_or(self.windows.cols);
+                let rows = rows.unwrap_or(self.windows.rows);
                 self.windows.resize(cols, rows);
-                self.renderer = AnsiIbmRenderer::new(cols, rows);
+                self.color_depth = color_depth.map(|d| ColorDepth::from_str(&d));
+                self.renderer_name = renderer;
+                self.charset = charset;
+                self.renderer = self.select_renderer(cols, rows);
+                self.shown_cursor = None;
                 let output = self.renderer.init();
-                let _ = self.output_tx.send(output).await;
+                let _ = self.send_output(output).await;
                 Response::Info {
                     cols,
                     rows,
@@ -529,7 +1008,8 @@ impl ClientSession {
 
             Command::Shutdown => {
                 let output = self.renderer.shutdown();
-                let _ = self.output_tx.send(output).await;
+                self.renderer.invalidate();
+                let _ = self.send_output(output).await;
                 Response::Ok
             }
 
@@ -603,7 +1083,7 @@ impl ClientSession {
 
             Command::SetCell { window, x, y, char, fg, bg } => {
                 if let Some(win) = self.windows.get_mut(&window) {
-                    win.set(x, y, char, Color::from(fg), Some(Color::from(bg)));
+                    win.set(x, y, char, fg, Some(bg));
                     Response::Ok
                 } else {
                     Response::Error { message: format!("Window not found: {}", window) }
@@ -612,7 +1092,7 @@ impl ClientSession {
 
             Command::Print { window, x, y, text, fg, bg } => {
                 if let Some(win) = self.windows.get_mut(&window) {
-                    win.print(x, y, &text, Color::from(fg), Some(Color::from(bg)));
+                    win.print(x, y, &text, fg, Some(bg));
                     Response::Ok
                 } else {
                     Response::Error { message: format!("Window not found: {}", window) }
@@ -630,7 +1110,7 @@ impl ClientSession {
 
             Command::Fill { window, x, y, width, height, char, fg, bg } => {
                 if let Some(win) = self.windows.get_mut(&window) {
-                    win.fill(x, y, width, height, char, Color::from(fg), Some(Color::from(bg)));
+                    win.fill(x, y, width, height, char, fg, Some(bg));
                     Response::Ok
                 } else {
                     Response::Error { message: format!("Window not found: {}", window) }
@@ -638,12 +1118,12 @@ impl ClientSession {
             }
 
             Command::SetDirect { x, y, char, fg, bg } => {
-                self.windows.background.set(x, y, char, Color::from(fg), Color::from(bg), Attrs::default());
+                self.windows.background.put_char(x, y, char, fg, bg, Attrs::default());
                 Response::Ok
             }
 
             Command::PrintDirect { x, y, text, fg, bg } => {
-                self.windows.background.write_str(x, y, &text, Color::from(fg), Color::from(bg), Attrs::default());
+                self.windows.background.write_str(x, y, &text, fg, bg, Attrs::default());
                 Response::Ok
             }
 
@@ -651,10 +1131,10 @@ impl ClientSession {
                 for cell in cells {
                     if let Some(ref window_id) = cell.window {
                         if let Some(win) = self.windows.get_mut(window_id) {
-                            win.set(cell.x, cell.y, cell.char, Color::from(cell.fg), Some(Color::from(cell.bg)));
+                            win.set(cell.x, cell.y, cell.char, cell.fg, Some(cell.bg));
                         }
                     } else {
-                        self.windows.background.set(cell.x, cell.y, cell.char, Color::from(cell.fg), Color::from(cell.bg), Attrs::default());
+                        self.windows.background.put_char(cell.x, cell.y, cell.char, cell.fg, cell.bg, Attrs::default());
                     }
                 }
                 Response::Ok
@@ -665,14 +1145,19 @@ impl ClientSession {
                 self.sync_terminals_to_windows().await;
                 // Composite windows
                 self.windows.composite();
+                // While we're watching another session's display, keep ours off screen
+                if self.viewing_shared_display() {
+                    return Response::Output { data: String::new() };
+                }
                 // Render
-                let output = self.renderer.render(&self.windows.display, force_full);
+                let output = self.render_display(force_full).await;
                 // Mark clean
                 self.windows.display.mark_all_clean();
                 self.windows.mark_all_clean();
                 // Send output
-                let _ = self.output_tx.send(output.clone()).await;
-                Response::Output { data: output }
+                let data = String::from_utf8_lossy(&output).into_owned();
+                let _ = self.send_output(output).await;
+                Response::Output { data }
             }
 
             Command::BringToFront { id } => {
@@ -687,23 +1172,41 @@ impl ClientSession {
 
             Command::EnableMouse { mode } => {
                 let mouse_mode = MouseMode::from_str(&mode);
+                self.mouse_mode = mouse_mode;
                 let output = self.renderer.enable_mouse(mouse_mode);
-                let _ = self.output_tx.send(output).await;
+                let _ = self.send_output(output).await;
                 Response::Ok
             }
 
             Command::DisableMouse => {
+                self.mouse_mode = MouseMode::None;
                 let output = self.renderer.disable_mouse();
-                let _ = self.output_tx.send(output).await;
+                let _ = self.send_output(output).await;
                 Response::Ok
             }
 
             // Session management commands are handled at server level, not session level
             // These return errors if they somehow get to process_command
+            Command::Auth { .. } => {
+                Response::Error { message: "Auth should be handled at server level".to_string() }
+            }
+
+            Command::Binary => {
+                Response::Error { message: "Binary should be handled at server level".to_string() }
+            }
+
             Command::ListSessions => {
                 Response::Error { message: "ListSessions should be handled at server level".to_string() }
             }
 
+            Command::RegisterGame { .. } => {
+                Response::Error { message: "RegisterGame should be handled at server level".to_string() }
+            }
+
+            Command::Handoff { .. } => {
+                Response::Error { message: "Handoff should be handled at server level".to_string() }
+            }
+
             Command::ShareDisplay { .. } => {
                 Response::Error { message: "ShareDisplay should be handled at server level".to_string() }
             }
@@ -740,6 +1243,14 @@ impl ClientSession {
             Command::ResizeTerminal { .. } => {
                 Response::Error { message: "ResizeTerminal should be handled at server level".to_string() }
             }
+
+            Command::ScrollTerminal { .. } => {
+                Response::Error { message: "ScrollTerminal should be handled at server level".to_string() }
+            }
+
+            Command::GetScrollback { .. } => {
+                Response::Error { message: "GetScrollback should be handled at server level".to_string() }
+            }
         }
     }
 
@@ -750,19 +1261,89 @@ impl ClientSession {
         for (window_id, handle) in &self.terminals {
             let terminal = handle.terminal.read().await;
             if let Some(win) = self.windows.get_mut(window_id) {
-                // Copy terminal cells to window
+                // Copy terminal cells to window (from history while scrolled back)
                 for y in 0..terminal.height.min(win.inner_height()) {
+                    let row = terminal.view_row(y);
                     for x in 0..terminal.width.min(win.inner_width()) {
-                        let cell = &terminal.screen[y][x];
-                        win.set(x, y, cell.char, cell.fg, Some(cell.bg));
+                        match row.get(x) {
+                            Some(cell) => win.set_cell(x, y, &terminal.display_cell(cell)),
+                            None => win.set(x, y, ' ', Color::White, Some(Color::Black)),
+                        }
+                    }
+                }
+                // Show how far back the view is
+                if terminal.scroll_offset > 0 {
+                    let label = format!(" ↑{}/{} ", terminal.scroll_offset, terminal.scrollback.len());
+                    let width = terminal.width.min(win.inner_width());
+                    let start = width.saturating_sub(label.chars().count());
+                    for (x, ch) in (start..width).zip(label.chars()) {
+                        win.set(x, 0, ch, Color::Black, Some(Color::BrightYellow));
                     }
                 }
             }
         }
-        // Clear dirty flags after sync
-        for (_, handle) in &self.terminals {
+        // Clear dirty flags after sync, showing new titles from the remote program
+        for (window_id, handle) in &self.terminals {
             let mut terminal = handle.terminal.write().await;
             terminal.dirty = false;
+            if std::mem::take(&mut terminal.title_changed) {
+                if let (Some(title), Some(win)) = (&terminal.title, self.windows.get_mut(window_id)) {
+                    win.set_title(title.as_str());
+                }
+            }
+        }
+    }
+
+    /// Scroll a terminal's view into its history (positive lines go back)
+    /// Returns the new offset and how many lines of history there are
+    pub async fn scroll_terminal(&mut self, id: &str, lines: isize) -> Option<(usize, usize)> {
+        let scrolled = {
+            let mut terminal = self.terminals.get(id)?.terminal.write().await;
+            terminal.scroll_view(lines);
+            (terminal.scroll_offset, terminal.scrollback.len())
+        };
+        self.sync_terminals_to_windows().await;
+        Some(scrolled)
+    }
+
+    /// Scroll the terminal under the mouse wheel, if there is one
+    pub async fn wheel_terminal_at(&mut self, x: usize, y: usize, button: MouseButton) -> bool {
+        let lines = match button {
+            MouseButton::WheelUp => 3,
+            MouseButton::WheelDown => -3,
+            _ => return false,
+        };
+        let Some(id) = self.windows.window_at(x, y).filter(|id| self.terminals.contains_key(*id)).map(String::from) else {
+            return false;
+        };
+        self.scroll_terminal(&id, lines).await;
+        true
+    }
+
+    /// Pass a mouse event on a terminal window's content to the remote program,
+    /// if it asked for mouse reports (coordinates are relative to the content)
+    pub async fn send_terminal_mouse(&self, event: &InputEvent) -> bool {
+        let InputEvent::Mouse { x, y, button, event, modifiers } = event else {
+            return false;
+        };
+        let (x, y) = (*x as usize, *y as usize);
+        let Some((id, win)) = self.windows.window_at(x, y).and_then(|id| Some((id, self.windows.get(id)?))) else {
+            return false;
+        };
+        let Some(handle) = self.terminals.get(id) else {
+            return false;
+        };
+        let (ox, oy) = win.content_offset();
+        let (Some(col), Some(row)) = ((x - win.x).checked_sub(ox), (y - win.y).checked_sub(oy)) else {
+            return false;
+        };
+        if col >= win.inner_width() || row >= win.inner_height() {
+            return false;
+        }
+        let modes = handle.terminal.read().await.modes;
+        match mouse_event_to_bytes(col, row, *button, *event, *modifiers, &modes) {
+            Some(bytes) => handle.input_tx.send(bytes).await.is_ok(),
+            None => false,
         }
     }
 
@@ -793,12 +1374,34 @@ pub struct Server {
     pub client_port: u16,
     /// Game port bind address (127.0.0.1 for local, 0.0.0.0 for network)
     pub game_bind: String,
+    /// WebSocket client port (browser terminals connect here), if enabled
+    pub websocket_port: Option<u16>,
+    /// Tokens games must authenticate with (none: no authentication)
+    pub auth_tokens: Vec<AuthToken>,
+    /// How long a session is kept after its client drops, so it can be resumed
+    /// (zero: sessions end with their connection)
+    pub resume_grace: Duration,
+    /// Unix socket path games can also connect on
+    pub game_socket: Option<PathBuf>,
+    /// Shell command for a game to run as a child process, speaking the
+    /// protocol over its stdin/stdout (the server exits with it)
+    pub game_command: Option<String>,
+    /// Let games open terminals that run programs on this machine
+    /// (the "pty" and "ssh" transports)
+    pub local_terminals: bool,
+    /// Directory recordings are written to (terminal recordings games ask
+    /// for, and session recordings); none: recording is off
+    pub record_dir: Option<PathBuf>,
+    /// Record every session's output in `record_dir`
+    pub record_sessions: RecordFormats,
     /// Active sessions
     sessions: Arc<RwLock<HashMap<String, ClientSession>>>,
     /// Shutdown channels for disconnecting clients
     shutdown_channels: Arc<RwLock<HashMap<String, oneshot::Sender<()>>>>,
     /// Channel to broadcast events to game connections
-    event_tx: broadcast::Sender<Response>,
+    event_tx: broadcast::Sender<GameEvent>,
+    /// Connected games
+    games: Arc<RwLock<GameRegistry>>,
 }
 
 impl Server {
@@ -808,9 +1411,18 @@ impl Server {
             game_port,
             client_port,
             game_bind,
+            websocket_port: None,
+            auth_tokens: Vec::new(),
+            resume_grace: Duration::from_secs(60),
+            game_socket: None,
+            game_command: None,
+            local_terminals: false,
+            record_dir: None,
+            record_sessions: RecordFormats::default(),
             sessions: Arc::new(RwLock::new(HashMap::new())),
             shutdown_channels: Arc::new(RwLock::new(HashMap::new())),
             event_tx,
+            games: Arc::new(RwLock::new(GameRegistry::default())),
         }
     }
 
@@ -819,30 +1431,71 @@ impl Server {
         info!("Starting APU server...");
         info!("Game port: {} (bind: {})", self.game_port, self.game_bind);
         info!("Client port: {}", self.client_port);
+        if let Some(port) = self.websocket_port {
+            info!("WebSocket port: {}", port);
+        }
 
         let game_listener = TcpListener::bind(format!("{}:{}", self.game_bind, self.game_port)).await?;
         let client_listener = TcpListener::bind(format!("0.0.0.0:{}", self.client_port)).await?;
+        let websocket_listener = match self.websocket_port {
+            Some(port) => Some(TcpListener::bind(format!("0.0.0.0:{}", port)).await?),
+            None => None,
+        };
+        #[cfg(unix)]
+        let unix_listener = match &self.game_socket {
+            Some(path) => {
+                // A socket file left behind by an earlier run would make bind fail
+                if std::fs::symlink_metadata(path).is_ok_and(|m| std::os::unix::fs::FileTypeExt::is_socket(&m.file_type())) {
+                    std::fs::remove_file(path)?;
+                }
+                info!("Game socket: {}", path.display());
+                Some(tokio::net::UnixListener::bind(path)?)
+            }
+            None => None,
+        };
+        #[cfg(not(unix))]
+        if self.game_socket.is_some() {
+            return Err("Unix game sockets aren't supported on this platform".into());
+        }
 
         info!("APU server listening");
 
-        let sessions = self.sessions.clone();
         let sessions2 = self.sessions.clone();
-        let shutdown_channels = self.shutdown_channels.clone();
         let shutdown_channels2 = self.shutdown_channels.clone();
-        let event_tx = self.event_tx.clone();
         let event_tx2 = self.event_tx.clone();
+        let games2 = self.games.clone();
+        let resume_grace = self.resume_grace;
+        if self.auth_tokens.is_empty() {
+            info!("Game port authentication disabled");
+        }
+        if self.local_terminals {
+            info!("Games may run local programs in terminal windows");
+        }
+        let record_dir2 = self.record_dir.clone();
+        let record_sessions = self.record_sessions;
+        if let Some(dir) = &self.record_dir {
+            std::fs::create_dir_all(dir)?;
+            info!("Recordings go in {}", dir.display());
+        }
+        let game_context = GameContext {
+            sessions: self.sessions.clone(),
+            shutdown_channels: self.shutdown_channels.clone(),
+            event_tx: self.event_tx.clone(),
+            auth_tokens: Arc::new(self.auth_tokens.clone()),
+            games: self.games.clone(),
+            local_terminals: self.local_terminals,
+            record_dir: self.record_dir.clone(),
+        };
 
         // Handle game connections
+        let context = game_context.clone();
         let game_handle = tokio::spawn(async move {
             loop {
                 match game_listener.accept().await {
                     Ok((socket, addr)) => {
                         info!("Game connected from {}", addr);
-                        let sessions = sessions.clone();
-                        let shutdown_channels = shutdown_channels.clone();
-                        let event_rx = event_tx.subscribe();
-                        let event_tx_clone = event_tx.clone();
-                        tokio::spawn(handle_game_connection(socket, sessions, shutdown_channels, event_rx, event_tx_clone));
+                        let game_id = format!("game_{}", addr.to_string().replace(":", "_").replace(".", "_"));
+                        tokio::spawn(handle_game_connection(socket, game_id, context.event_tx.subscribe(), context.clone()));
                     }
                     Err(e) => {
                         error!("Game accept error: {}", e);
@@ -860,7 +1513,8 @@ impl Server {
                         let sessions = sessions2.clone();
                         let shutdown_channels = shutdown_channels2.clone();
                         let event_tx = event_tx2.clone();
-                        tokio::spawn(handle_client_connection(socket, addr.to_string(), sessions, shutdown_channels, event_tx));
+                        let games = games2.clone();
+                        tokio::spawn(handle_client_connection(socket, addr.to_string(), ClientProtocol::Telnet, ClientOptions::default(), sessions, shutdown_channels, event_tx, games, resume_grace, session_recording(&record_dir2, record_sessions)));
                     }
                     Err(e) => {
                         error!("Client accept error: {}", e);
@@ -869,327 +1523,748 @@ impl Server {
             }
         });
 
-        // Wait for both
-        let _ = tokio::try_join!(game_handle, client_handle)?;
+        // Handle WebSocket client connections
+        if let Some(websocket_listener) = websocket_listener {
+            let sessions = self.sessions.clone();
+            let shutdown_channels = self.shutdown_channels.clone();
+            let event_tx = self.event_tx.clone();
+            let games = self.games.clone();
+            let resume_grace = self.resume_grace;
+            let record_sessions = session_recording(&self.record_dir, self.record_sessions);
+            tokio::spawn(async move {
+                loop {
+                    match websocket_listener.accept().await {
+                        Ok((socket, addr)) => {
+                            let sessions = sessions.clone();
+                            let shutdown_channels = shutdown_channels.clone();
+                            let event_tx = event_tx.clone();
+                            let games = games.clone();
+                            let record_sessions = record_sessions.clone();
+                            tokio::spawn(async move {
+                                match websocket::accept(socket).await {
+                                    Ok((stream, path)) => {
+                                        // (the query may hold a resume token, so it isn't logged)
+                                        info!("WebSocket client connected from {} ({})", addr, path.split('?').next().unwrap_or(""));
+                                        // ws://host:port/cells asks for structured cell output instead of ANSI,
+                                        // ?resume=<token> for a dropped session
+                                        let options = ClientOptions::from_path(&path);
+                                        handle_client_connection(stream, addr.to_string(), ClientProtocol::WebSocket, options, sessions, shutdown_channels, event_tx, games, resume_grace, record_sessions).await;
+                                    }
+                                    Err(e) => {
+                                        debug!("WebSocket handshake with {} failed: {}", addr, e);
+                                    }
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            error!("WebSocket accept error: {}", e);
+                        }
+                    }
+                }
+            });
+        }
+
+        // Handle game connections on the Unix socket
+        #[cfg(unix)]
+        if let Some(unix_listener) = unix_listener {
+            let context = game_context.clone();
+            tokio::spawn(async move {
+                // Unix socket peers are usually unnamed, so number them instead
+                let mut count = 0u64;
+                loop {
+                    match unix_listener.accept().await {
+                        Ok((socket, _)) => {
+                            count += 1;
+                            info!("Game connected on Unix socket (#{})", count);
+                            let game_id = format!("game_unix_{}", count);
+                            tokio::spawn(handle_game_connection(socket, game_id, context.event_tx.subscribe(), context.clone()));
+                        }
+                        Err(e) => {
+                            error!("Game socket accept error: {}", e);
+                        }
+                    }
+                }
+            });
+        }
+
+        // Run the child game, if any
+        let child_handle = match &self.game_command {
+            Some(command) => {
+                let mut child = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()?;
+                let pid = child.id().unwrap_or_default();
+                info!("Started game process {}: {}", pid, command);
+                let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                    return Err("Game process has no stdin/stdout".into());
+                };
+                let socket = tokio::io::join(stdout, stdin);
+                let game_id = format!("game_child_{}", pid);
+                let connection = handle_game_connection(socket, game_id, self.event_tx.subscribe(), game_context);
+                tokio::spawn(async move {
+                    connection.await;
+                    match child.wait().await {
+                        Ok(status) => info!("Game process exited ({})", status),
+                        Err(e) => error!("Failed to wait for game process: {}", e),
+                    }
+                })
+            }
+            None => tokio::spawn(std::future::pending()),
+        };
+
+        // Wait for the listeners (or the child game)
+        tokio::select! {
+            result = async { tokio::try_join!(game_handle, client_handle) } => {
+                result?;
+            }
+            _ = child_handle => {
+                info!("Shutting down with the game process");
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = &self.game_socket {
+            let _ = std::fs::remove_file(path);
+        }
+
+        // Write out whatever recordings still have queued
+        let recordings: Vec<Recording> = self.sessions.write().await.values_mut().flat_map(ClientSession::take_recordings).collect();
+        let _ = tokio::task::spawn_blocking(move || recordings.into_iter().for_each(|mut recording| recording.finish())).await;
 
         Ok(())
     }
 }
 
-/// Handle a game connection (receives JSON commands, sends events)
-async fn handle_game_connection(
-    socket: TcpStream,
+/// How long a game has to send its auth line
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest auth line a game may send (bytes)
+const MAX_AUTH_LINE: u64 = 4096;
+
+/// Server state shared by every game connection
+#[derive(Clone)]
+struct GameContext {
     sessions: Arc<RwLock<HashMap<String, ClientSession>>>,
     shutdown_channels: Arc<RwLock<HashMap<String, oneshot::Sender<()>>>>,
-    mut event_rx: broadcast::Receiver<Response>,
-    event_tx: broadcast::Sender<Response>,
-) {
-    let (reader, mut writer) = socket.into_split();
+    event_tx: broadcast::Sender<GameEvent>,
+    /// Tokens a game may authenticate with (none: no authentication)
+    auth_tokens: Arc<Vec<AuthToken>>,
+    games: Arc<RwLock<GameRegistry>>,
+    /// Whether games may run local programs in terminals
+    local_terminals: bool,
+    /// Where games' recordings go (none: games can't record)
+    record_dir: Option<PathBuf>,
+}
+
+/// Handle a game connection (receives JSON commands, sends events)
+/// Sessions attached to another game are off limits; events go only to the
+/// game a session is attached to (or every game, for unattached sessions)
+/// Works over any byte stream: TCP, a Unix socket or a child's stdin/stdout
+async fn handle_game_connection<S>(socket: S, game_id: String, mut event_rx: broadcast::Receiver<GameEvent>, context: GameContext)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let GameContext { sessions, shutdown_channels, event_tx, auth_tokens, games, local_terminals, record_dir } = context;
+    let (reader, mut writer) = tokio::io::split(socket);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
+    // Authenticate before the game sees or touches any session
+    let scope = if auth_tokens.is_empty() {
+        SessionScope::all()
+    } else {
+        // A peer that never finishes its first line mustn't hold the connection open
+        let read = tokio::time::timeout(AUTH_TIMEOUT, (&mut reader).take(MAX_AUTH_LINE).read_line(&mut line)).await;
+        if !matches!(read, Ok(Ok(_))) || (line.len() as u64 >= MAX_AUTH_LINE && !line.ends_with('\n')) {
+            warn!("Game sent no auth line in time (or too long a one)");
+            return;
+        }
+        let auth = parse_targeted_command(line.trim()).ok();
+        let request_id = auth.as_ref().and_then(|a| a.request_id.clone());
+        let granted = match auth.map(|a| a.command) {
+            Some(Command::Auth { token }) => authenticate(&auth_tokens, &token).map(|t| t.scope.clone()),
+            _ => None,
+        };
+        let (reply, scope) = match granted {
+            Some(scope) => (Response::Ok, scope),
+            None => (Response::Error { message: "Authentication failed".to_string() }, SessionScope::all()),
+        };
+        let json = match &request_id {
+            Some(request_id) => serialize_reply(&reply, request_id),
+            None => serialize_response(&reply),
+        };
+        let _ = writer.write_all(format!("{}\n", json).as_bytes()).await;
+        let _ = writer.flush().await;
+        if matches!(reply, Response::Error { .. }) {
+            warn!("Game failed to authenticate");
+            return;
+        }
+        info!("Game authenticated");
+        scope
+    };
+
     // Notify game about all existing sessions (for reconnection after game restart)
     {
         let sessions_read = sessions.read().await;
-        for session_id in sessions_read.keys() {
-            let connect_event = Response::ClientConnect { session: session_id.clone() };
+        let visible: Vec<&ClientSession> = sessions_read.values().filter(|s| scope.allows(&s.id) && s.available_to(&game_id)).collect();
+        for session in &visible {
+            let connect_event = Response::ClientConnect { session: session.id.clone() };
             let json = serialize_response(&connect_event);
             if let Err(e) = writer.write_all(format!("{}\n", json).as_bytes()).await {
                 error!("Failed to send existing session to game: {}", e);
             }
         }
         let _ = writer.flush().await;
-        if !sessions_read.is_empty() {
-            info!("Notified game about {} existing session(s)", sessions_read.len());
+        if !visible.is_empty() {
+            info!("Notified game about {} existing session(s)", visible.len());
         }
     }
 
-    // Task to send events to game
+    // Replies to this game's commands (not broadcast to other games)
+    let (reply_tx, mut reply_rx) = mpsc::unbounded_channel::<(Option<Value>, Response)>();
+
+    // Task to send events and replies to game
+    let writer_scope = scope.clone();
+    let writer_game = game_id.clone();
     let writer_handle = tokio::spawn(async move {
         loop {
-            match event_rx.recv().await {
-                Ok(event) => {
-                    let json = serialize_response(&event);
-                    if let Err(e) = writer.write_all(format!("{}\n", json).as_bytes()).await {
-                        error!("Failed to send event to game: {}", e);
-                        break;
+            let json = tokio::select! {
+                reply = reply_rx.recv() => match reply {
+                    Some((Some(request_id), response)) => serialize_reply(&response, &request_id),
+                    Some((None, response)) => serialize_response(&response),
+                    None => break,
+                },
+                event = event_rx.recv() => match event {
+                    // Events for other games, or about sessions outside the game's scope, aren't its business
+                    // (a game limited to some sessions doesn't get events that name none)
+                    Ok(event) if event.game.as_ref().is_some_and(|g| *g != writer_game) => continue,
+                    Ok(event) if event.response.session().map_or(!writer_scope.is_all(), |id| !writer_scope.allows(id)) => continue,
+                    Ok(event) => serialize_response(&event.response),
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        debug!("Game connection lagged by {} events", n);
+                        continue;
                     }
-                    let _ = writer.flush().await;
-                }
-                Err(broadcast::error::RecvError::Closed) => break,
-                Err(broadcast::error::RecvError::Lagged(n)) => {
-                    debug!("Game connection lagged by {} events", n);
-                }
+                },
+            };
+            if let Err(e) = writer.write_all(format!("{}\n", json).as_bytes()).await {
+                error!("Failed to send event to game: {}", e);
+                break;
             }
+            let _ = writer.flush().await;
         }
     });
 
-    // Read commands from game
+    // Read commands from game: JSON lines, or binary frames once the game asks for them
+    let mut binary_frames = false;
     loop {
-        line.clear();
-        match reader.read_line(&mut line).await {
-            Ok(0) => {
-                info!("Game disconnected");
-                break;
-            }
-            Ok(_) => {
-                let trimmed = line.trim();
-                if trimmed.is_empty() {
-                    continue;
+        let parsed = if binary_frames {
+            match binary::read_frame(&mut reader).await {
+                Ok(Some(frame)) => binary::decode_frame(&frame).map_err(|e| (e, None)),
+                Ok(None) => {
+                    info!("Game disconnected");
+                    break;
                 }
-
-                debug!("Game command: {}", trimmed);
-
-                // Parse command with session targeting
-                match parse_targeted_command(trimmed) {
-                    Ok(targeted) => {
-                        let mut sessions = sessions.write().await;
-
-                        // Handle server-level commands first
-                        match &targeted.command {
-                            Command::ListSessions => {
-                                let session_list: Vec<SessionInfo> = sessions
-                                    .values()
-                                    .map(|s| s.info())
-                                    .collect();
-                                debug!("ListSessions: {} sessions", session_list.len());
-                                // Send sessions list as response
-                                let _ = event_tx.send(Response::Sessions { sessions: session_list });
-                                continue;
-                            }
-
-                            Command::ShareDisplay { source, target } => {
-                                // Mark that target should receive source's display updates
-                                if let Some(target_session) = sessions.get_mut(target) {
-                                    if !target_session.display_shares_from.contains(source) {
-                                        target_session.display_shares_from.push(source.clone());
-                                    }
-                                }
-                                if let Some(source_session) = sessions.get_mut(source) {
-                                    if !source_session.display_shares_to.contains(target) {
-                                        source_session.display_shares_to.push(target.clone());
-                                    }
-                                }
-                                debug!("ShareDisplay: {} -> {}", source, target);
-                                continue;
+                Err(e) => {
+                    error!("Game read error: {}", e);
+                    break;
+                }
+            }
+        } else {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) => {
+                    info!("Game disconnected");
+                    break;
+                }
+                Ok(_) => {
+                    let trimmed = line.trim();
+                    if trimmed.is_empty() {
+                        continue;
+                    }
+                    debug!("Game command: {}", trimmed);
+                    // Parse command with session targeting
+                    parse_targeted_command(trimmed).map_err(|e| (e.to_string(), parse_request_id(trimmed)))
+                }
+                Err(e) => {
+                    error!("Game read error: {}", e);
+                    break;
+                }
+            }
+        };
+
+        match parsed {
+            Ok(targeted) => {
+                let mut sessions = sessions.write().await;
+
+                // Games that tag a command with a request_id get a reply to it
+                let request_id = targeted.request_id.clone();
+                let reply = |response: Response| {
+                    if request_id.is_some() {
+                        let _ = reply_tx.send((request_id.clone(), response));
+                    }
+                };
+
+                // Games may only target sessions their token allows, and not ones attached to other games
+                let named = targeted.session.iter().map(String::as_str).filter(|id| *id != "*")
+                    .chain(targeted.command.sessions_named());
+                let denied = named.into_iter().find(|id| {
+                    !scope.allows(id) || sessions.get(*id).is_some_and(|s| !s.available_to(&game_id))
+                });
+                if let Some(denied) = denied {
+                    // Always reported, like parse errors
+                    let _ = reply_tx.send((request_id.clone(), Response::Error {
+                        message: format!("Session not allowed: {}", denied),
+                    }));
+                    continue;
+                }
+
+                // Handle server-level commands first
+                match &targeted.command {
+                    // Already authenticated (or authentication is off)
+                    Command::Auth { .. } => {
+                        reply(Response::Ok);
+                        continue;
+                    }
+
+                    Command::Binary => {
+                        binary_frames = true;
+                        reply(Response::Ok);
+                        continue;
+                    }
+
+                    Command::RegisterGame { name, lobby } => {
+                        // The lobby gets every new player, so it has to be able to see them all
+                        if *lobby && !scope.is_all() {
+                            reply(Response::Error { message: "Only a game with an unscoped token can be the lobby".to_string() });
+                            continue;
+                        }
+                        let mut registry = games.write().await;
+                        if registry.find(name).is_some_and(|id| id != game_id) {
+                            reply(Response::Error { message: format!("Game name taken: {}", name) });
+                            continue;
+                        }
+                        registry.names.insert(game_id.clone(), name.clone());
+                        if *lobby {
+                            registry.lobby = Some(game_id.clone());
+                        } else if registry.lobby.as_deref() == Some(game_id.as_str()) {
+                            registry.lobby = None;
+                        }
+                        info!("Game {} registered as {}{}", game_id, name, if *lobby { " (lobby)" } else { "" });
+                        reply(Response::Ok);
+                        continue;
+                    }
+
+                    Command::Handoff { game } => {
+                        let to = games.read().await.find(game).map(String::from);
+                        let Some(to) = to else {
+                            reply(Response::Error { message: format!("Game not found: {}", game) });
+                            continue;
+                        };
+                        let Some(session) = targeted.session.as_deref().filter(|id| *id != "*").and_then(|id| sessions.get_mut(id)) else {
+                            reply(require_session(&sessions, targeted.session.as_deref().filter(|id| *id != "*")));
+                            continue;
+                        };
+                        // The old game is told where the player went; the new one sees a new player
+                        let from = session.set_game(Some(to.clone())).unwrap_or_else(|| game_id.clone());
+                        let _ = event_tx.send(GameEvent::new(Some(from), Response::ClientHandoff {
+                            session: session.id.clone(),
+                            game: game.clone(),
+                        }));
+                        let _ = event_tx.send(GameEvent::new(Some(to), Response::ClientConnect { session: session.id.clone() }));
+                        info!("Session {} handed off to {}", session.id, game);
+                        reply(Response::Ok);
+                        continue;
+                    }
+
+                    Command::ListSessions => {
+                        let session_list: Vec<SessionInfo> = sessions
+                            .values()
+                            .filter(|s| scope.allows(&s.id) && s.available_to(&game_id))
+                            .map(|s| s.info())
+                            .collect();
+                        debug!("ListSessions: {} sessions", session_list.len());
+                        // Sessions list goes back to the asking game only
+                        let _ = reply_tx.send((request_id.clone(), Response::Sessions { sessions: session_list }));
+                        continue;
+                    }
+
+                    Command::ShareDisplay { source, target, spectator, scale } => {
+                        // A session showing a display it can't get updates for would freeze
+                        let response = match sessions_exist(&sessions, &[source, target]) {
+                            Response::Ok if source == target => {
+                                Response::Error { message: format!("Session {} can't view its own display", source) }
+                            }
+                            response => response,
+                        };
+                        let shared = matches!(response, Response::Ok);
+                        reply(response);
+                        if !shared {
+                            continue;
+                        }
+                        // Mark that target should receive source's display updates
+                        if let Some(target_session) = sessions.get_mut(target) {
+                            if !target_session.display_shares_from.contains(source) {
+                                target_session.display_shares_from.push(source.clone());
                             }
+                            target_session.spectator = *spectator;
+                            target_session.scale_shared_display = *scale;
+                        }
+                        if let Some(source_session) = sessions.get_mut(source) {
+                            if !source_session.display_shares_to.contains(target) {
+                                source_session.display_shares_to.push(target.clone());
+                            }
+                        }
+                        propagate_display_shares(&mut sessions, source).await;
+                        debug!("ShareDisplay: {} -> {}", source, target);
+                        continue;
+                    }
 
-                            Command::UnshareDisplay { source, target } => {
-                                if let Some(target_session) = sessions.get_mut(target) {
-                                    target_session.display_shares_from.retain(|s| s != source);
-                                }
-                                if let Some(source_session) = sessions.get_mut(source) {
-                                    source_session.display_shares_to.retain(|t| t != target);
-                                }
-                                debug!("UnshareDisplay: {} -> {}", source, target);
-                                continue;
+                    Command::UnshareDisplay { source, target } => {
+                        reply(Response::Ok);
+                        if let Some(target_session) = sessions.get_mut(target) {
+                            target_session.stop_viewing_display(source).await;
+                        }
+                        if let Some(source_session) = sessions.get_mut(source) {
+                            source_session.display_shares_to.retain(|t| t != target);
+                        }
+                        debug!("UnshareDisplay: {} -> {}", source, target);
+                        continue;
+                    }
+
+                    Command::ShareWindow { window_id, source, target, x, y, route_input } => {
+                        // The window has to exist to be shared (it may come and go afterwards)
+                        let response = match sessions_exist(&sessions, &[source, target]) {
+                            Response::Ok if sessions.get(source).is_some_and(|s| s.windows.get(window_id).is_none()) => {
+                                Response::Error { message: format!("Window not found: {}", window_id) }
                             }
+                            response => response,
+                        };
+                        let shared = matches!(response, Response::Ok);
+                        reply(response);
+                        if !shared {
+                            continue;
+                        }
+                        if let Some(target_session) = sessions.get_mut(target) {
+                            let mirror = WindowMirror::new(source.clone(), window_id.clone(), *x, *y, *route_input);
+                            // Sharing again replaces the old settings
+                            target_session.remove_window_mirrors(source, Some(window_id));
+                            target_session.window_mirrors.push(mirror);
+                        }
+                        propagate_window_shares(&mut sessions, source).await;
+                        debug!("ShareWindow: {} from {} -> {}", window_id, source, target);
+                        continue;
+                    }
 
-                            Command::ShareWindow { .. } | Command::UnshareWindow { .. } => {
-                                // TODO: Implement window-level sharing
-                                debug!("Window sharing not yet implemented");
-                                continue;
+                    Command::UnshareWindow { window_id, source, target } => {
+                        reply(Response::Ok);
+                        if let Some(target_session) = sessions.get_mut(target) {
+                            if target_session.remove_window_mirrors(source, Some(window_id)) {
+                                target_session.redraw().await;
                             }
+                        }
+                        debug!("UnshareWindow: {} from {} -> {}", window_id, source, target);
+                        continue;
+                    }
 
-                            // Handle Shutdown command specially to disconnect the session
-                            Command::Shutdown => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    // First send the shutdown output to the session
-                                    if let Some(session) = sessions.get_mut(session_id) {
-                                        let _ = session.process_command(Command::Shutdown).await;
-                                    }
-                                    // Then trigger the shutdown signal to disconnect
-                                    let mut channels = shutdown_channels.write().await;
-                                    if let Some(tx) = channels.remove(session_id) {
-                                        let _ = tx.send(());
-                                        info!("Shutdown signal sent to session {}", session_id);
-                                    }
-                                }
-                                continue;
+                    // Handle Shutdown command specially to disconnect the session
+                    Command::Shutdown => {
+                        reply(require_session(&sessions, targeted.session.as_deref()));
+                        if let Some(session_id) = targeted.session.as_deref() {
+                            // First send the shutdown output to the session
+                            if let Some(session) = sessions.get_mut(session_id) {
+                                let _ = session.process_command(Command::Shutdown).await;
                             }
+                            // Then trigger the shutdown signal to disconnect
+                            let mut channels = shutdown_channels.write().await;
+                            if let Some(tx) = channels.remove(session_id) {
+                                let _ = tx.send(());
+                                info!("Shutdown signal sent to session {}", session_id);
+                            }
+                        }
+                        continue;
+                    }
 
-                            // Handle CreateTerminal command
-                            Command::CreateTerminal { ref id, ref host, port, x, y, width, height, ref terminal_type, ref border, ref title, closable, resizable } => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    if let Some(session) = sessions.get_mut(session_id) {
-                                        let term_type = TerminalType::from_str(terminal_type);
-                                        let border_style: crate::core::window::BorderStyle = match border.as_str() {
-                                            "none" => crate::core::window::BorderStyle::None,
-                                            "double" => crate::core::window::BorderStyle::Double,
-                                            _ => crate::core::window::BorderStyle::Single,
-                                        };
+                    // Handle CreateTerminal command
+                    Command::CreateTerminal { ref id, ref host, port, ref transport, ref command, x, y, width, height, ref terminal_type, ref encoding, ref border, ref title, closable, resizable } => {
+                        let endpoint = match transport.as_deref().map(Transport::parse) {
+                            Some(None) => Err(format!("Unknown transport: {}", transport.as_deref().unwrap_or_default())),
+                            Some(Some(transport)) if transport.is_local() && !local_terminals => {
+                                Err("Local terminals are disabled (start the server with --allow-local-terminals)".to_string())
+                            }
+                            transport => {
+                                let endpoint = Endpoint { transport: transport.flatten().unwrap_or_default(), host: host.clone(), port: *port, command: command.clone() };
+                                endpoint.validate().map(|_| endpoint)
+                            }
+                        };
+                        let endpoint = match endpoint {
+                            Ok(endpoint) => endpoint,
+                            Err(message) => {
+                                reply(Response::Error { message });
+                                continue;
+                            }
+                        };
+                        reply(require_session(&sessions, targeted.session.as_deref()));
+                        if let Some(session_id) = targeted.session.as_deref() {
+                            if let Some(session) = sessions.get_mut(session_id) {
+                                let term_type = TerminalType::from_str(terminal_type);
+                                let border_style: crate::core::window::BorderStyle = match border.as_str() {
+                                    "none" => crate::core::window::BorderStyle::None,
+                                    "double" => crate::core::window::BorderStyle::Double,
+                                    _ => crate::core::window::BorderStyle::Single,
+                                };
 
-                                        // Content size depends on border style
-                                        let (content_width, content_height) = if border_style == crate::core::window::BorderStyle::None {
-                                            (*width, *height)  // No border, content is full size
-                                        } else {
-                                            ((*width).saturating_sub(2), (*height).saturating_sub(2))  // Border takes 2 chars
-                                        };
+                                // Content size depends on border style
+                                let (content_width, content_height) = if border_style == crate::core::window::BorderStyle::None {
+                                    (*width, *height)  // No border, content is full size
+                                } else {
+                                    ((*width).saturating_sub(2), (*height).saturating_sub(2))  // Border takes 2 chars
+                                };
 
-                                        // Create terminal handle (spawns connection task in background)
-                                        let handle = create_terminal_handle(
-                                            id.clone(),
-                                            host.clone(),
-                                            *port,
-                                            content_width,
-                                            content_height,
-                                            term_type,
-                                            event_tx.clone(),
-                                        );
-
-                                        // Create window for terminal
-                                        let win = session.windows.create_window(id.clone(), *x, (*y).max(1), *width, *height);
-                                        win.set_border(border_style.into());
-                                        if let Some(t) = title {
-                                            win.set_title(t.clone());
-                                        } else if border_style != crate::core::window::BorderStyle::None {
-                                            win.set_title(format!("{}:{}", host, *port));
-                                        }
-                                        win.closable = *closable;
-                                        win.resizable = *resizable;
-                                        win.draggable = border_style != crate::core::window::BorderStyle::None;
-
-                                        // Store terminal handle
-                                        session.terminals.insert(id.clone(), handle);
-                                        session.focused_window = Some(id.clone());
-                                        info!("Terminal {} connecting to {}:{}", id, host, *port);
-                                    }
+                                // Create terminal handle (spawns connection task in background)
+                                let handle = create_terminal_handle(
+                                    session.id.clone(),
+                                    id.clone(),
+                                    endpoint.clone(),
+                                    content_width,
+                                    content_height,
+                                    term_type,
+                                    encoding.as_deref().map(TerminalEncoding::from_str).unwrap_or_default(),
+                                    event_tx.clone(),
+                                    session.game.clone(),
+                                );
+
+                                // Create window for terminal
+                                let win = session.windows.create_window(id.clone(), *x, (*y).max(1), *width, *height);
+                                win.set_border(border_style);
+                                if let Some(t) = title {
+                                    win.set_title(t.clone());
+                                } else if border_style != crate::core::window::BorderStyle::None {
+                                    win.set_title(endpoint.describe());
                                 }
-                                continue;
+                                win.closable = *closable;
+                                win.resizable = *resizable;
+                                win.draggable = border_style != crate::core::window::BorderStyle::None;
+
+                                // Store terminal handle
+                                session.terminals.insert(id.clone(), handle);
+                                session.focused_window = Some(id.clone());
+                                info!("Terminal {} connecting to {} ({:?})", id, endpoint.describe(), endpoint.transport);
                             }
+                        }
+                        continue;
+                    }
 
-                            // Handle CloseTerminal command
-                            Command::CloseTerminal { id } => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    if let Some(session) = sessions.get_mut(session_id) {
-                                        session.close_terminal(&id);
-                                        info!("Terminal {} closed", id);
-                                    }
-                                }
-                                continue;
+                    // Handle CloseTerminal command
+                    Command::CloseTerminal { id } => {
+                        let response = require_terminal(&sessions, targeted.session.as_deref(), id);
+                        let found = matches!(response, Response::Ok);
+                        reply(response);
+                        if let Some(session_id) = targeted.session.as_deref().filter(|_| found) {
+                            if let Some(session) = sessions.get_mut(session_id) {
+                                session.close_terminal(id);
+                                info!("Terminal {} closed", id);
                             }
+                        }
+                        continue;
+                    }
 
-                            // Handle TerminalInput command
-                            Command::TerminalInput { id, data } => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    if let Some(session) = sessions.get(session_id) {
-                                        let _ = session.send_terminal_input(&id, data.as_bytes()).await;
-                                    }
-                                }
-                                continue;
+                    // Handle TerminalInput command
+                    Command::TerminalInput { id, data } => {
+                        let mut response = require_terminal(&sessions, targeted.session.as_deref(), id);
+                        if let Some(session) = targeted.session.as_deref().and_then(|session_id| sessions.get(session_id)) {
+                            // (the connection task is gone once the remote hangs up)
+                            if matches!(response, Response::Ok) && !session.send_terminal_input(id, data.as_bytes()).await {
+                                response = Response::Error { message: format!("Terminal not connected: {}", id) };
                             }
+                        }
+                        reply(response);
+                        continue;
+                    }
 
-                            // Handle TerminalConfig command
-                            Command::TerminalConfig { id, local_echo, line_ending } => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    if let Some(session) = sessions.get_mut(session_id) {
-                                        if let Some(handle) = session.terminals.get_mut(id) {
-                                            if let Some(echo) = local_echo {
-                                                handle.local_echo = *echo;
-                                                debug!("Terminal {} local_echo set to {}", id, echo);
-                                            }
-                                            if let Some(ending) = line_ending {
-                                                handle.line_ending = ending.clone();
-                                                debug!("Terminal {} line_ending set to {}", id, ending);
-                                            }
+                    // Handle TerminalConfig command
+                    Command::TerminalConfig { id, local_echo, line_ending, encoding, log, record } => {
+                        let mut response = require_terminal(&sessions, targeted.session.as_deref(), id);
+                        if let Some(session_id) = targeted.session.as_deref() {
+                            if let Some(session) = sessions.get_mut(session_id) {
+                                if let Some(handle) = session.terminals.get_mut(id) {
+                                    if let Some(echo) = local_echo {
+                                        handle.local_echo = *echo;
+                                        debug!("Terminal {} local_echo set to {}", id, echo);
+                                    }
+                                    if let Some(ending) = line_ending {
+                                        handle.line_ending = ending.clone();
+                                        debug!("Terminal {} line_ending set to {}", id, ending);
+                                    }
+                                    if let Some(encoding) = encoding {
+                                        handle.terminal.write().await.encoding = TerminalEncoding::from_str(encoding);
+                                        debug!("Terminal {} encoding set to {}", id, encoding);
+                                    }
+                                    if log.is_some() || record.is_some() {
+                                        let (cols, rows) = {
+                                            let terminal = handle.terminal.read().await;
+                                            (terminal.width, terminal.height)
+                                        };
+                                        let mut recording = handle.recording.lock().unwrap_or_else(|e| e.into_inner());
+                                        match recording.configure(record_dir.as_deref(), log.as_deref(), record.as_deref(), cols, rows, id) {
+                                            Ok(()) => info!("Terminal {} recording: log {:?}, asciicast {:?}", id, log, record),
+                                            Err(message) => response = Response::Error { message },
                                         }
                                     }
                                 }
-                                continue;
                             }
+                        }
+                        reply(response);
+                        continue;
+                    }
 
-                            // Handle ResizeTerminal command
-                            Command::ResizeTerminal { id, x, y, width, height, border, title, closable, resizable, draggable } => {
-                                if let Some(session_id) = targeted.session.as_deref() {
-                                    if let Some(session) = sessions.get_mut(session_id) {
-                                        // Calculate content size (window size minus border)
-                                        let border_style: crate::core::window::BorderStyle = match border.as_str() {
-                                            "none" => crate::core::window::BorderStyle::None,
-                                            "double" => crate::core::window::BorderStyle::Double,
-                                            _ => crate::core::window::BorderStyle::Single,
-                                        };
-                                        let (content_width, content_height) = if border_style == crate::core::window::BorderStyle::None {
-                                            (*width, *height)
-                                        } else {
-                                            (width.saturating_sub(2), height.saturating_sub(2))
-                                        };
+                    // Handle ScrollTerminal command
+                    Command::ScrollTerminal { id, lines, bottom } => {
+                        let session = targeted.session.as_deref().and_then(|session_id| sessions.get_mut(session_id));
+                        let response = match session {
+                            Some(session) => {
+                                // Scrolling to the bottom goes past any amount of history
+                                let lines = if *bottom { isize::MIN } else { *lines };
+                                match session.scroll_terminal(id, lines).await {
+                                    Some((offset, scrollback)) => Response::TerminalScroll { id: id.clone(), offset, scrollback },
+                                    None => Response::Error { message: format!("Terminal not found: {}", id) },
+                                }
+                            }
+                            None => require_session(&sessions, targeted.session.as_deref()),
+                        };
+                        reply(response);
+                        continue;
+                    }
 
-                                        // Resize the terminal emulator and send NAWS
-                                        if let Some(handle) = session.terminals.get_mut(id) {
-                                            // Resize terminal emulator buffer (use try_write to avoid blocking)
-                                            if let Ok(mut terminal) = handle.terminal.try_write() {
-                                                terminal.resize(content_width, content_height);
-                                                debug!("Terminal {} resized to {}x{}", id, content_width, content_height);
-                                            }
+                    // Handle GetScrollback command (always answered, like list_sessions)
+                    Command::GetScrollback { id, start, count } => {
+                        let handle = targeted.session.as_deref()
+                            .and_then(|session_id| sessions.get(session_id))
+                            .and_then(|session| session.terminals.get(id));
+                        let response = match handle {
+                            Some(handle) => Response::Scrollback {
+                                id: id.clone(),
+                                start: *start,
+                                lines: handle.terminal.read().await.scrollback_text(*start, *count),
+                            },
+                            None => require_terminal(&sessions, targeted.session.as_deref(), id),
+                        };
+                        let _ = reply_tx.send((request_id.clone(), response));
+                        continue;
+                    }
 
-                                            // Send NAWS (window size) to remote
-                                            let w = content_width as u16;
-                                            let h = content_height as u16;
-                                            let naws = vec![
-                                                255, 250, 31,  // IAC SB NAWS
-                                                (w >> 8) as u8, (w & 0xff) as u8,
-                                                (h >> 8) as u8, (h & 0xff) as u8,
-                                                255, 240  // IAC SE
-                                            ];
-                                            let _ = handle.input_tx.try_send(naws);
-                                        }
+                    // Handle ResizeTerminal command
+                    Command::ResizeTerminal { id, x, y, width, height, border, title, closable, resizable, draggable } => {
+                        let response = require_terminal(&sessions, targeted.session.as_deref(), id);
+                        let found = matches!(response, Response::Ok);
+                        reply(response);
+                        if let Some(session_id) = targeted.session.as_deref().filter(|_| found) {
+                            if let Some(session) = sessions.get_mut(session_id) {
+                                // Calculate content size (window size minus border)
+                                let border_style: crate::core::window::BorderStyle = match border.as_str() {
+                                    "none" => crate::core::window::BorderStyle::None,
+                                    "double" => crate::core::window::BorderStyle::Double,
+                                    _ => crate::core::window::BorderStyle::Single,
+                                };
+                                let (content_width, content_height) = if border_style == crate::core::window::BorderStyle::None {
+                                    (*width, *height)
+                                } else {
+                                    (width.saturating_sub(2), height.saturating_sub(2))
+                                };
 
-                                        // Update the window
-                                        if let Some(win) = session.windows.get_mut(id) {
-                                            win.x = *x;
-                                            win.y = (*y).max(1);  // Protect menu bar
-                                            win.resize(*width, *height);
-                                            win.set_border(border_style);
-                                            if let Some(t) = title {
-                                                win.set_title(t.clone());
-                                            } else if border_style != crate::core::window::BorderStyle::None {
-                                                // Keep existing title for bordered windows
-                                            } else {
-                                                win.set_title(String::new());
-                                            }
-                                            win.closable = *closable;
-                                            win.resizable = *resizable;
-                                            win.draggable = *draggable;
-                                            win.dirty = true;
-                                        }
-                                        info!("Terminal {} resized to {}x{} at ({},{})", id, width, height, x, y);
+                                // Resize the terminal emulator and send NAWS
+                                if let Some(handle) = session.terminals.get_mut(id) {
+                                    // Resize terminal emulator buffer (use try_write to avoid blocking)
+                                    if let Ok(mut terminal) = handle.terminal.try_write() {
+                                        terminal.resize(content_width, content_height);
+                                        debug!("Terminal {} resized to {}x{}", id, content_width, content_height);
+                                    }
+
+                                    // Send NAWS (window size) to remote
+                                    let _ = handle.resize_tx.try_send((content_width as u16, content_height as u16));
+                                    if let Ok(mut recording) = handle.recording.lock() {
+                                        recording.resize(content_width, content_height);
                                     }
                                 }
-                                continue;
-                            }
 
-                            _ => {} // Other commands handled below
+                                // Update the window
+                                if let Some(win) = session.windows.get_mut(id) {
+                                    win.x = *x;
+                                    win.y = (*y).max(1);  // Protect menu bar
+                                    win.resize(*width, *height);
+                                    win.set_border(border_style);
+                                    if let Some(t) = title {
+                                        win.set_title(t.clone());
+                                    } else if border_style != crate::core::window::BorderStyle::None {
+                                        // Keep existing title for bordered windows
+                                    } else {
+                                        win.set_title(String::new());
+                                    }
+                                    win.closable = *closable;
+                                    win.resizable = *resizable;
+                                    win.draggable = *draggable;
+                                    win.dirty = true;
+                                }
+                                info!("Terminal {} resized to {}x{} at ({},{})", id, width, height, x, y);
+                            }
                         }
+                        continue;
+                    }
 
-                        // Route command based on session field
-                        let target_session = targeted.session.as_deref();
+                    _ => {} // Other commands handled below
+                }
 
-                        match target_session {
-                            // Broadcast to all sessions (None or "*")
-                            None | Some("*") => {
-                                for (_, session) in sessions.iter_mut() {
-                                    let _response = session.process_command(targeted.command.clone()).await;
-                                }
-                            }
+                // Route command based on session field
+                let target_session = targeted.session.as_deref();
 
-                            // Send to specific session only
-                            Some(session_id) => {
-                                if let Some(session) = sessions.get_mut(session_id) {
-                                    let _response = session.process_command(targeted.command.clone()).await;
-                                } else {
-                                    debug!("Target session not found: {}", session_id);
-                                }
-                            }
+                let response = match target_session {
+                    // Broadcast to all sessions (None or "*")
+                    None | Some("*") => {
+                        let mut responses = Vec::new();
+                        for (_, session) in sessions.iter_mut().filter(|(id, s)| scope.allows(id) && s.available_to(&game_id)) {
+                            responses.push(session.process_command(targeted.command.clone()).await);
                         }
+                        combine_responses(responses)
                     }
-                    Err(e) => {
-                        error!("Parse error: {}", e);
+
+                    // Send to specific session only
+                    Some(session_id) => {
+                        if let Some(session) = sessions.get_mut(session_id) {
+                            session.process_command(targeted.command.clone()).await
+                        } else {
+                            debug!("Target session not found: {}", session_id);
+                            session_not_found(session_id)
+                        }
+                    }
+                };
+
+                // Flushed windows may be mirrored into other sessions
+                if matches!(targeted.command, Command::Flush { .. }) {
+                    let flushed: Vec<String> = match target_session {
+                        None | Some("*") => sessions.values()
+                            .filter(|s| scope.allows(&s.id) && s.available_to(&game_id))
+                            .map(|s| s.id.clone())
+                            .collect(),
+                        Some(session_id) => vec![session_id.to_string()],
+                    };
+                    for source in flushed {
+                        propagate_window_shares(&mut sessions, &source).await;
+                        propagate_display_shares(&mut sessions, &source).await;
                     }
                 }
+
+                reply(response);
             }
-            Err(e) => {
-                error!("Game read error: {}", e);
-                break;
+            Err((e, request_id)) => {
+                error!("Parse error: {}", e);
+                // Always reported, so games without request IDs see bad commands too
+                let _ = reply_tx.send((request_id, Response::Error {
+                    message: format!("Parse error: {}", e),
+                }));
             }
         }
     }
@@ -1198,18 +2273,81 @@ async fn handle_game_connection(
     // They'll keep working and the next game that connects will pick them up
     info!("Game disconnected - clients remain connected, waiting for new game");
 
+    // Its players go back to the lobby, or to every game if there's none
+    {
+        let mut sessions = sessions.write().await;
+        let mut registry = games.write().await;
+        registry.names.remove(&game_id);
+        if registry.lobby.as_deref() == Some(game_id.as_str()) {
+            registry.lobby = None;
+        }
+        for session in sessions.values_mut().filter(|s| s.game().as_deref() == Some(game_id.as_str())) {
+            session.set_game(registry.lobby.clone());
+            let _ = event_tx.send(GameEvent::new(session.game(), Response::ClientConnect { session: session.id.clone() }));
+        }
+    }
+
     writer_handle.abort();
 }
 
-/// Handle a client connection (telnet player)
-async fn handle_client_connection(
-    socket: TcpStream,
+/// Handle a client connection (telnet or WebSocket player)
+#[allow(clippy::too_many_arguments)]
+async fn handle_client_connection<S>(
+    socket: S,
     addr: String,
+    protocol: ClientProtocol,
+    options: ClientOptions,
     sessions: Arc<RwLock<HashMap<String, ClientSession>>>,
     shutdown_channels: Arc<RwLock<HashMap<String, oneshot::Sender<()>>>>,
-    event_tx: broadcast::Sender<Response>,
-) {
-    let session_id = format!("session_{}", addr.replace(":", "_").replace(".", "_"));
+    event_tx: broadcast::Sender<GameEvent>,
+    games: Arc<RwLock<GameRegistry>>,
+    resume_grace: Duration,
+    record_sessions: Option<(PathBuf, RecordFormats)>,
+)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    // Create output channel
+    let (output_tx, mut output_rx) = mpsc::channel::<Vec<u8>>(100);
+
+    // Channel for telnet negotiation replies (sent as-is, unlike output)
+    let (telnet_tx, mut telnet_rx) = mpsc::channel::<Vec<u8>>(16);
+
+    // Resume a detached session if the client has its token
+    let resumed = match &options.resume_token {
+        Some(token) => resume_session(&mut *sessions.write().await, token, output_tx.clone(), options.renderer.clone(), &event_tx),
+        None => None,
+    };
+    let is_resumed = resumed.is_some();
+
+    // Otherwise create a session, attached to the lobby game if there is one
+    let mut session_id = match resumed {
+        Some(session_id) => {
+            info!("Client {} resumed session {}", addr, session_id);
+            session_id
+        }
+        None => {
+            let session_id = format!("session_{}", addr.replace(":", "_").replace(".", "_"));
+            let mut session = ClientSession::new(session_id.clone(), addr.clone(), output_tx, 80, 24);
+            if options.renderer.is_some() {
+                session.client_renderer = options.renderer;
+                session.renderer = session.select_renderer(80, 24);
+            }
+            if let Some((dir, formats)) = &record_sessions {
+                if let Err(e) = session.start_recording(dir, *formats) {
+                    error!("Can't record session {}: {}", session_id, e);
+                }
+            }
+            session.set_game(games.read().await.lobby.clone());
+            let game = session.game();
+            let mut sessions = sessions.write().await;
+            sessions.insert(session_id.clone(), session);
+
+            // Notify games of new client
+            let _ = event_tx.send(GameEvent::new(game, Response::ClientConnect { session: session_id.clone() }));
+            session_id
+        }
+    };
 
     // Create shutdown channel for this session
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
@@ -1218,40 +2356,42 @@ async fn handle_client_connection(
         channels.insert(session_id.clone(), shutdown_tx);
     }
 
-    // Create output channel
-    let (output_tx, mut output_rx) = mpsc::channel::<String>(100);
-
-    // Notify games of new client
-    let _ = event_tx.send(Response::ClientConnect { session: session_id.clone() });
-
-    // Create session
-    {
-        let session = ClientSession::new(session_id.clone(), addr.clone(), output_tx, 80, 24);
-        let mut sessions = sessions.write().await;
-        sessions.insert(session_id.clone(), session);
-    }
-
-    let (reader, mut writer) = socket.into_split();
+    let (reader, mut writer) = tokio::io::split(socket);
 
     // Send telnet negotiation to enable raw mode (suppress local echo)
-    if let Err(e) = writer.write_all(&telnet_raw_mode()).await {
-        error!("Failed to send telnet negotiation: {}", e);
-        return;
+    let mut client_telnet = ClientTelnet::new();
+    if protocol == ClientProtocol::Telnet {
+        if let Err(e) = writer.write_all(&client_telnet.start()).await {
+            error!("Failed to send telnet negotiation: {}", e);
+            return;
+        }
+        let _ = writer.flush().await;
     }
-    let _ = writer.flush().await;
 
-    // Initialize display
+    // Initialize display (a resumed session is redrawn as it was)
     {
         let mut sessions = sessions.write().await;
         if let Some(session) = sessions.get_mut(&session_id) {
-            let _ = session.init().await;
+            if is_resumed {
+                session.restore_display().await;
+            } else {
+                let _ = session.init().await;
+            }
         }
     }
 
-    // Task to send output to client
+    // Task to send output (and telnet replies) to client
     let write_handle = tokio::spawn(async move {
-        while let Some(output) = output_rx.recv().await {
-            if let Err(e) = writer.write_all(output.as_bytes()).await {
+        loop {
+            let data = tokio::select! {
+                Some(output) = output_rx.recv() => match protocol {
+                    ClientProtocol::Telnet => escape_iac(output),
+                    ClientProtocol::WebSocket => output,
+                },
+                Some(reply) = telnet_rx.recv() => reply,
+                else => break,
+            };
+            if let Err(e) = writer.write_all(&data).await {
                 error!("Client write error: {}", e);
                 break;
             }
@@ -1271,11 +2411,15 @@ async fn handle_client_connection(
     let mut flush_interval = tokio::time::interval(std::time::Duration::from_millis(30));
     flush_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+    // Whether the session was shut down (rather than the connection dropping)
+    let mut shut_down = false;
+
     loop {
         tokio::select! {
             // Check for shutdown signal
             _ = &mut shutdown_rx => {
                 info!("Client {} shutdown requested", session_id);
+                shut_down = true;
                 break;
             }
             // Auto-flush terminals periodically
@@ -1286,10 +2430,10 @@ async fn handle_client_connection(
                     if !session.terminals.is_empty() {
                         session.sync_terminals_to_windows().await;
                         session.windows.composite();
-                        let output = session.renderer.render(&session.windows.display, false);
+                        let output = session.render_display(false).await;
                         session.windows.display.mark_all_clean();
                         session.windows.mark_all_clean();
-                        let _ = session.output_tx.send(output).await;
+                        let _ = session.send_output(output).await;
                     }
                 }
             }
@@ -1301,8 +2445,45 @@ async fn handle_client_connection(
                         break;
                     }
                     Ok(n) => {
-                        // Filter out telnet protocol commands
-                        let filtered = filter_telnet_commands(&buf[..n]);
+                        let filtered = match protocol {
+                            // WebSocket frames were already unwrapped
+                            ClientProtocol::WebSocket => buf[..n].to_vec(),
+                            ClientProtocol::Telnet => {
+                                // Filter out telnet protocol commands, answering negotiation
+                                let mut replies = Vec::new();
+                                let mut telnet_events = Vec::new();
+                                let filtered = client_telnet.process(&buf[..n], &mut replies, &mut telnet_events);
+                                if !replies.is_empty() {
+                                    let _ = telnet_tx.send(replies).await;
+                                }
+                                if !telnet_events.is_empty() {
+                                    let mut sessions = sessions.write().await;
+                                    if let Some(session) = sessions.get_mut(&session_id) {
+                                        for telnet_event in telnet_events {
+                                            match telnet_event {
+                                                ClientTelnetEvent::TerminalType(name) => {
+                                                    debug!("Client {} terminal type: {}", session_id, name);
+                                                    session.add_terminal_type(name).await;
+                                                }
+                                                ClientTelnetEvent::WindowSize(cols, rows) => {
+                                                    let Some((cols, rows)) = session.resize_to_client(cols, rows) else {
+                                                        continue;
+                                                    };
+                                                    info!("Client {} resized to {}x{}", session_id, cols, rows);
+                                                    let _ = event_tx.send(GameEvent::new(session.game(), Response::ClientResized {
+                                                        session: session_id.clone(),
+                                                        cols,
+                                                        rows,
+                                                        terminal_type: session.capabilities.terminal_type().map(String::from),
+                                                    }));
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                                filtered
+                            }
+                        };
                         if filtered.is_empty() {
                             continue;
                         }
@@ -1317,7 +2498,7 @@ async fn handle_client_connection(
                             // Check for console toggle (Ctrl+\ or F10)
                             let is_console_toggle = match &event {
                                 InputEvent::Char { char: ch } => ClientSession::is_console_toggle_char(*ch),
-                                InputEvent::Key { key } => *key == crate::input::Key::F10,
+                                InputEvent::Key { key, .. } => *key == crate::input::Key::F10,
                                 _ => false,
                             };
                             if is_console_toggle {
@@ -1327,9 +2508,9 @@ async fn handle_client_connection(
                                     session.draw_console().await;
                                     if !session.console_open {
                                         // Redraw screen when closing console
-                                        let _ = event_tx.send(Response::RefreshRequested {
+                                        let _ = event_tx.send(GameEvent::new(session.game(), Response::RefreshRequested {
                                             session: session_id.clone(),
-                                        });
+                                        }));
                                     }
                                 }
                                 continue;
@@ -1341,38 +2522,75 @@ async fn handle_client_connection(
                                 if let Some(session) = sessions.get_mut(&session_id) {
                                     if session.console_open {
                                         match &event {
-                                            InputEvent::Char { char: ch } => {
-                                                if *ch >= ' ' && *ch != '\x7f' {
-                                                    session.console_input.push(*ch);
-                                                    session.draw_console().await;
-                                                }
+                                            InputEvent::Char { char: ch } if *ch >= ' ' && *ch != '\x7f' => {
+                                                session.console_input.push(*ch);
+                                                session.draw_console().await;
                                             }
-                                            InputEvent::Key { key } => {
+                                            InputEvent::Key { key, .. } => {
                                                 match key {
                                                     crate::input::Key::Enter => {
-                                                        let (should_reset, should_close) = session.process_console_command();
+                                                        let action = session.process_console_command();
                                                         session.console_open = false;
 
-                                                        if should_reset {
-                                                            // Request game to refresh everything
-                                                            let _ = event_tx.send(Response::RefreshRequested {
-                                                                session: session_id.clone(),
-                                                            });
-                                                        }
-                                                        if should_close {
-                                                            // Trigger shutdown for this session
-                                                            drop(sessions);
-                                                            let mut channels = shutdown_channels.write().await;
-                                                            if let Some(tx) = channels.remove(&session_id) {
-                                                                let _ = tx.send(());
-                                                                info!("Console close command - disconnecting session {}", session_id);
+                                                        match action {
+                                                            ConsoleAction::Reset => {
+                                                                // Request game to refresh everything
+                                                                let _ = event_tx.send(GameEvent::new(session.game(), Response::RefreshRequested {
+                                                                    session: session_id.clone(),
+                                                                }));
+                                                            }
+                                                            ConsoleAction::Close => {
+                                                                // Trigger shutdown for this session
+                                                                drop(sessions);
+                                                                let mut channels = shutdown_channels.write().await;
+                                                                if let Some(tx) = channels.remove(&session_id) {
+                                                                    let _ = tx.send(());
+                                                                    info!("Console close command - disconnecting session {}", session_id);
+                                                                }
+                                                                continue;
                                                             }
-                                                            continue;
+                                                            ConsoleAction::Resume(token) => {
+                                                                let output_tx = session.output_tx.clone();
+                                                                let client_renderer = session.client_renderer.clone();
+                                                                let capabilities = session.capabilities.clone();
+                                                                let size = (session.windows.cols, session.windows.rows);
+                                                                if let Some(resumed_id) = resume_session(&mut sessions, &token, output_tx, client_renderer, &event_tx) {
+                                                                    // This connection's own session is no longer needed
+                                                                    remove_session(&mut sessions, &event_tx, &session_id).await;
+                                                                    {
+                                                                        let mut channels = shutdown_channels.write().await;
+                                                                        if let Some(tx) = channels.remove(&session_id) {
+                                                                            channels.insert(resumed_id.clone(), tx);
+                                                                        }
+                                                                    }
+                                                                    info!("Connection for {} resumed session {}", session_id, resumed_id);
+                                                                    session_id = resumed_id;
+
+                                                                    // The resumed session takes on this connection's terminal
+                                                                    if let Some(resumed) = sessions.get_mut(&session_id) {
+                                                                        resumed.capabilities = capabilities;
+                                                                        resumed.renderer = resumed.select_renderer(resumed.windows.cols, resumed.windows.rows);
+                                                                        if size != (resumed.windows.cols, resumed.windows.rows) {
+                                                                            resumed.resize(size.0, size.1);
+                                                                            let _ = event_tx.send(GameEvent::new(resumed.game(), Response::ClientResized {
+                                                                                session: session_id.clone(),
+                                                                                cols: size.0,
+                                                                                rows: size.1,
+                                                                                terminal_type: resumed.capabilities.terminal_type().map(String::from),
+                                                                            }));
+                                                                        }
+                                                                        resumed.restore_display().await;
+                                                                    }
+                                                                    continue;
+                                                                }
+                                                                info!("Session {} gave an unknown resume token", session_id);
+                                                            }
+                                                            ConsoleAction::None => {}
                                                         }
                                                         // Redraw screen
-                                                        let _ = event_tx.send(Response::RefreshRequested {
+                                                        let _ = event_tx.send(GameEvent::new(session_game(&sessions, &session_id), Response::RefreshRequested {
                                                             session: session_id.clone(),
-                                                        });
+                                                        }));
                                                     }
                                                     crate::input::Key::Backspace => {
                                                         session.console_input.pop();
@@ -1381,9 +2599,9 @@ async fn handle_client_connection(
                                                     crate::input::Key::Escape => {
                                                         session.console_open = false;
                                                         session.console_input.clear();
-                                                        let _ = event_tx.send(Response::RefreshRequested {
+                                                        let _ = event_tx.send(GameEvent::new(session.game(), Response::RefreshRequested {
                                                             session: session_id.clone(),
-                                                        });
+                                                        }));
                                                     }
                                                     _ => {}
                                                 }
@@ -1395,46 +2613,89 @@ async fn handle_client_connection(
                                 }
                             }
 
+                            // Spectators only watch
+                            if sessions.read().await.get(&session_id).is_some_and(|s| s.spectator && s.viewing_shared_display()) {
+                                continue;
+                            }
+
                             // Check if this is a mouse event that might interact with window chrome
                             if let InputEvent::Mouse { x, y, button, event: mouse_event_type, .. } = &event {
                                 let mut sessions = sessions.write().await;
+                                let mut forward = None;
                                 if let Some(session) = sessions.get_mut(&session_id) {
-                                    let (window_events, forward_to_game) = session.handle_mouse_event(
-                                        *x as usize,
-                                        *y as usize,
-                                        *button,
-                                        *mouse_event_type,
-                                    );
+                                    // The wheel over a terminal window goes to the remote program if it
+                                    // tracks the mouse, and otherwise scrolls the terminal's history
+                                    let wheel = matches!(button, MouseButton::WheelUp | MouseButton::WheelDown);
+                                    let wheel_used = wheel && (session.send_terminal_mouse(&event).await
+                                        || (*mouse_event_type == MouseEventType::Press
+                                            && session.wheel_terminal_at(*x as usize, *y as usize, *button).await));
+                                    let (window_events, mut forward_to_game) = if wheel_used {
+                                        (Vec::new(), false)
+                                    } else {
+                                        session.handle_mouse_event(*x as usize, *y as usize, *button, *mouse_event_type)
+                                    };
+                                    // Input on a terminal window's content goes to its remote program, if it wants it
+                                    if forward_to_game && !wheel && session.send_terminal_mouse(&event).await {
+                                        forward_to_game = false;
+                                    }
 
                                     // Emit any window events (WindowMoved, WindowResized, etc.)
                                     for window_event in window_events {
-                                        let _ = event_tx.send(window_event);
+                                        let _ = event_tx.send(GameEvent::new(session.game(), window_event));
                                     }
 
                                     // Auto-flush for live drag/resize feedback
                                     session.auto_flush().await;
 
                                     // Only forward to game if not consumed by window chrome
+                                    // (input on a mirrored window may belong to its source session)
                                     if forward_to_game {
-                                        let _ = event_tx.send(Response::Input {
-                                            session: session_id.clone(),
-                                            event,
-                                        });
+                                        forward = Some(session.mirror_input(&event).unwrap_or((session_id.clone(), event)));
                                     }
                                 }
+                                if let Some((input_session, event)) = forward {
+                                    let _ = event_tx.send(GameEvent::new(session_game(&sessions, &input_session), Response::Input {
+                                        session: input_session,
+                                        event,
+                                    }));
+                                }
+                                // Sessions watching this display see the drag too
+                                propagate_display_shares(&mut sessions, &session_id).await;
                             } else {
                                 // Check if there's a focused terminal to route input to
                                 let mut sent_to_terminal = false;
+                                let mut input_session = session_id.clone();
+                                let mut event = event;
                                 {
                                     let mut sessions_write = sessions.write().await;
                                     if let Some(session) = sessions_write.get_mut(&session_id) {
+                                        if let Some((source, routed)) = session.mirror_input(&event) {
+                                            input_session = source;
+                                            event = routed;
+                                        }
+                                        // Shift+PageUp/PageDown page through a focused terminal's history
+                                        let page = match &event {
+                                            InputEvent::Key { key: Key::PageUp, modifiers } if modifiers.shift => Some(1),
+                                            InputEvent::Key { key: Key::PageDown, modifiers } if modifiers.shift => Some(-1),
+                                            _ => None,
+                                        };
+                                        let mut snapped = false;
                                         if let Some(ref focused_id) = session.focused_window.clone() {
-                                            if let Some(handle) = session.terminals.get(focused_id) {
+                                            if let (Some(page), Some(handle)) = (page, session.terminals.get(focused_id)) {
+                                                let height = handle.terminal.read().await.height;
+                                                session.scroll_terminal(focused_id, page * height.saturating_sub(1).max(1) as isize).await;
+                                                session.auto_flush().await;
+                                                sent_to_terminal = true;
+                                            } else if let Some(handle) = session.terminals.get(focused_id) {
                                                 // Convert input event to bytes for terminal
-                                                let bytes = input_event_to_bytes(&event, &handle.line_ending);
+                                                let (application_cursor_keys, remote_echo) = {
+                                                    let terminal = handle.terminal.read().await;
+                                                    (terminal.modes.application_cursor_keys, terminal.remote_echo)
+                                                };
+                                                let bytes = input_event_to_bytes(&event, &handle.line_ending, application_cursor_keys);
                                                 if !bytes.is_empty() {
-                                                    // Handle local echo if enabled
-                                                    if handle.local_echo {
+                                                    // Handle local echo if enabled (unless the remote echoes for us)
+                                                    if handle.local_echo && !remote_echo {
                                                         // Feed the input to terminal emulator for local echo
                                                         let echo_bytes = match &event {
                                                             InputEvent::Char { char } => {
@@ -1442,7 +2703,7 @@ async fn handle_client_connection(
                                                                 let s = char.encode_utf8(&mut buf);
                                                                 s.as_bytes().to_vec()
                                                             }
-                                                            InputEvent::Key { key } => {
+                                                            InputEvent::Key { key, .. } => {
                                                                 use crate::input::Key;
                                                                 match key {
                                                                     Key::Enter => b"\r\n".to_vec(),
@@ -1458,20 +2719,27 @@ async fn handle_client_connection(
                                                         }
                                                     }
 
+                                                    // Typing returns the view to the live screen
+                                                    snapped = handle.terminal.write().await.snap_to_bottom();
                                                     let _ = handle.input_tx.send(bytes).await;
                                                     sent_to_terminal = true;
                                                 }
                                             }
                                         }
+                                        if snapped {
+                                            session.sync_terminals_to_windows().await;
+                                            session.auto_flush().await;
+                                        }
                                     }
                                 }
 
                                 // If not sent to terminal, forward to game
                                 if !sent_to_terminal {
-                                    let _ = event_tx.send(Response::Input {
-                                        session: session_id.clone(),
+                                    let game = session_game(&*sessions.read().await, &input_session);
+                                    let _ = event_tx.send(GameEvent::new(game, Response::Input {
+                                        session: input_session,
                                         event,
-                                    });
+                                    }));
                                 }
                             }
                         }
@@ -1485,13 +2753,30 @@ async fn handle_client_connection(
         }
     }
 
-    // Notify games of disconnect
-    let _ = event_tx.send(Response::ClientDisconnect { session: session_id.clone() });
-
-    // Cleanup
-    {
-        let mut sessions = sessions.write().await;
-        sessions.remove(&session_id);
+    // Cleanup: sessions that were shut down (or can't be resumed) go now,
+    // others wait a while to be resumed
+    let resumable = sessions.read().await.get(&session_id).is_some_and(|s| s.resume_token.is_some());
+    if shut_down || resume_grace.is_zero() || !resumable {
+        remove_session(&mut *sessions.write().await, &event_tx, &session_id).await;
+    } else {
+        let detached_at = Instant::now();
+        if let Some(session) = sessions.write().await.get_mut(&session_id) {
+            session.detached_at = Some(detached_at);
+        }
+        info!("Session {} detached, resumable for {}s", session_id, resume_grace.as_secs());
+
+        let sessions = sessions.clone();
+        let event_tx = event_tx.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(resume_grace).await;
+            let mut sessions = sessions.write().await;
+            // Unless it was resumed (and maybe detached again) in the meantime
+            if sessions.get(&session_id).is_some_and(|s| s.detached_at == Some(detached_at)) {
+                info!("Session {} was not resumed", session_id);
+                remove_session(&mut sessions, &event_tx, &session_id).await;
+            }
+        });
     }
     {
         let mut channels = shutdown_channels.write().await;
@@ -1501,18 +2786,171 @@ async fn handle_client_connection(
     write_handle.abort();
 }
 
+/// Attach a new connection to the detached session holding `token`
+/// (`client_renderer` is what the new connection asked for, if anything)
+/// Returns the session's ID; its game is sent `client_reconnect`
+fn resume_session(
+    sessions: &mut HashMap<String, ClientSession>,
+    token: &str,
+    output_tx: mpsc::Sender<Vec<u8>>,
+    client_renderer: Option<String>,
+    event_tx: &broadcast::Sender<GameEvent>,
+) -> Option<String> {
+    let session = sessions.values_mut().find(|s| {
+        s.detached_at.is_some() && s.resume_token.as_ref().is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()))
+    })?;
+    session.output_tx = output_tx;
+    // The new connection may read a different format than the old one
+    if client_renderer != session.client_renderer {
+        session.client_renderer = client_renderer;
+        session.renderer = session.select_renderer(session.windows.cols, session.windows.rows);
+    }
+    session.detached_at = None;
+    let _ = event_tx.send(GameEvent::new(session.game(), Response::ClientReconnect { session: session.id.clone() }));
+    Some(session.id.clone())
+}
+
+/// Remove a session for good, telling its game and the sessions it shared with
+async fn remove_session(sessions: &mut HashMap<String, ClientSession>, event_tx: &broadcast::Sender<GameEvent>, session_id: &str) {
+    let Some(removed) = sessions.remove(session_id) else {
+        return;
+    };
+    let _ = event_tx.send(GameEvent::new(removed.game(), Response::ClientDisconnect { session: session_id.to_string() }));
+
+    // Windows and displays this session shared can't be updated any more
+    for session in sessions.values_mut() {
+        if session.remove_window_mirrors(session_id, None) {
+            session.redraw().await;
+        }
+        session.display_shares_to.retain(|t| t != session_id);
+        session.stop_viewing_display(session_id).await;
+    }
+}
+
+/// Copy a session's windows into the sessions mirroring them, and redraw those sessions
+async fn propagate_window_shares(sessions: &mut HashMap<String, ClientSession>, source_id: &str) {
+    let mirrors: Vec<(String, String, String)> = sessions
+        .iter()
+        .flat_map(|(target_id, target)| {
+            target.window_mirrors.iter()
+                .filter(|m| m.source == source_id)
+                .map(move |m| (target_id.clone(), m.mirror_id.clone(), m.window_id.clone()))
+        })
+        .collect();
+
+    let mut redraw = Vec::new();
+    for (target_id, mirror_id, window_id) in mirrors {
+        let window = sessions.get(source_id).and_then(|s| s.windows.get(&window_id)).cloned();
+        if let Some(target) = sessions.get_mut(&target_id) {
+            target.update_window_mirror(&mirror_id, window);
+            if !redraw.contains(&target_id) {
+                redraw.push(target_id);
+            }
+        }
+    }
+
+    for target_id in redraw {
+        if let Some(target) = sessions.get_mut(&target_id) {
+            target.redraw().await;
+        }
+    }
+}
+
+fn session_not_found(session_id: &str) -> Response {
+    Response::Error { message: format!("Session not found: {}", session_id) }
+}
+
+/// Ok if every named session exists, else an error naming the first missing one
+fn sessions_exist(sessions: &HashMap<String, ClientSession>, ids: &[&String]) -> Response {
+    match ids.iter().find(|id| !sessions.contains_key(id.as_str())) {
+        Some(id) => session_not_found(id),
+        None => Response::Ok,
+    }
+}
+
+/// Where and how to record sessions, if at all
+fn session_recording(record_dir: &Option<PathBuf>, formats: RecordFormats) -> Option<(PathBuf, RecordFormats)> {
+    let dir = record_dir.clone()?;
+    (formats.log || formats.cast).then_some((dir, formats))
+}
+
+/// Reply for a command that only makes sense for one existing session
+fn require_session(sessions: &HashMap<String, ClientSession>, session: Option<&str>) -> Response {
+    match session {
+        Some(id) if sessions.contains_key(id) => Response::Ok,
+        Some(id) => session_not_found(id),
+        None => Response::Error { message: "This command needs a session".to_string() },
+    }
+}
+
+/// Reply for a command on one terminal: ok if the session has it
+fn require_terminal(sessions: &HashMap<String, ClientSession>, session: Option<&str>, id: &str) -> Response {
+    match require_session(sessions, session) {
+        Response::Ok if !session.and_then(|s| sessions.get(s)).is_some_and(|s| s.terminals.contains_key(id)) => {
+            Response::Error { message: format!("Terminal not found: {}", id) }
+        }
+        response => response,
+    }
+}
+
+/// One reply for a command broadcast to several sessions:
+/// the only response if there was one session, else the first error, else ok
+fn combine_responses(mut responses: Vec<Response>) -> Response {
+    if responses.len() == 1 {
+        return responses.remove(0);
+    }
+    responses.into_iter()
+        .find(|r| matches!(r, Response::Error { .. }))
+        .unwrap_or(Response::Ok)
+}
+
+/// Game a session is attached to
+fn session_game(sessions: &HashMap<String, ClientSession>, session_id: &str) -> Option<String> {
+    sessions.get(session_id).and_then(|s| s.game())
+}
+
+/// Send a session's display to the sessions watching it
+async fn propagate_display_shares(sessions: &mut HashMap<String, ClientSession>, source_id: &str) {
+    let Some(source) = sessions.get(source_id) else {
+        return;
+    };
+    if source.display_shares_to.is_empty() {
+        return;
+    }
+    let display = source.windows.display.clone();
+
+    for target_id in source.display_shares_to.clone() {
+        if let Some(target) = sessions.get_mut(&target_id) {
+            if target.display_shares_from.iter().any(|s| s == source_id) {
+                target.show_shared_display(&display).await;
+            }
+        }
+    }
+}
+
 /// Convert an input event to bytes for sending to a terminal
 /// line_ending: "cr" (default) sends CR only, "crlf" sends CR+LF, "lf" sends LF only (Ctrl+J)
-fn input_event_to_bytes(event: &InputEvent, line_ending: &str) -> Vec<u8> {
+/// application_cursor_keys: the remote program asked for `ESC O` arrows (DECCKM)
+fn input_event_to_bytes(event: &InputEvent, line_ending: &str, application_cursor_keys: bool) -> Vec<u8> {
     match event {
         InputEvent::Char { char } => {
             let mut buf = [0u8; 4];
             let s = char.encode_utf8(&mut buf);
             s.as_bytes().to_vec()
         }
-        InputEvent::Key { key } => {
-            use crate::input::Key;
+        InputEvent::Key { key, .. } => {
             match key {
+                Key::Up | Key::Down | Key::Right | Key::Left | Key::Home | Key::End if application_cursor_keys => {
+                    let code = match key {
+                        Key::Up => b'A',
+                        Key::Down => b'B',
+                        Key::Right => b'C',
+                        Key::Left => b'D',
+                        Key::Home => b'H',
+                        _ => b'F',
+                    };
+                    vec![0x1b, b'O', code]
+                }
                 Key::Up => b"\x1b[A".to_vec(),
                 Key::Down => b"\x1b[B".to_vec(),
                 Key::Right => b"\x1b[C".to_vec(),
@@ -1552,63 +2990,143 @@ fn input_event_to_bytes(event: &InputEvent, line_ending: &str) -> Vec<u8> {
     }
 }
 
+/// Encode a mouse event at a terminal cell the way the remote program asked for
+/// (None if it doesn't want this event)
+fn mouse_event_to_bytes(x: usize, y: usize, button: MouseButton, event: MouseEventType, modifiers: Modifiers, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let tracking = modes.mouse_tracking;
+    let wanted = match event {
+        MouseEventType::Press => tracking != MouseTracking::Off,
+        MouseEventType::Release => tracking >= MouseTracking::Normal,
+        MouseEventType::Drag => tracking >= MouseTracking::Button,
+        MouseEventType::Move => tracking == MouseTracking::Any,
+    };
+    let wheel = matches!(button, MouseButton::WheelUp | MouseButton::WheelDown);
+    if !wanted || (wheel && event == MouseEventType::Release) {
+        return None;
+    }
+
+    let mut code = match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+        MouseButton::WheelUp => 64,
+        MouseButton::WheelDown => 65,
+        MouseButton::None => 3,
+    };
+    if matches!(event, MouseEventType::Drag | MouseEventType::Move) {
+        code += 32;
+    }
+    // X10 mode doesn't report modifiers
+    if tracking != MouseTracking::X10 {
+        code += 4 * modifiers.shift as usize + 8 * modifiers.alt as usize + 16 * modifiers.ctrl as usize;
+    }
+
+    if modes.sgr_mouse {
+        let action = if event == MouseEventType::Release { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{};{};{}{}", code, x + 1, y + 1, action).into_bytes());
+    }
+    // The legacy encoding doesn't say which button was released
+    if event == MouseEventType::Release {
+        code = (code & !3) | 3;
+    }
+    // ...and can't reach past column or row 222 (223 would be sent as 0xFF,
+    // which is IAC to a telnet remote)
+    if x + 1 > 222 || y + 1 > 222 {
+        return None;
+    }
+    Some(vec![0x1b, b'[', b'M', 32 + code as u8, 33 + x as u8, 33 + y as u8])
+}
+
+/// Terminal types to report over telnet: the emulation, then MTTS capability bits
+fn telnet_terminal_types(terminal_type: TerminalType, encoding: TerminalEncoding) -> Vec<String> {
+    let (name, mut mtts) = match terminal_type {
+        TerminalType::Raw => return vec!["DUMB".to_string()],
+        TerminalType::Vt100 => ("VT100", telnet::MTTS_VT100),
+        TerminalType::Ansi => ("ANSI", telnet::MTTS_ANSI | telnet::MTTS_VT100),
+        TerminalType::Xterm => ("XTERM-256COLOR", telnet::MTTS_ANSI | telnet::MTTS_VT100),
+    };
+    if terminal_type != TerminalType::Vt100 {
+        mtts |= MTTS_256_COLORS | MTTS_TRUECOLOR | telnet::MTTS_MOUSE_TRACKING;
+    }
+    if encoding == TerminalEncoding::Utf8 {
+        mtts |= telnet::MTTS_UTF8;
+    }
+    vec![name.to_string(), format!("MTTS {}", mtts)]
+}
+
+/// Game a session is attached to right now
+fn owner(game: &SharedGame) -> Option<String> {
+    game.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
 /// Create a terminal handle and spawn connection task
 /// The connection happens in the background; events are sent on success/failure
+/// (to `game`, the game the owning session is attached to at the time)
+#[allow(clippy::too_many_arguments)]
 fn create_terminal_handle(
+    session: String,
     id: String,
-    host: String,
-    port: u16,
+    endpoint: Endpoint,
     width: usize,
     height: usize,
     terminal_type: TerminalType,
-    event_tx: broadcast::Sender<Response>,
+    encoding: TerminalEncoding,
+    event_tx: broadcast::Sender<GameEvent>,
+    game: SharedGame,
 ) -> TerminalHandle {
     // Create terminal emulator
-    let terminal = Arc::new(RwLock::new(Terminal::new(id.clone(), width, height, terminal_type)));
+    let mut terminal = Terminal::new(id.clone(), width, height, terminal_type);
+    terminal.encoding = encoding;
+    let terminal = Arc::new(RwLock::new(terminal));
+    let recording = Arc::new(std::sync::Mutex::new(Recording::default()));
 
     // Create channel for sending input to remote
     let (input_tx, mut input_rx) = mpsc::channel::<Vec<u8>>(100);
+    // Window size changes, passed on to the backend
+    let (resize_tx, mut resize_rx) = mpsc::channel::<(u16, u16)>(4);
 
     // Spawn connection task (connects in background)
     let terminal_clone = terminal.clone();
+    let recording_clone = recording.clone();
     let event_tx_clone = event_tx.clone();
     let id_clone = id.clone();
-    let host_clone = host.clone();
+    let host = endpoint.host.clone();
+    let port = endpoint.port;
 
     let task = tokio::spawn(async move {
         // Try to connect
-        let connect_result = TcpStream::connect(format!("{}:{}", host_clone, port)).await;
-        let stream = match connect_result {
-            Ok(s) => s,
+        let terminal_types = telnet_terminal_types(terminal_type, encoding);
+        let connect_result = transport::connect(&endpoint, terminal_types, width as u16, height as u16).await;
+        let connection = match connect_result {
+            Ok(c) => c,
             Err(e) => {
-                let _ = event_tx_clone.send(Response::TerminalError {
+                let _ = event_tx_clone.send(GameEvent::new(owner(&game), Response::TerminalError {
+                    session: session.clone(),
                     id: id_clone,
                     error: format!("Connection failed: {}", e),
-                });
+                }));
                 return;
             }
         };
 
-        let (mut reader, mut writer) = stream.into_split();
+        let transport::Connection { mut reader, mut writer, mut backend } = connection;
 
         // Send connected event
-        let _ = event_tx_clone.send(Response::TerminalConnected {
+        let _ = event_tx_clone.send(GameEvent::new(owner(&game), Response::TerminalConnected {
+            session: session.clone(),
             id: id_clone.clone(),
-            host: host_clone.clone(),
-            port,
-        });
-
-        // Send proactive telnet negotiation to announce our capabilities
-        // IAC WILL TERMINAL-TYPE (255 251 24) - we can send terminal type
-        // IAC WILL NAWS (255 251 31) - we can send window size
-        let telnet_init: &[u8] = &[
-            255, 251, 24,  // IAC WILL TERMINAL-TYPE
-            255, 251, 31,  // IAC WILL NAWS (window size)
-        ];
-        let _ = writer.write_all(telnet_init).await;
-        let _ = writer.flush().await;
+            host: if endpoint.transport == Transport::Pty { endpoint.describe() } else { endpoint.host.clone() },
+            port: endpoint.port,
+        }));
+
+        // Announce our terminal type and window size (telnet)
+        let greeting = backend.start();
+        if !greeting.is_empty() {
+            let _ = writer.write_all(&greeting).await;
+            let _ = writer.flush().await;
+        }
 
-        // Task to send input to remote (and handle telnet responses)
+        // Task to send input to remote (and handle protocol responses)
         let id_for_writer = id_clone.clone();
         let (telnet_tx, mut telnet_rx) = mpsc::channel::<Vec<u8>>(100);
 
@@ -1623,7 +3141,7 @@ fn create_terminal_handle(
                         let _ = writer.flush().await;
                     }
                     Some(data) = telnet_rx.recv() => {
-                        // Telnet protocol responses
+                        // Protocol responses
                         if let Err(e) = writer.write_all(&data).await {
                             error!("Terminal {} telnet write error: {}", id_for_writer, e);
                             break;
@@ -1637,128 +3155,69 @@ fn create_terminal_handle(
 
         // Read from remote and update terminal
         let mut buf = [0u8; 4096];
-        let mut telnet_state = TelnetState::Normal;
-        let mut telnet_cmd: u8 = 0;
-        let mut subneg_buffer: Vec<u8> = Vec::new();
 
         loop {
-            match reader.read(&mut buf).await {
+            let result = tokio::select! {
+                result = reader.read(&mut buf) => result,
+                Some((cols, rows)) = resize_rx.recv() => {
+                    // Telnet tells the remote once it has asked for NAWS; a pty just resizes
+                    let naws = backend.resize(cols, rows);
+                    if !naws.is_empty() {
+                        let _ = telnet_tx.send(naws).await;
+                    }
+                    continue;
+                }
+            };
+            match result {
                 Ok(0) => {
                     // Connection closed
-                    let _ = event_tx_clone.send(Response::TerminalDisconnected {
+                    let _ = event_tx_clone.send(GameEvent::new(owner(&game), Response::TerminalDisconnected {
+                        session: session.clone(),
                         id: id_clone.clone(),
-                        reason: "Connection closed".to_string(),
-                    });
+                        reason: backend.closed().await,
+                    }));
                     break;
                 }
                 Ok(n) => {
-                    // Filter telnet commands and process terminal data
-                    let mut filtered_data: Vec<u8> = Vec::new();
-
-                    for &byte in &buf[..n] {
-                        match telnet_state {
-                            TelnetState::Normal => {
-                                if byte == 255 {  // IAC
-                                    telnet_state = TelnetState::Iac;
-                                } else {
-                                    filtered_data.push(byte);
-                                }
-                            }
-                            TelnetState::Iac => {
-                                match byte {
-                                    255 => {
-                                        // Escaped IAC
-                                        filtered_data.push(255);
-                                        telnet_state = TelnetState::Normal;
-                                    }
-                                    250 => {
-                                        // SB - Subnegotiation Begin
-                                        telnet_state = TelnetState::Subneg;
-                                        subneg_buffer.clear();
-                                    }
-                                    251..=254 => {
-                                        // WILL/WONT/DO/DONT
-                                        telnet_cmd = byte;
-                                        telnet_state = TelnetState::Option;
-                                    }
-                                    _ => {
-                                        telnet_state = TelnetState::Normal;
-                                    }
-                                }
-                            }
-                            TelnetState::Option => {
-                                // Handle telnet option negotiation
-                                let option = byte;
-                                match (telnet_cmd, option) {
-                                    (253, 24) => {
-                                        // DO TERMINAL-TYPE - respond with WILL
-                                        let _ = telnet_tx.send(vec![255, 251, 24]).await;
-                                    }
-                                    (253, 31) => {
-                                        // DO NAWS - respond with WILL and send window size
-                                        let _ = telnet_tx.send(vec![255, 251, 31]).await;
-                                        // Send window size: IAC SB NAWS width_hi width_lo height_hi height_lo IAC SE
-                                        let w = width as u16;
-                                        let h = height as u16;
-                                        let _ = telnet_tx.send(vec![
-                                            255, 250, 31,  // IAC SB NAWS
-                                            (w >> 8) as u8, (w & 0xff) as u8,
-                                            (h >> 8) as u8, (h & 0xff) as u8,
-                                            255, 240  // IAC SE
-                                        ]).await;
-                                    }
-                                    _ => {}
-                                }
-                                telnet_state = TelnetState::Normal;
-                            }
-                            TelnetState::Subneg => {
-                                if byte == 255 {
-                                    telnet_state = TelnetState::SubnegIac;
-                                } else {
-                                    subneg_buffer.push(byte);
-                                }
-                            }
-                            TelnetState::SubnegIac => {
-                                if byte == 240 {  // SE - Subnegotiation End
-                                    // Process subnegotiation
-                                    if !subneg_buffer.is_empty() {
-                                        let option = subneg_buffer[0];
-                                        if option == 24 && subneg_buffer.len() > 1 && subneg_buffer[1] == 1 {
-                                            // TERMINAL-TYPE SEND - respond with terminal type
-                                            // IAC SB TERMINAL-TYPE IS ANSI IAC SE
-                                            let mut response = vec![255, 250, 24, 0];  // IAC SB TERMINAL-TYPE IS
-                                            response.extend_from_slice(b"ANSI");
-                                            response.extend_from_slice(&[255, 240]);  // IAC SE
-                                            let _ = telnet_tx.send(response).await;
-                                        }
-                                    }
-                                    telnet_state = TelnetState::Normal;
-                                } else if byte == 255 {
-                                    subneg_buffer.push(255);
-                                } else {
-                                    subneg_buffer.push(byte);
-                                    telnet_state = TelnetState::Subneg;
-                                }
-                            }
-                        }
+                    // Filter protocol (telnet commands), answering negotiation
+                    let mut replies = Vec::new();
+                    let mut terminal = terminal_clone.write().await;
+                    let filtered_data = backend.receive(&buf[..n], &mut replies, &mut terminal);
+                    if let Ok(mut recording) = recording_clone.lock() {
+                        // The log gets the stream as it came; the asciicast what was displayed
+                        recording.log(&buf[..n]);
+                        recording.output(&filtered_data, terminal.encoding);
+                    }
+                    if !replies.is_empty() {
+                        let _ = telnet_tx.send(replies).await;
                     }
 
                     // Process filtered data through terminal emulator
                     if !filtered_data.is_empty() {
-                        let mut terminal = terminal_clone.write().await;
+                        let title = terminal.title.clone();
                         terminal.process_data(&filtered_data);
 
                         // Drain response queue (e.g., cursor position reports for ANSI detection)
                         while let Some(response) = terminal.response_queue.pop_front() {
-                            let _ = telnet_tx.send(response).await;
+                            let _ = telnet_tx.send(backend.encode(response)).await;
+                        }
+
+                        // Let the game know the remote program retitled its window (OSC 0/2)
+                        if terminal.title != title {
+                            let _ = event_tx_clone.send(GameEvent::new(owner(&game), Response::TerminalTitleChanged {
+                                session: session.clone(),
+                                id: id_clone.clone(),
+                                title: terminal.title.clone().unwrap_or_default(),
+                            }));
                         }
                     }
                 }
                 Err(e) => {
-                    let _ = event_tx_clone.send(Response::TerminalDisconnected {
+                    let _ = event_tx_clone.send(GameEvent::new(owner(&game), Response::TerminalDisconnected {
+                        session: session.clone(),
                         id: id_clone.clone(),
                         reason: format!("Read error: {}", e),
-                    });
+                    }));
                     break;
                 }
             }
@@ -1770,10 +3229,634 @@ fn create_terminal_handle(
     TerminalHandle {
         terminal,
         input_tx,
+        resize_tx,
         abort_handle: task.abort_handle(),
         host,
         port,
         local_echo: false,
         line_ending: "cr".to_string(),
+        recording,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::CursorStyle;
+    use crate::telnet::{IAC, SB, SE, TERMINAL_TYPE, TTYPE_IS, TTYPE_SEND, WILL};
+
+    #[test]
+    fn test_client_telnet_ttype_cycle() {
+        let mut telnet = ClientTelnet::new();
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        let send = [IAC, SB, TERMINAL_TYPE, TTYPE_SEND, IAC, SE];
+
+        telnet.start();
+        telnet.process(&[IAC, WILL, TERMINAL_TYPE], &mut replies, &mut events);
+        assert_eq!(replies, send);
+
+        // MTTS clients report name, terminal type, then capability bits
+        let mut is = |name: &str, replies: &mut Vec<u8>, events: &mut Vec<ClientTelnetEvent>| {
+            let mut msg = vec![IAC, SB, TERMINAL_TYPE, TTYPE_IS];
+            msg.extend_from_slice(name.as_bytes());
+            msg.extend_from_slice(&[IAC, SE]);
+            replies.clear();
+            telnet.process(&msg, replies, events);
+        };
+        is("MUDLET", &mut replies, &mut events);
+        assert_eq!(replies, send);
+        is("XTERM-256COLOR", &mut replies, &mut events);
+        assert_eq!(replies, send);
+        is("MTTS 265", &mut replies, &mut events);
+        assert!(replies.is_empty());
+
+        let mut caps = ClientCapabilities::default();
+        for event in events {
+            if let ClientTelnetEvent::TerminalType(name) = event {
+                caps.add_terminal_type(name);
+            }
+        }
+        assert_eq!(caps.terminal_type(), Some("MUDLET"));
+        assert_eq!(caps.mtts, Some(265));
+        assert_eq!(caps.color_depth(), ColorDepth::TrueColor);
+    }
+
+    #[test]
+    fn test_oversized_naws_is_clamped() {
+        let mut telnet = ClientTelnet::new();
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        telnet.process(&[IAC, SB, telnet::NAWS, 255, 255, 255, 255, 255, 255, 255, 255, IAC, SE], &mut replies, &mut events);
+        let Some(ClientTelnetEvent::WindowSize(cols, rows)) = events.pop() else {
+            panic!("No window size: {:?}", events);
+        };
+        assert_eq!((cols, rows), (65535, 65535));
+
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        assert_eq!(session.resize_to_client(cols, rows), Some((MAX_CLIENT_COLS, MAX_CLIENT_ROWS)));
+        assert_eq!((session.windows.cols, session.windows.rows), (MAX_CLIENT_COLS, MAX_CLIENT_ROWS));
+        assert_eq!(session.resize_to_client(cols, rows), None);
+        assert_eq!(session.resize_to_client(0, 24), None);
+    }
+
+    #[test]
+    fn test_window_mirror_placement_and_input() {
+        let (tx, _rx) = mpsc::channel(4);
+        let mut source = ClientSession::new("a".into(), "test".into(), tx.clone(), 80, 24);
+        let mut target = ClientSession::new("b".into(), "test".into(), tx, 80, 24);
+
+        source.windows.create_window("map", 10, 5, 20, 8);
+        target.window_mirrors.push(WindowMirror::new("a".into(), "map".into(), Some(40), None, true));
+        target.update_window_mirror("map@a", source.windows.get("map").cloned());
+
+        let mirror = target.windows.get("map@a").unwrap();
+        assert_eq!((mirror.x, mirror.y), (40, 5));
+        assert!(!mirror.closable && !mirror.resizable);
+
+        // A click on the mirror is reported at the same spot in the source window
+        let click = InputEvent::Mouse {
+            x: 42,
+            y: 7,
+            button: MouseButton::Left,
+            event: MouseEventType::Press,
+            modifiers: Default::default(),
+        };
+        match target.mirror_input(&click) {
+            Some((session, InputEvent::Mouse { x, y, .. })) => {
+                assert_eq!(session, "a");
+                assert_eq!((x, y), (12, 7));
+            }
+            other => panic!("Not routed: {:?}", other),
+        }
+
+        // Moving the source window doesn't move the mirror once placed
+        source.windows.get_mut("map").unwrap().x = 0;
+        target.update_window_mirror("map@a", source.windows.get("map").cloned());
+        assert_eq!(target.windows.get("map@a").unwrap().x, 40);
+
+        assert!(target.remove_window_mirrors("a", None));
+        assert!(target.windows.get("map@a").is_none());
+        assert!(target.mirror_input(&click).is_none());
+    }
+
+    #[test]
+    fn test_sessions_attached_to_games() {
+        let mut registry = GameRegistry::default();
+        registry.names.insert("game_1".into(), "lobby".into());
+        registry.names.insert("game_2".into(), "chess".into());
+        assert_eq!(registry.find("chess"), Some("game_2"));
+        assert_eq!(registry.find("go"), None);
+
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        // Unattached sessions are open to every game
+        assert!(session.available_to("game_1") && session.available_to("game_2"));
+        session.set_game(Some("game_2".into()));
+        assert!(!session.available_to("game_1"));
+        assert!(session.available_to("game_2"));
+    }
+
+    #[test]
+    fn test_client_options_from_path() {
+        assert_eq!(ClientOptions::from_path("/"), ClientOptions::default());
+        let options = ClientOptions::from_path("/cells/?resume=00ff&x=1");
+        assert_eq!(options.renderer.as_deref(), Some("cells"));
+        assert_eq!(options.resume_token.as_deref(), Some("00ff"));
+        assert_eq!(ClientOptions::from_path("/?resume=").resume_token, None);
+    }
+
+    #[test]
+    fn test_resume_takes_new_connection_renderer() {
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx.clone(), 80, 24);
+        let token = session.resume_token.clone().unwrap();
+        assert_eq!(token.len(), 32);
+        assert_ne!(Some(&token), new_resume_token().as_ref());
+        session.detached_at = Some(Instant::now());
+        let mut sessions = HashMap::from([("a".to_string(), session)]);
+        let (event_tx, _event_rx) = broadcast::channel(4);
+
+        assert_eq!(resume_session(&mut sessions, "0123", tx.clone(), None, &event_tx), None);
+        assert_eq!(resume_session(&mut sessions, &token, tx, Some("cells".into()), &event_tx).as_deref(), Some("a"));
+        assert_eq!(sessions["a"].renderer.name(), "cells");
+    }
+
+    #[tokio::test]
+    async fn test_console_goes_through_renderer() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        session.client_renderer = Some("cells".into());
+        session.renderer = session.select_renderer(80, 24);
+        session.toggle_console();
+        session.console_input = "résumé".into();
+        session.draw_console().await;
+
+        let output = String::from_utf8(rx.recv().await.unwrap()).unwrap();
+        assert!(!output.contains('\x1b'), "{:?}", output);
+        let message: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(message["type"], "cells");
+        assert!(output.contains("APU Console"));
+
+        // Closing the console and redrawing puts back what was under it
+        session.toggle_console();
+        session.redraw().await;
+        let output = String::from_utf8(rx.recv().await.unwrap()).unwrap();
+        assert!(!output.contains("APU Console") && output.contains("\"runs\""), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn test_init_refuses_charset_without_ansi() {
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        let init = |renderer: Option<&str>, charset: &str| Command::Init {
+            cols: None,
+            rows: None,
+            color_depth: None,
+            renderer: renderer.map(String::from),
+            charset: Some(charset.into()),
+        };
+
+        for charset in ["petscii", "atascii"] {
+            match session.process_command(init(None, charset)).await {
+                Response::Error { message } => assert!(message.contains(charset) && message.contains("ansi-ibm"), "{}", message),
+                other => panic!("Expected an error, got {:?}", other),
+            }
+            assert_eq!(session.charset, Charset::Utf8);
+            assert_eq!(session.renderer.name(), "ansi-ibm");
+        }
+
+        assert!(matches!(session.process_command(init(None, "cp437")).await, Response::Info { .. }));
+        assert_eq!(session.charset, Charset::Cp437);
+        assert!(matches!(session.process_command(init(Some("cells"), "petscii")).await, Response::Info { .. }));
+        assert_eq!(session.charset, Charset::Petscii);
+    }
+
+    #[tokio::test]
+    async fn test_session_cast_only_of_text() {
+        let dir = std::env::temp_dir().join(format!("apu-session-cast-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let formats = RecordFormats { log: true, cast: true };
+        let (tx, mut rx) = mpsc::channel(4);
+
+        // A /cells session gets its raw log but no asciicast
+        let mut cells = ClientSession::new("cells".into(), "test".into(), tx.clone(), 80, 24);
+        cells.client_renderer = Some("cells".into());
+        cells.renderer = cells.select_renderer(80, 24);
+        cells.start_recording(&dir, formats).unwrap();
+        assert!(!cells.recording.lock().unwrap().is_casting());
+
+        // Switching to a renderer whose output isn't text stops the asciicast
+        let mut session = ClientSession::new("ansi".into(), "test".into(), tx, 80, 24);
+        session.start_recording(&dir, formats).unwrap();
+        assert!(session.recording.lock().unwrap().is_casting());
+        session.renderer_name = Some("cells".into());
+        session.renderer = session.select_renderer(80, 24);
+        session.send_output(b"{}".to_vec()).await.unwrap();
+        rx.recv().await.unwrap();
+        assert!(!session.recording.lock().unwrap().is_casting());
+        assert!(session.recording.lock().unwrap().is_active());
+
+        session.recording.lock().unwrap().finish();
+        cells.recording.lock().unwrap().finish();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_application_cursor_keys() {
+        let up = InputEvent::key(Key::Up);
+        assert_eq!(input_event_to_bytes(&up, "cr", false), b"\x1b[A");
+        assert_eq!(input_event_to_bytes(&up, "cr", true), b"\x1bOA");
+        assert_eq!(input_event_to_bytes(&InputEvent::key(Key::End), "cr", true), b"\x1bOF");
+        // Only the cursor keys change
+        assert_eq!(input_event_to_bytes(&InputEvent::key(Key::PageUp), "cr", true), b"\x1b[5~");
+    }
+
+    #[test]
+    fn test_terminal_mouse_reports() {
+        let mut modes = TerminalModes::default();
+        let none = Modifiers::default();
+        let press = |modes: &TerminalModes| mouse_event_to_bytes(4, 2, MouseButton::Left, MouseEventType::Press, none, modes);
+        assert_eq!(press(&modes), None);
+
+        modes.mouse_tracking = MouseTracking::Normal;
+        assert_eq!(press(&modes).unwrap(), b"\x1b[M\x20\x25\x23");
+        let release = mouse_event_to_bytes(4, 2, MouseButton::Left, MouseEventType::Release, none, &modes);
+        assert_eq!(release.unwrap(), b"\x1b[M\x23\x25\x23");
+        // Motion isn't wanted until ?1002
+        assert_eq!(mouse_event_to_bytes(5, 2, MouseButton::Left, MouseEventType::Drag, none, &modes), None);
+        // Coordinates never come out as IAC
+        assert_eq!(mouse_event_to_bytes(221, 0, MouseButton::Left, MouseEventType::Press, none, &modes).unwrap(), b"\x1b[M\x20\xfe\x21");
+        assert_eq!(mouse_event_to_bytes(222, 0, MouseButton::Left, MouseEventType::Press, none, &modes), None);
+
+        modes.mouse_tracking = MouseTracking::Button;
+        modes.sgr_mouse = true;
+        let ctrl = Modifiers { ctrl: true, ..none };
+        assert_eq!(mouse_event_to_bytes(5, 2, MouseButton::Left, MouseEventType::Drag, ctrl, &modes).unwrap(), b"\x1b[<48;6;3M");
+        assert_eq!(mouse_event_to_bytes(4, 2, MouseButton::Right, MouseEventType::Release, none, &modes).unwrap(), b"\x1b[<2;5;3m");
+        assert_eq!(mouse_event_to_bytes(0, 0, MouseButton::WheelUp, MouseEventType::Press, none, &modes).unwrap(), b"\x1b[<64;1;1M");
+    }
+
+    #[tokio::test]
+    async fn test_focused_terminal_cursor() {
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        session.windows.create_window("bbs", 10, 5, 20, 8);
+        let (ox, oy) = session.windows.get("bbs").unwrap().content_offset();
+        let (handle, _input_rx) = test_terminal_handle(18, 6);
+        let terminal = handle.terminal.clone();
+        terminal.write().await.process_data(b"login: \x1b[4 q");
+        session.terminals.insert("bbs".into(), handle);
+
+        // Only shown while the terminal window has focus
+        assert_eq!(session.terminal_cursor().await, None);
+        session.focused_window = Some("bbs".into());
+        let cursor = CursorState { x: 10 + ox + 7, y: 5 + oy, style: CursorStyle::SteadyUnderline };
+        assert_eq!(session.terminal_cursor().await, Some(cursor));
+        session.windows.composite();
+        let output = session.render_display(false).await;
+        assert!(output.ends_with(b"\x1b[4 q\x1b[?25h"));
+
+        // Hidden when the program asks, or when another window covers it
+        terminal.write().await.process_data(b"\x1b[?25l");
+        assert_eq!(session.render_display(false).await, b"\x1b[?25l");
+        terminal.write().await.process_data(b"\x1b[?25h");
+        assert_eq!(session.terminal_cursor().await, Some(cursor));
+        session.windows.create_window("popup", 0, 0, 40, 12);
+        assert_eq!(session.terminal_cursor().await, None);
+    }
+
+    /// A terminal handle with no connection behind it
+    /// (and the receiving end of its input)
+    fn test_terminal_handle(width: usize, height: usize) -> (TerminalHandle, mpsc::Receiver<Vec<u8>>) {
+        let (input_tx, input_rx) = mpsc::channel(1);
+        let (resize_tx, _resize_rx) = mpsc::channel(1);
+        let handle = TerminalHandle {
+            terminal: Arc::new(RwLock::new(Terminal::new("bbs".into(), width, height, TerminalType::Ansi))),
+            input_tx,
+            resize_tx,
+            abort_handle: tokio::spawn(async {}).abort_handle(),
+            host: "localhost".into(),
+            port: 23,
+            local_echo: false,
+            line_ending: "cr".into(),
+            recording: Default::default(),
+        };
+        (handle, input_rx)
+    }
+
+    #[tokio::test]
+    async fn test_game_connection_over_any_stream() {
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&test_sessions(&[]), &[], &Arc::default(), &event_tx);
+        writer.write_all(b"{\"cmd\": \"list_sessions\"}\n").await.unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim(), r#"{"type":"sessions","sessions":[]}"#);
+    }
+
+    type GameReader = BufReader<tokio::io::ReadHalf<tokio::io::DuplexStream>>;
+    type GameWriter = tokio::io::WriteHalf<tokio::io::DuplexStream>;
+
+    /// Connect a game (`game_test`) to these sessions, taking events from
+    /// `event_tx` (no auth tokens: no authentication)
+    fn connect_game(
+        sessions: &Arc<RwLock<HashMap<String, ClientSession>>>,
+        auth_tokens: &[&str],
+        games: &Arc<RwLock<GameRegistry>>,
+        event_tx: &broadcast::Sender<GameEvent>,
+    ) -> (GameReader, GameWriter) {
+        let (game, apu) = tokio::io::duplex(65536);
+        let context = GameContext {
+            sessions: sessions.clone(),
+            shutdown_channels: Arc::new(RwLock::new(HashMap::new())),
+            event_tx: event_tx.clone(),
+            auth_tokens: Arc::new(auth_tokens.iter().map(|t| AuthToken::parse(t).unwrap()).collect()),
+            games: games.clone(),
+            local_terminals: false,
+            record_dir: None,
+        };
+        tokio::spawn(handle_game_connection(apu, "game_test".to_string(), event_tx.subscribe(), context));
+        let (reader, writer) = tokio::io::split(game);
+        (BufReader::new(reader), writer)
+    }
+
+    /// Send a command tagged with a request_id and return the reply to it
+    async fn request(reader: &mut GameReader, writer: &mut GameWriter, command: &str) -> Value {
+        let mut command: Value = serde_json::from_str(command).unwrap();
+        command["request_id"] = Value::from("r");
+        writer.write_all(format!("{}\n", command).as_bytes()).await.unwrap();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            let reply: Value = serde_json::from_str(&line).unwrap();
+            if reply["request_id"] == "r" {
+                return reply;
+            }
+        }
+    }
+
+    fn test_sessions(ids: &[&str]) -> Arc<RwLock<HashMap<String, ClientSession>>> {
+        let (tx, _rx) = mpsc::channel(64);
+        Arc::new(RwLock::new(ids.iter()
+            .map(|id| (id.to_string(), ClientSession::new(id.to_string(), "test".into(), tx.clone(), 80, 24)))
+            .collect()))
+    }
+
+    #[tokio::test]
+    async fn test_share_window_needs_source_window() {
+        let sessions = test_sessions(&["a", "b"]);
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&sessions, &[], &Arc::default(), &event_tx);
+
+        let share = r#"{"cmd": "share_window", "window_id": "map", "source": "a", "target": "b"}"#;
+        let reply = request(&mut reader, &mut writer, share).await;
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["message"], "Window not found: map");
+        assert!(sessions.read().await["b"].window_mirrors.is_empty());
+
+        sessions.write().await.get_mut("a").unwrap().windows.create_window("map", 10, 5, 20, 8);
+        assert_eq!(request(&mut reader, &mut writer, share).await["type"], "ok");
+        assert!(sessions.read().await["b"].windows.get("map@a").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_share_display_needs_other_session() {
+        let (tx, mut rx) = mpsc::channel(64);
+        let sessions = Arc::new(RwLock::new(HashMap::from([("a".to_string(), ClientSession::new("a".into(), "test".into(), tx, 80, 24))])));
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&sessions, &[], &Arc::default(), &event_tx);
+
+        let reply = request(&mut reader, &mut writer, r#"{"cmd": "share_display", "source": "x", "target": "a"}"#).await;
+        assert_eq!(reply["message"], "Session not found: x");
+        let reply = request(&mut reader, &mut writer, r#"{"cmd": "share_display", "source": "a", "target": "a"}"#).await;
+        assert_eq!(reply["type"], "error");
+        assert!(!sessions.read().await["a"].viewing_shared_display());
+
+        // The target still shows its own screen
+        request(&mut reader, &mut writer, r#"{"cmd": "create_window", "session": "a", "id": "main", "x": 0, "y": 0, "width": 20, "height": 5}"#).await;
+        request(&mut reader, &mut writer, r#"{"cmd": "print", "session": "a", "window": "main", "x": 1, "y": 1, "text": "Hello"}"#).await;
+        let reply = request(&mut reader, &mut writer, r#"{"cmd": "flush", "session": "a"}"#).await;
+        assert!(reply["data"].as_str().unwrap().contains("Hello"), "{}", reply);
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_terminal_commands_need_terminal() {
+        let sessions = test_sessions(&["a"]);
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&sessions, &[], &Arc::default(), &event_tx);
+
+        let commands = [
+            r#"{"cmd": "close_terminal", "session": "a", "id": "bbs"}"#,
+            r#"{"cmd": "terminal_input", "session": "a", "id": "bbs", "data": "hi"}"#,
+            r#"{"cmd": "terminal_config", "session": "a", "id": "bbs", "local_echo": true}"#,
+            r#"{"cmd": "resize_terminal", "session": "a", "id": "bbs", "x": 0, "y": 1, "width": 40, "height": 10}"#,
+        ];
+        for command in commands {
+            let reply = request(&mut reader, &mut writer, command).await;
+            assert_eq!(reply["message"], "Terminal not found: bbs", "{}", command);
+        }
+        let reply = request(&mut reader, &mut writer, r#"{"cmd": "close_terminal", "session": "x", "id": "bbs"}"#).await;
+        assert_eq!(reply["message"], "Session not found: x");
+
+        // A terminal whose connection has ended can't take input
+        let (handle, input_rx) = test_terminal_handle(38, 8);
+        drop(input_rx);
+        sessions.write().await.get_mut("a").unwrap().terminals.insert("bbs".into(), handle);
+        let reply = request(&mut reader, &mut writer, commands[1]).await;
+        assert_eq!(reply["message"], "Terminal not connected: bbs");
+        for command in [commands[2], commands[3], commands[0]] {
+            assert_eq!(request(&mut reader, &mut writer, command).await["type"], "ok", "{}", command);
+        }
+        assert!(sessions.read().await["a"].terminals.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shared_input_routing() {
+        let sessions = test_sessions(&["s"]);
+        sessions.write().await.get_mut("s").unwrap().windows.create_window("map", 10, 5, 20, 8);
+        let (event_tx, mut events) = broadcast::channel(64);
+        let (client, apu) = tokio::io::duplex(65536);
+        tokio::spawn(handle_client_connection(
+            apu,
+            "t".to_string(),
+            ClientProtocol::WebSocket,
+            ClientOptions::default(),
+            sessions.clone(),
+            Arc::new(RwLock::new(HashMap::new())),
+            event_tx,
+            Arc::new(RwLock::new(GameRegistry::default())),
+            Duration::ZERO,
+            None,
+        ));
+        let (_client_reader, mut client) = tokio::io::split(client);
+        assert!(matches!(events.recv().await.unwrap().response, Response::ClientConnect { .. }));
+
+        // Watching "s" as a spectator: typing goes nowhere (opening and closing
+        // the console then asks for a refresh, so there's an event to wait for)
+        {
+            let mut sessions = sessions.write().await;
+            let target = sessions.get_mut("session_t").unwrap();
+            target.display_shares_from.push("s".into());
+            target.spectator = true;
+        }
+        client.write_all(b"x\x1c\x1c").await.unwrap();
+        assert!(matches!(events.recv().await.unwrap().response, Response::RefreshRequested { .. }));
+
+        // Not a spectator: keys on a mirror that routes input come from its source
+        let mut sessions_write = sessions.write().await;
+        let source_map = sessions_write["s"].windows.get("map").cloned();
+        let target = sessions_write.get_mut("session_t").unwrap();
+        target.spectator = false;
+        target.window_mirrors.push(WindowMirror::new("s".into(), "map".into(), None, None, true));
+        target.update_window_mirror("map@s", source_map);
+        target.focused_window = Some("map@s".into());
+        drop(sessions_write);
+        client.write_all(b"k").await.unwrap();
+        match events.recv().await.unwrap().response {
+            Response::Input { session, event: InputEvent::Char { char: 'k' } } => assert_eq!(session, "s"),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // ...and from the viewer's own session otherwise
+        sessions.write().await.get_mut("session_t").unwrap().window_mirrors[0].route_input = false;
+        client.write_all(b"j").await.unwrap();
+        match events.recv().await.unwrap().response {
+            Response::Input { session, event: InputEvent::Char { char: 'j' } } => assert_eq!(session, "session_t"),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shares_end_with_source_session() {
+        let sessions = test_sessions(&["s", "t"]);
+        let mut sessions = sessions.write().await;
+        sessions.get_mut("s").unwrap().windows.create_window("map", 10, 5, 20, 8);
+        sessions.get_mut("s").unwrap().display_shares_to.push("t".into());
+        let source_map = sessions["s"].windows.get("map").cloned();
+        let target = sessions.get_mut("t").unwrap();
+        target.display_shares_from.push("s".into());
+        target.spectator = true;
+        target.window_mirrors.push(WindowMirror::new("s".into(), "map".into(), None, None, true));
+        target.update_window_mirror("map@s", source_map);
+        target.focused_window = Some("map@s".into());
+
+        let (event_tx, _events) = broadcast::channel(4);
+        remove_session(&mut sessions, &event_tx, "s").await;
+        let target = &sessions["t"];
+        assert!(target.window_mirrors.is_empty());
+        assert!(target.windows.get("map@s").is_none());
+        assert_eq!(target.focused_window, None);
+        assert!(!target.viewing_shared_display() && !target.spectator);
+    }
+
+    #[tokio::test]
+    async fn test_terminal_events_follow_handoff() {
+        let (event_tx, mut events) = broadcast::channel(16);
+        let (tx, _rx) = mpsc::channel(4);
+        let mut session = ClientSession::new("a".into(), "test".into(), tx, 80, 24);
+        session.set_game(Some("game_test".into()));
+
+        // A terminal on a TCP server we control
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint { transport: Transport::Raw, host: "127.0.0.1".into(), port: listener.local_addr().unwrap().port(), command: Vec::new() };
+        let handle = create_terminal_handle("a".into(), "bbs".into(), endpoint, 20, 5, TerminalType::Ansi, TerminalEncoding::Utf8, event_tx.clone(), session.game.clone());
+        session.terminals.insert("bbs".into(), handle);
+        let (mut remote, _) = listener.accept().await.unwrap();
+        let connected = events.recv().await.unwrap();
+        assert!(matches!(connected.response, Response::TerminalConnected { .. }));
+        assert_eq!(connected.game.as_deref(), Some("game_test"));
+
+        let sessions = Arc::new(RwLock::new(HashMap::from([("a".to_string(), session)])));
+        let games = Arc::new(RwLock::new(GameRegistry::default()));
+        games.write().await.names.insert("game_2".into(), "chess".into());
+        let (mut reader, mut writer) = connect_game(&sessions, &[], &games, &event_tx);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        writer.write_all(b"{\"cmd\": \"handoff\", \"session\": \"a\", \"game\": \"chess\", \"request_id\": 1}\n").await.unwrap();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).await.unwrap();
+            if line.contains("\"request_id\":1") {
+                break;
+            }
+        }
+        assert!(line.contains("\"type\":\"ok\""), "{}", line);
+
+        // The remote's title and hangup go to the game the session is with now
+        remote.write_all(b"\x1b]2;chess\x07").await.unwrap();
+        drop(remote);
+        let mut terminal_events = Vec::new();
+        while terminal_events.len() < 2 {
+            let event = events.recv().await.unwrap();
+            if matches!(event.response, Response::TerminalTitleChanged { .. } | Response::TerminalDisconnected { .. }) {
+                terminal_events.push(event);
+            }
+        }
+        assert!(terminal_events.iter().all(|e| e.game.as_deref() == Some("game_2")), "{:?}", terminal_events);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_game_events() {
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&test_sessions(&[]), &["lan=session_a*"], &Arc::default(), &event_tx);
+        let mut line = String::new();
+        writer.write_all(b"{\"cmd\": \"auth\", \"token\": \"lan\"}\n").await.unwrap();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim(), r#"{"type":"ok"}"#);
+
+        // Another session's window and terminal events are dropped
+        let events = [
+            Response::WindowMoved { session: "session_b".into(), id: "main".into(), x: 1, y: 2 },
+            Response::WindowCloseRequested { session: "session_b".into(), id: "main".into() },
+            Response::TerminalConnected { session: "session_b".into(), id: "bbs".into(), host: "localhost".into(), port: 23 },
+            Response::TerminalTitleChanged { session: "session_b".into(), id: "bbs".into(), title: "secret".into() },
+            Response::Ok,
+            Response::WindowFocused { session: "session_a".into(), id: "main".into() },
+        ];
+        for response in events {
+            event_tx.send(GameEvent::new(None, response)).unwrap();
+        }
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line.trim(), r#"{"type":"window_focused","session":"session_a","id":"main"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_only_unscoped_game_is_lobby() {
+        let (event_tx, _) = broadcast::channel(16);
+        let games = Arc::new(RwLock::new(GameRegistry::default()));
+        let tokens = ["lan=session_10_0_*", "admin"];
+        let register = r#"{"cmd": "register_game", "name": "lobby", "lobby": true}"#;
+
+        let (mut reader, mut writer) = connect_game(&test_sessions(&[]), &tokens, &games, &event_tx);
+        assert_eq!(request(&mut reader, &mut writer, r#"{"cmd": "auth", "token": "lan"}"#).await["type"], "ok");
+        let reply = request(&mut reader, &mut writer, register).await;
+        assert_eq!(reply["type"], "error");
+        assert_eq!(games.read().await.lobby, None);
+        assert!(games.read().await.names.is_empty());
+        // It can still register as an ordinary game
+        let reply = request(&mut reader, &mut writer, r#"{"cmd": "register_game", "name": "lan"}"#).await;
+        assert_eq!(reply["type"], "ok");
+
+        let (mut reader, mut writer) = connect_game(&test_sessions(&[]), &tokens, &games, &event_tx);
+        assert_eq!(request(&mut reader, &mut writer, r#"{"cmd": "auth", "token": "admin"}"#).await["type"], "ok");
+        assert_eq!(request(&mut reader, &mut writer, register).await["type"], "ok");
+        assert!(games.read().await.lobby.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_game_auth_line_too_long() {
+        let (event_tx, _) = broadcast::channel(16);
+        let (mut reader, mut writer) = connect_game(&test_sessions(&[]), &["s3cret"], &Arc::default(), &event_tx);
+
+        // The connection is closed without waiting for the end of the line
+        // (or for the auth timeout)
+        writer.write_all(&vec![b'a'; MAX_AUTH_LINE as usize + 100]).await.unwrap();
+        let mut rest = Vec::new();
+        let read = tokio::time::timeout(AUTH_TIMEOUT / 2, reader.read_to_end(&mut rest)).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
     }
 }
@@ -0,0 +1,719 @@
+//! Telnet Option Negotiation (RFC 854, RFC 1143)
+//!
+//! Shared by both ends APU speaks telnet on:
+//! - [`ClientTelnet`]: player connections, where APU is the server
+//! - [`RemoteTelnet`]: terminal windows, where APU is the client of a remote host
+//!
+//! [`Telnet`] strips commands from the byte stream (even when split across
+//! reads) and tracks each option on both sides with the Q method, so requests
+//! are answered exactly once and never loop. The role types decide which
+//! options to agree to and handle their subnegotiations (TTYPE, NAWS, CHARSET).
+
+use crate::terminal::TerminalEncoding;
+
+// Commands
+pub const IAC: u8 = 255; // Interpret As Command
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250; // Subnegotiation Begin
+pub const GA: u8 = 249; // Go Ahead
+pub const EOR_MARK: u8 = 239; // End of Record (once EOR is negotiated)
+pub const SE: u8 = 240; // Subnegotiation End
+
+// Options
+pub const BINARY: u8 = 0;
+pub const ECHO: u8 = 1;
+pub const SUPPRESS_GO_AHEAD: u8 = 3;
+pub const TERMINAL_TYPE: u8 = 24;
+pub const EOR: u8 = 25;
+pub const NAWS: u8 = 31;
+pub const LINEMODE: u8 = 34;
+pub const CHARSET: u8 = 42;
+
+// TERMINAL-TYPE subnegotiation commands (RFC 1091)
+pub const TTYPE_IS: u8 = 0;
+pub const TTYPE_SEND: u8 = 1;
+
+// CHARSET subnegotiation commands (RFC 2066)
+pub const CHARSET_REQUEST: u8 = 1;
+pub const CHARSET_ACCEPTED: u8 = 2;
+pub const CHARSET_REJECTED: u8 = 3;
+
+// MTTS (Mud Terminal Type Standard) capability bits
+pub const MTTS_ANSI: u32 = 1;
+pub const MTTS_VT100: u32 = 2;
+pub const MTTS_UTF8: u32 = 4;
+pub const MTTS_256_COLORS: u32 = 8;
+pub const MTTS_MOUSE_TRACKING: u32 = 16;
+pub const MTTS_TRUECOLOR: u32 = 256;
+
+/// How many times to ask a player's client for another terminal type (MTTS cycles through three)
+const MAX_TTYPE_REQUESTS: usize = 4;
+
+/// Longest subnegotiation kept (anything longer is truncated)
+const MAX_SUBNEG_LEN: usize = 1024;
+
+/// Which end of the connection an option applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Our end (we send WILL/WONT, they send DO/DONT)
+    Local,
+    /// Their end (we send DO/DONT, they send WILL/WONT)
+    Remote,
+}
+
+impl Side {
+    /// Commands we send to enable / disable the option on this side
+    fn commands(self) -> (u8, u8) {
+        match self {
+            Side::Local => (WILL, WONT),
+            Side::Remote => (DO, DONT),
+        }
+    }
+}
+
+/// Something learned from the byte stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TelnetEvent {
+    /// An option was turned on
+    Enabled(Side, u8),
+    /// An option was turned off (or refused)
+    Disabled(Side, u8),
+    /// A complete subnegotiation: the option and its data (IACs unescaped)
+    Subnegotiation(u8, Vec<u8>),
+    /// The end of a prompt (GA, or EOR once negotiated)
+    Prompt,
+}
+
+/// Q method state of one option on one side (RFC 1143)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum QState {
+    #[default]
+    No,
+    Yes,
+    /// We asked to turn it off and are waiting for the answer
+    WantNo,
+    /// We asked to turn it on and are waiting for the answer
+    WantYes,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct OptionState {
+    state: QState,
+    /// Reverse the option again once the pending request is answered
+    opposite: bool,
+    /// Whether we agree to enable it when the other end asks
+    accepted: bool,
+}
+
+/// Parser state machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParseState {
+    Normal,
+    Iac,
+    /// Got IAC WILL/WONT/DO/DONT, waiting for the option
+    Option,
+    Subneg,
+    SubnegIac,
+}
+
+/// Telnet command parser and option negotiation
+pub struct Telnet {
+    state: ParseState,
+    cmd: u8,
+    subneg: Vec<u8>,
+    local: [OptionState; 256],
+    remote: [OptionState; 256],
+}
+
+impl Default for Telnet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Telnet {
+    pub fn new() -> Self {
+        Self {
+            state: ParseState::Normal,
+            cmd: 0,
+            subneg: Vec::new(),
+            local: [OptionState::default(); 256],
+            remote: [OptionState::default(); 256],
+        }
+    }
+
+    fn option(&mut self, side: Side, option: u8) -> &mut OptionState {
+        match side {
+            Side::Local => &mut self.local[option as usize],
+            Side::Remote => &mut self.remote[option as usize],
+        }
+    }
+
+    /// Agree to enable an option when the other end asks
+    pub fn accept(&mut self, side: Side, option: u8) {
+        self.option(side, option).accepted = true;
+    }
+
+    /// Whether an option is currently on
+    pub fn is_enabled(&self, side: Side, option: u8) -> bool {
+        let options = match side {
+            Side::Local => &self.local,
+            Side::Remote => &self.remote,
+        };
+        options[option as usize].state == QState::Yes
+    }
+
+    /// Ask for an option to be turned on (and agree to it from now on)
+    pub fn enable(&mut self, side: Side, option: u8, replies: &mut Vec<u8>) {
+        let (will, _) = side.commands();
+        let opt = self.option(side, option);
+        opt.accepted = true;
+        match (opt.state, opt.opposite) {
+            (QState::No, _) => {
+                opt.state = QState::WantYes;
+                replies.extend_from_slice(&[IAC, will, option]);
+            }
+            (QState::WantNo, false) => opt.opposite = true,
+            (QState::WantYes, true) => opt.opposite = false,
+            _ => {}
+        }
+    }
+
+    /// Ask for an option to be turned off (and refuse it from now on)
+    pub fn disable(&mut self, side: Side, option: u8, replies: &mut Vec<u8>) {
+        let (_, wont) = side.commands();
+        let opt = self.option(side, option);
+        opt.accepted = false;
+        match (opt.state, opt.opposite) {
+            (QState::Yes, _) => {
+                opt.state = QState::WantNo;
+                replies.extend_from_slice(&[IAC, wont, option]);
+            }
+            (QState::WantNo, true) => opt.opposite = false,
+            (QState::WantYes, false) => opt.opposite = true,
+            _ => {}
+        }
+    }
+
+    /// Handle the other end's WILL/DO (`on`) or WONT/DONT for an option on `side`
+    fn receive(&mut self, side: Side, option: u8, on: bool, replies: &mut Vec<u8>, events: &mut Vec<TelnetEvent>) {
+        let (will, wont) = side.commands();
+        let opt = self.option(side, option);
+        let (state, opposite, changed) = match (opt.state, opt.opposite, on) {
+            (QState::No, _, true) if opt.accepted => {
+                replies.extend_from_slice(&[IAC, will, option]);
+                (QState::Yes, false, Some(true))
+            }
+            (QState::No, _, true) => {
+                replies.extend_from_slice(&[IAC, wont, option]);
+                (QState::No, false, None)
+            }
+            (QState::Yes, _, false) => {
+                replies.extend_from_slice(&[IAC, wont, option]);
+                (QState::No, false, Some(false))
+            }
+            // Our request to turn it off was answered (a WILL/DO here is a protocol error)
+            (QState::WantNo, false, _) => (QState::No, false, Some(false)),
+            (QState::WantNo, true, true) => (QState::Yes, false, None),
+            (QState::WantNo, true, false) => {
+                // ...and we've since changed our mind
+                replies.extend_from_slice(&[IAC, will, option]);
+                (QState::WantYes, false, Some(false))
+            }
+            // Our request to turn it on was answered
+            (QState::WantYes, false, true) => (QState::Yes, false, Some(true)),
+            (QState::WantYes, true, true) => {
+                replies.extend_from_slice(&[IAC, wont, option]);
+                (QState::WantNo, false, Some(true))
+            }
+            (QState::WantYes, _, false) => (QState::No, false, Some(false)),
+            // Already in the state asked for
+            (state, opposite, _) => (state, opposite, None),
+        };
+        opt.state = state;
+        opt.opposite = opposite;
+        match changed {
+            Some(true) => events.push(TelnetEvent::Enabled(side, option)),
+            Some(false) => events.push(TelnetEvent::Disabled(side, option)),
+            None => {}
+        }
+    }
+
+    /// Process raw bytes from the other end
+    /// Returns the data bytes; negotiation replies and events are appended to `replies` and `events`
+    pub fn process(&mut self, data: &[u8], replies: &mut Vec<u8>, events: &mut Vec<TelnetEvent>) -> Vec<u8> {
+        let mut filtered = Vec::with_capacity(data.len());
+        for &byte in data {
+            match self.state {
+                ParseState::Normal => {
+                    if byte == IAC {
+                        self.state = ParseState::Iac;
+                    } else {
+                        filtered.push(byte);
+                    }
+                }
+                ParseState::Iac => {
+                    self.state = ParseState::Normal;
+                    match byte {
+                        IAC => {
+                            // Escaped IAC (255 255) = literal 255
+                            filtered.push(IAC);
+                        }
+                        SB => {
+                            self.subneg.clear();
+                            self.state = ParseState::Subneg;
+                        }
+                        WILL | WONT | DO | DONT => {
+                            self.cmd = byte;
+                            self.state = ParseState::Option;
+                        }
+                        GA | EOR_MARK => events.push(TelnetEvent::Prompt),
+                        _ => {
+                            // Other 2-byte command (NOP, AYT...)
+                        }
+                    }
+                }
+                ParseState::Option => {
+                    match self.cmd {
+                        WILL => self.receive(Side::Remote, byte, true, replies, events),
+                        WONT => self.receive(Side::Remote, byte, false, replies, events),
+                        DO => self.receive(Side::Local, byte, true, replies, events),
+                        _ => self.receive(Side::Local, byte, false, replies, events),
+                    }
+                    self.state = ParseState::Normal;
+                }
+                ParseState::Subneg => {
+                    if byte == IAC {
+                        self.state = ParseState::SubnegIac;
+                    } else if self.subneg.len() < MAX_SUBNEG_LEN {
+                        self.subneg.push(byte);
+                    }
+                }
+                ParseState::SubnegIac => {
+                    match byte {
+                        SE => {
+                            if let Some((&option, data)) = self.subneg.split_first() {
+                                events.push(TelnetEvent::Subnegotiation(option, data.to_vec()));
+                            }
+                            self.state = ParseState::Normal;
+                        }
+                        _ => {
+                            // IAC IAC is a literal 255; anything else is malformed, keep going
+                            if self.subneg.len() < MAX_SUBNEG_LEN {
+                                self.subneg.push(byte);
+                            }
+                            self.state = ParseState::Subneg;
+                        }
+                    }
+                }
+            }
+        }
+        filtered
+    }
+}
+
+/// Build IAC SB option data IAC SE (escaping IACs in the data)
+pub fn subnegotiation(option: u8, data: &[u8]) -> Vec<u8> {
+    let mut msg = vec![IAC, SB, option];
+    msg.extend(escape_iac(data.to_vec()));
+    msg.extend_from_slice(&[IAC, SE]);
+    msg
+}
+
+/// NAWS subnegotiation reporting a window size
+pub fn naws(cols: u16, rows: u16) -> Vec<u8> {
+    let [c1, c2] = cols.to_be_bytes();
+    let [r1, r2] = rows.to_be_bytes();
+    subnegotiation(NAWS, &[c1, c2, r1, r2])
+}
+
+/// Double any IAC bytes in output data (8-bit charsets like CP437 use 0xFF)
+pub fn escape_iac(data: Vec<u8>) -> Vec<u8> {
+    if !data.contains(&IAC) {
+        return data;
+    }
+    let mut escaped = Vec::with_capacity(data.len() + 8);
+    for byte in data {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+/// Information learned from a client's telnet negotiation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientTelnetEvent {
+    /// Client reported a terminal type (TTYPE IS)
+    TerminalType(String),
+    /// Client reported its window size (NAWS)
+    WindowSize(u16, u16),
+}
+
+/// Telnet for player connections (APU is the server)
+/// Puts the client in character-at-a-time mode without local echo and
+/// collects its terminal types and window size
+pub struct ClientTelnet {
+    telnet: Telnet,
+    /// Terminal type requests sent so far
+    ttype_requests: usize,
+    /// Last terminal type reported (a repeat ends the TTYPE cycle)
+    last_ttype: Option<String>,
+}
+
+impl Default for ClientTelnet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientTelnet {
+    pub fn new() -> Self {
+        let mut telnet = Telnet::new();
+        telnet.accept(Side::Local, BINARY);
+        telnet.accept(Side::Remote, BINARY);
+        Self {
+            telnet,
+            ttype_requests: 0,
+            last_ttype: None,
+        }
+    }
+
+    /// Negotiation to send when the client connects: we echo and don't need
+    /// go-aheads (so the client sends each key as typed), and we'd like its
+    /// terminal type and window size
+    pub fn start(&mut self) -> Vec<u8> {
+        let mut replies = Vec::new();
+        self.telnet.enable(Side::Local, ECHO, &mut replies);
+        self.telnet.enable(Side::Local, SUPPRESS_GO_AHEAD, &mut replies);
+        self.telnet.enable(Side::Remote, SUPPRESS_GO_AHEAD, &mut replies);
+        self.telnet.enable(Side::Remote, TERMINAL_TYPE, &mut replies);
+        self.telnet.enable(Side::Remote, NAWS, &mut replies);
+        replies
+    }
+
+    /// Process raw bytes from the client
+    /// Returns the data bytes; negotiation replies and events are appended to `replies` and `events`
+    pub fn process(&mut self, data: &[u8], replies: &mut Vec<u8>, events: &mut Vec<ClientTelnetEvent>) -> Vec<u8> {
+        let mut telnet_events = Vec::new();
+        let filtered = self.telnet.process(data, replies, &mut telnet_events);
+        for event in telnet_events {
+            match event {
+                TelnetEvent::Enabled(Side::Remote, TERMINAL_TYPE) => {
+                    // Client agreed to send its terminal type - ask for it
+                    self.request_ttype(replies);
+                }
+                TelnetEvent::Subnegotiation(option, data) => self.finish_subneg(option, &data, replies, events),
+                _ => {}
+            }
+        }
+        filtered
+    }
+
+    /// Send IAC SB TERMINAL-TYPE SEND IAC SE
+    fn request_ttype(&mut self, replies: &mut Vec<u8>) {
+        self.ttype_requests += 1;
+        replies.extend(subnegotiation(TERMINAL_TYPE, &[TTYPE_SEND]));
+    }
+
+    /// Handle a complete subnegotiation
+    fn finish_subneg(&mut self, option: u8, data: &[u8], replies: &mut Vec<u8>, events: &mut Vec<ClientTelnetEvent>) {
+        match option {
+            TERMINAL_TYPE if data.first() == Some(&TTYPE_IS) => {
+                let name = String::from_utf8_lossy(&data[1..]).trim().to_string();
+                // Clients cycle through their types and repeat the last one when done
+                if name.is_empty() || self.last_ttype.as_deref() == Some(name.as_str()) {
+                    return;
+                }
+                self.last_ttype = Some(name.clone());
+                let is_mtts = name.starts_with("MTTS ");
+                events.push(ClientTelnetEvent::TerminalType(name));
+                if !is_mtts && self.ttype_requests < MAX_TTYPE_REQUESTS {
+                    self.request_ttype(replies);
+                }
+            }
+            NAWS if data.len() >= 4 => {
+                let cols = u16::from_be_bytes([data[0], data[1]]);
+                let rows = u16::from_be_bytes([data[2], data[3]]);
+                events.push(ClientTelnetEvent::WindowSize(cols, rows));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Something a remote host changed through telnet negotiation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteTelnetEvent {
+    /// The host started (true) or stopped echoing what's typed
+    Echo(bool),
+    /// The host agreed on a character set (CHARSET)
+    Charset(TerminalEncoding),
+}
+
+/// Telnet for terminal windows (APU is the client of a remote host)
+/// Agrees to what a host usually asks of a terminal, reports terminal types
+/// and the window size, and accepts UTF-8 or CP437 through CHARSET
+pub struct RemoteTelnet {
+    telnet: Telnet,
+    /// Terminal types to report, in order (RFC 1091 cycling)
+    terminal_types: Vec<String>,
+    /// Next terminal type to report
+    ttype_index: usize,
+    /// Window size reported via NAWS
+    window_size: (u16, u16),
+}
+
+impl RemoteTelnet {
+    pub fn new(terminal_types: Vec<String>, cols: u16, rows: u16) -> Self {
+        let mut telnet = Telnet::new();
+        for option in [BINARY, SUPPRESS_GO_AHEAD, TERMINAL_TYPE, NAWS, CHARSET] {
+            telnet.accept(Side::Local, option);
+        }
+        for option in [BINARY, ECHO, SUPPRESS_GO_AHEAD, EOR, CHARSET] {
+            telnet.accept(Side::Remote, option);
+        }
+        Self {
+            telnet,
+            terminal_types,
+            ttype_index: 0,
+            window_size: (cols, rows),
+        }
+    }
+
+    /// Negotiation to send once connected: offer our terminal type and window size
+    pub fn start(&mut self) -> Vec<u8> {
+        let mut replies = Vec::new();
+        self.telnet.enable(Side::Local, TERMINAL_TYPE, &mut replies);
+        self.telnet.enable(Side::Local, NAWS, &mut replies);
+        replies
+    }
+
+    /// Whether the host is echoing what's typed
+    pub fn remote_echo(&self) -> bool {
+        self.telnet.is_enabled(Side::Remote, ECHO)
+    }
+
+    /// Record a new window size, returning the NAWS update to send (if the host wants one)
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Vec<u8> {
+        self.window_size = (cols, rows);
+        if self.telnet.is_enabled(Side::Local, NAWS) {
+            naws(cols, rows)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Process raw bytes from the host
+    /// Returns the data bytes; negotiation replies and events are appended to `replies` and `events`
+    pub fn process(&mut self, data: &[u8], replies: &mut Vec<u8>, events: &mut Vec<RemoteTelnetEvent>) -> Vec<u8> {
+        let mut telnet_events = Vec::new();
+        let filtered = self.telnet.process(data, replies, &mut telnet_events);
+        for event in telnet_events {
+            match event {
+                TelnetEvent::Enabled(Side::Local, NAWS) => {
+                    let (cols, rows) = self.window_size;
+                    replies.extend(naws(cols, rows));
+                }
+                TelnetEvent::Enabled(Side::Remote, ECHO) => events.push(RemoteTelnetEvent::Echo(true)),
+                TelnetEvent::Disabled(Side::Remote, ECHO) => events.push(RemoteTelnetEvent::Echo(false)),
+                TelnetEvent::Subnegotiation(TERMINAL_TYPE, data) if data.first() == Some(&TTYPE_SEND) => {
+                    self.send_ttype(replies);
+                }
+                TelnetEvent::Subnegotiation(CHARSET, data) if data.first() == Some(&CHARSET_REQUEST) => {
+                    let (reply, encoding) = charset_reply(&data[1..]);
+                    replies.extend(subnegotiation(CHARSET, &reply));
+                    events.extend(encoding.map(RemoteTelnetEvent::Charset));
+                }
+                _ => {}
+            }
+        }
+        filtered
+    }
+
+    /// Send the next terminal type: each in turn, then the last one again to
+    /// mark the end, then start over (RFC 1091)
+    fn send_ttype(&mut self, replies: &mut Vec<u8>) {
+        let Some(last) = self.terminal_types.len().checked_sub(1) else {
+            return;
+        };
+        let index = self.ttype_index.min(last);
+        self.ttype_index = if self.ttype_index > last { 0 } else { self.ttype_index + 1 };
+        let mut data = vec![TTYPE_IS];
+        data.extend_from_slice(self.terminal_types[index].as_bytes());
+        replies.extend(subnegotiation(TERMINAL_TYPE, &data));
+    }
+}
+
+/// Answer a CHARSET REQUEST (`data` is the separator and the offered names):
+/// accept the first one we can decode
+fn charset_reply(data: &[u8]) -> (Vec<u8>, Option<TerminalEncoding>) {
+    // An optional translation table offer comes first; we don't take it
+    let data = data.strip_prefix(b"[TTABLE]\x01").unwrap_or(data);
+    if let Some((&separator, names)) = data.split_first() {
+        for name in names.split(|&b| b == separator) {
+            let encoding = match String::from_utf8_lossy(name).to_uppercase().as_str() {
+                "UTF-8" | "UTF8" => TerminalEncoding::Utf8,
+                "CP437" | "IBM437" | "IBM-437" => TerminalEncoding::Cp437,
+                _ => continue,
+            };
+            let mut reply = vec![CHARSET_ACCEPTED];
+            reply.extend_from_slice(name);
+            return (reply, Some(encoding));
+        }
+    }
+    (vec![CHARSET_REJECTED], None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_q_method() {
+        let mut telnet = Telnet::new();
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        telnet.accept(Side::Remote, ECHO);
+
+        // Accepted options are agreed to once; repeats aren't answered again
+        telnet.process(&[IAC, WILL, ECHO, IAC, WILL, ECHO], &mut replies, &mut events);
+        assert_eq!(replies, [IAC, DO, ECHO]);
+        assert_eq!(events, vec![TelnetEvent::Enabled(Side::Remote, ECHO)]);
+        assert!(telnet.is_enabled(Side::Remote, ECHO));
+
+        // Others are refused
+        replies.clear();
+        events.clear();
+        telnet.process(&[IAC, DO, LINEMODE], &mut replies, &mut events);
+        assert_eq!(replies, [IAC, WONT, LINEMODE]);
+        assert!(events.is_empty());
+
+        // Our own request is answered without a reply
+        replies.clear();
+        telnet.enable(Side::Local, NAWS, &mut replies);
+        telnet.enable(Side::Local, NAWS, &mut replies);
+        assert_eq!(replies, [IAC, WILL, NAWS]);
+        replies.clear();
+        telnet.process(&[IAC, DO, NAWS], &mut replies, &mut events);
+        assert!(replies.is_empty());
+        assert!(telnet.is_enabled(Side::Local, NAWS));
+
+        // Changing our mind while a request is pending queues the reversal
+        telnet.enable(Side::Remote, SUPPRESS_GO_AHEAD, &mut replies);
+        telnet.disable(Side::Remote, SUPPRESS_GO_AHEAD, &mut replies);
+        assert_eq!(replies, [IAC, DO, SUPPRESS_GO_AHEAD]);
+        replies.clear();
+        telnet.process(&[IAC, WILL, SUPPRESS_GO_AHEAD], &mut replies, &mut events);
+        assert_eq!(replies, [IAC, DONT, SUPPRESS_GO_AHEAD]);
+        replies.clear();
+        telnet.process(&[IAC, WONT, SUPPRESS_GO_AHEAD], &mut replies, &mut events);
+        assert!(replies.is_empty());
+        assert!(!telnet.is_enabled(Side::Remote, SUPPRESS_GO_AHEAD));
+
+        // The other end turning an option off is acknowledged
+        telnet.process(&[IAC, WONT, ECHO], &mut replies, &mut events);
+        assert_eq!(replies, [IAC, DONT, ECHO]);
+        assert_eq!(events.last(), Some(&TelnetEvent::Disabled(Side::Remote, ECHO)));
+    }
+
+    #[test]
+    fn test_client_telnet_strips_split_subnegotiation() {
+        let mut telnet = ClientTelnet::new();
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+
+        // NAWS 100x40 split across two reads, surrounded by data
+        let data = telnet.process(&[b'a', IAC, SB, NAWS, 0, 100], &mut replies, &mut events);
+        assert_eq!(data, b"a");
+        let data = telnet.process(&[0, 40, IAC, SE, b'b'], &mut replies, &mut events);
+        assert_eq!(data, b"b");
+        assert_eq!(events, vec![ClientTelnetEvent::WindowSize(100, 40)]);
+    }
+
+    #[test]
+    fn test_escape_iac() {
+        assert_eq!(escape_iac(b"plain".to_vec()), b"plain");
+        // CP437 non-breaking space is 0xFF
+        assert_eq!(escape_iac(vec![b'a', IAC, b'b']), vec![b'a', IAC, IAC, b'b']);
+    }
+
+    #[test]
+    fn test_client_telnet_start() {
+        let mut telnet = ClientTelnet::new();
+        assert_eq!(telnet.start(), vec![
+            IAC, WILL, ECHO,
+            IAC, WILL, SUPPRESS_GO_AHEAD,
+            IAC, DO, SUPPRESS_GO_AHEAD,
+            IAC, DO, TERMINAL_TYPE,
+            IAC, DO, NAWS,
+        ]);
+
+        // Agreement to what we asked for isn't answered again
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        telnet.process(&[IAC, DO, ECHO, IAC, DO, SUPPRESS_GO_AHEAD, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, WONT, NAWS], &mut replies, &mut events);
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn test_remote_telnet_negotiation() {
+        let types = vec!["XTERM-256COLOR".to_string(), "MTTS 271".to_string()];
+        let mut telnet = RemoteTelnet::new(types, 80, 24);
+        assert_eq!(telnet.start(), vec![IAC, WILL, TERMINAL_TYPE, IAC, WILL, NAWS]);
+
+        // A host that echoes and suppresses go-ahead (character mode) gets its answers
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+        let data = telnet.process(&[IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, DO, NAWS, b'>', IAC, EOR_MARK], &mut replies, &mut events);
+        assert_eq!(data, b">");
+        let mut expected = vec![IAC, DO, ECHO, IAC, DO, SUPPRESS_GO_AHEAD];
+        expected.extend(naws(80, 24));
+        assert_eq!(replies, expected);
+        assert_eq!(events, vec![RemoteTelnetEvent::Echo(true)]);
+        assert!(telnet.remote_echo());
+
+        // Later size changes go out as NAWS
+        assert_eq!(telnet.resize(100, 255), vec![IAC, SB, NAWS, 0, 100, 0, IAC, IAC, IAC, SE]);
+
+        // Terminal types cycle, repeating the last one to mark the end
+        let mut ttypes = Vec::new();
+        for _ in 0..4 {
+            replies.clear();
+            telnet.process(&[IAC, DO, TERMINAL_TYPE, IAC, SB, TERMINAL_TYPE, TTYPE_SEND, IAC, SE], &mut replies, &mut events);
+            let start = replies.iter().position(|&b| b == SB).unwrap() + 3;
+            ttypes.push(String::from_utf8_lossy(&replies[start..replies.len() - 2]).into_owned());
+        }
+        assert_eq!(ttypes, vec!["XTERM-256COLOR", "MTTS 271", "MTTS 271", "XTERM-256COLOR"]);
+    }
+
+    #[test]
+    fn test_remote_telnet_charset() {
+        let mut telnet = RemoteTelnet::new(vec!["ANSI".to_string()], 80, 24);
+        let mut replies = Vec::new();
+        let mut events = Vec::new();
+
+        let mut request = vec![IAC, DO, CHARSET, IAC, SB, CHARSET, CHARSET_REQUEST];
+        request.extend_from_slice(b";KOI8-R;ibm437;UTF-8");
+        request.extend_from_slice(&[IAC, SE]);
+        telnet.process(&request, &mut replies, &mut events);
+
+        let mut expected = vec![IAC, WILL, CHARSET, IAC, SB, CHARSET, CHARSET_ACCEPTED];
+        expected.extend_from_slice(b"ibm437");
+        expected.extend_from_slice(&[IAC, SE]);
+        assert_eq!(replies, expected);
+        assert_eq!(events, vec![RemoteTelnetEvent::Charset(TerminalEncoding::Cp437)]);
+
+        // Nothing we can decode
+        replies.clear();
+        let mut request = vec![IAC, SB, CHARSET, CHARSET_REQUEST];
+        request.extend_from_slice(b" KOI8-R ISO-8859-5");
+        request.extend_from_slice(&[IAC, SE]);
+        telnet.process(&request, &mut replies, &mut events);
+        assert_eq!(replies, vec![IAC, SB, CHARSET, CHARSET_REJECTED, IAC, SE]);
+    }
+}
@@ -3,9 +3,16 @@
 //! Provides ANSI terminal emulation for remote connections.
 //! Parses incoming ANSI escape sequences and maintains terminal state.
 
-use crate::core::{Cell, Color, Attrs};
+use crate::core::{char_width, Attrs, Cell, CellWidth, Color};
+use crate::renderer::charset::cp437_char;
+use crate::renderer::CursorStyle;
 use tokio::sync::mpsc;
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Longest OSC sequence kept (longer ones, e.g. clipboard transfers, are ignored)
+const MAX_OSC_LEN: usize = 4096;
 
 /// Terminal emulator state
 pub struct Terminal {
@@ -33,16 +40,99 @@ pub struct Terminal {
     pub scrollback: VecDeque<Vec<Cell>>,
     /// Max scrollback lines
     pub max_scrollback: usize,
+    /// Lines the view is scrolled back into history (0 = live screen)
+    pub scroll_offset: usize,
+    /// Top line of the scrolling region (DECSTBM)
+    pub scroll_top: usize,
+    /// Bottom line of the scrolling region, inclusive
+    pub scroll_bottom: usize,
+    /// DEC private modes set by the remote program
+    pub modes: TerminalModes,
+    /// Cursor shape requested by the remote program (DECSCUSR)
+    pub cursor_style: CursorStyle,
+    /// The main screen, put aside while the alternate screen is up
+    main_screen: Option<MainScreen>,
     /// Whether display needs refresh
     pub dirty: bool,
     /// Parser state
     parser_state: ParserState,
     /// Escape sequence buffer
     esc_buffer: String,
+    /// Operating System Command being read (raw bytes, so titles can be UTF-8)
+    osc_buffer: Vec<u8>,
+    /// Window title set by the remote program (OSC 0/2)
+    pub title: Option<String>,
+    /// Whether the title changed since the window last showed it
+    pub title_changed: bool,
+    /// Palette entries redefined by the remote program (OSC 4)
+    pub palette: HashMap<u8, (u8, u8, u8)>,
+    /// Default foreground redefined by the remote program (OSC 10)
+    pub default_fg: Option<(u8, u8, u8)>,
+    /// Default background redefined by the remote program (OSC 11)
+    pub default_bg: Option<(u8, u8, u8)>,
+    /// Hyperlink for printed text (OSC 8)
+    link: Option<Arc<str>>,
     /// Terminal type for compatibility
     pub terminal_type: TerminalType,
+    /// How bytes from the remote become characters
+    pub encoding: TerminalEncoding,
+    /// UTF-8 sequence being decoded
+    utf8: Utf8Decoder,
     /// Response queue - data to send back to remote server
     pub response_queue: VecDeque<Vec<u8>>,
+    /// Whether the remote server echoes what's typed (telnet ECHO), making local echo redundant
+    pub remote_echo: bool,
+}
+
+/// DEC private modes (`ESC [ ? n h` / `ESC [ ? n l`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalModes {
+    /// Application cursor keys (?1): arrows send `ESC O A` instead of `ESC [ A`
+    pub application_cursor_keys: bool,
+    /// Origin mode (?6): cursor positions are relative to the scrolling region
+    pub origin: bool,
+    /// Autowrap (?7): printing past the last column wraps to the next line
+    pub autowrap: bool,
+    /// Cursor visible (?25)
+    pub cursor_visible: bool,
+    /// Mouse events the remote program wants reported (?9, ?1000, ?1002, ?1003)
+    pub mouse_tracking: MouseTracking,
+    /// Report mouse events as `ESC [ < b ; x ; y M` instead of raw bytes (?1006)
+    pub sgr_mouse: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            application_cursor_keys: false,
+            origin: false,
+            autowrap: true,
+            cursor_visible: true,
+            mouse_tracking: MouseTracking::Off,
+            sgr_mouse: false,
+        }
+    }
+}
+
+/// Mouse reporting requested by the remote program (each level includes the ones before)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MouseTracking {
+    Off,
+    /// Presses only (?9)
+    X10,
+    /// Presses and releases (?1000)
+    Normal,
+    /// Also motion while a button is held (?1002)
+    Button,
+    /// All motion (?1003)
+    Any,
+}
+
+/// The main screen while the alternate screen is shown
+struct MainScreen {
+    screen: Vec<Vec<Cell>>,
+    /// Cursor to restore on the way back (?1049 only)
+    cursor: Option<(usize, usize)>,
 }
 
 /// Parser state machine
@@ -56,6 +146,70 @@ enum ParserState {
     Csi,
     /// Got ESC ], reading OSC sequence
     Osc,
+    /// Got ESC inside an OSC sequence (`\` completes the string terminator)
+    OscEscape,
+}
+
+/// Character encoding of the remote's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TerminalEncoding {
+    /// UTF-8 (modern hosts)
+    #[default]
+    Utf8,
+    /// IBM PC code page 437, one byte per character (DOS-era BBSes)
+    Cp437,
+}
+
+impl TerminalEncoding {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "cp437" | "ibm437" | "dos" => TerminalEncoding::Cp437,
+            _ => TerminalEncoding::Utf8,
+        }
+    }
+}
+
+/// Incremental UTF-8 decoder (sequences can be split across reads)
+#[derive(Debug, Default)]
+struct Utf8Decoder {
+    /// Bits decoded so far
+    code: u32,
+    /// Continuation bytes still expected
+    remaining: u8,
+}
+
+impl Utf8Decoder {
+    /// Feed a byte 0x80-0xFF, getting a character once one is complete
+    /// (malformed input becomes U+FFFD)
+    fn push(&mut self, byte: u8) -> Option<char> {
+        if (0x80..=0xbf).contains(&byte) {
+            if self.remaining == 0 {
+                return Some(char::REPLACEMENT_CHARACTER);
+            }
+            self.code = (self.code << 6) | (byte & 0x3f) as u32;
+            self.remaining -= 1;
+            return (self.remaining == 0)
+                .then(|| char::from_u32(self.code).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        // A new sequence starts (cutting short any unfinished one)
+        let interrupted = self.interrupt();
+        let (code, remaining) = match byte {
+            0xc2..=0xdf => (byte & 0x1f, 1),
+            0xe0..=0xef => (byte & 0x0f, 2),
+            0xf0..=0xf4 => (byte & 0x07, 3),
+            _ => return Some(char::REPLACEMENT_CHARACTER),
+        };
+        self.code = code as u32;
+        self.remaining = remaining;
+        interrupted.then_some(char::REPLACEMENT_CHARACTER)
+    }
+
+    /// Abandon an unfinished sequence, returning whether there was one
+    fn interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.remaining) > 0
+    }
 }
 
 /// Terminal type for compatibility
@@ -72,6 +226,7 @@ pub enum TerminalType {
 }
 
 impl TerminalType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "vt100" => TerminalType::Vt100,
@@ -101,11 +256,27 @@ impl Terminal {
             saved_cursor: None,
             scrollback: VecDeque::new(),
             max_scrollback: 1000,
+            scroll_offset: 0,
+            scroll_top: 0,
+            scroll_bottom: height.saturating_sub(1),
+            modes: TerminalModes::default(),
+            cursor_style: CursorStyle::default(),
+            main_screen: None,
             dirty: true,
             parser_state: ParserState::Normal,
             esc_buffer: String::new(),
+            osc_buffer: Vec::new(),
+            title: None,
+            title_changed: false,
+            palette: HashMap::new(),
+            default_fg: None,
+            default_bg: None,
+            link: None,
             terminal_type,
+            encoding: TerminalEncoding::default(),
+            utf8: Utf8Decoder::default(),
             response_queue: VecDeque::new(),
+            remote_echo: false,
         }
     }
 
@@ -114,7 +285,7 @@ impl Terminal {
         if self.terminal_type == TerminalType::Raw {
             // Raw mode - just display printable characters
             for &byte in data {
-                if byte >= 32 && byte < 127 {
+                if (32..127).contains(&byte) {
                     self.put_char(byte as char);
                 } else if byte == b'\n' {
                     self.newline();
@@ -135,6 +306,10 @@ impl Terminal {
 
     /// Process a single byte
     fn process_byte(&mut self, byte: u8) {
+        // Anything but a continuation byte cuts short a UTF-8 sequence
+        if byte < 0x80 && self.utf8.interrupt() && self.parser_state == ParserState::Normal {
+            self.put_char(char::REPLACEMENT_CHARACTER);
+        }
         match self.parser_state {
             ParserState::Normal => {
                 match byte {
@@ -148,9 +323,7 @@ impl Terminal {
                     }
                     0x08 => {
                         // BS - backspace
-                        if self.cursor_x > 0 {
-                            self.cursor_x -= 1;
-                        }
+                        self.cursor_x = self.cursor_x.saturating_sub(1);
                     }
                     0x09 => {
                         // TAB - move to next tab stop
@@ -171,10 +344,14 @@ impl Terminal {
                         // Printable ASCII
                         self.put_char(byte as char);
                     }
-                    0x80..=0xff => {
-                        // Extended ASCII / CP437 - display as-is
-                        self.put_char(byte as char);
-                    }
+                    0x80..=0xff => match self.encoding {
+                        TerminalEncoding::Utf8 => {
+                            if let Some(ch) = self.utf8.push(byte) {
+                                self.put_char(ch);
+                            }
+                        }
+                        TerminalEncoding::Cp437 => self.put_char(cp437_char(byte)),
+                    },
                     _ => {
                         // Ignore other control characters
                     }
@@ -190,7 +367,7 @@ impl Terminal {
                     b']' => {
                         // OSC - Operating System Command
                         self.parser_state = ParserState::Osc;
-                        self.esc_buffer.clear();
+                        self.osc_buffer.clear();
                     }
                     b'7' => {
                         // Save cursor position
@@ -217,9 +394,11 @@ impl Terminal {
                         self.parser_state = ParserState::Normal;
                     }
                     b'M' => {
-                        // Reverse index (move up, scroll if needed)
-                        if self.cursor_y > 0 {
-                            self.cursor_y -= 1;
+                        // Reverse index (move up, scrolling the region down at its top)
+                        if self.cursor_y == self.scroll_top {
+                            self.scroll_region_down(1);
+                        } else {
+                            self.cursor_y = self.cursor_y.saturating_sub(1);
                         }
                         self.parser_state = ParserState::Normal;
                     }
@@ -235,7 +414,7 @@ impl Terminal {
                 }
             }
             ParserState::Csi => {
-                if byte >= 0x40 && byte <= 0x7e {
+                if (0x40..=0x7e).contains(&byte) {
                     // Final byte - execute sequence
                     self.execute_csi(byte as char);
                     self.parser_state = ParserState::Normal;
@@ -245,39 +424,193 @@ impl Terminal {
                 }
             }
             ParserState::Osc => {
-                if byte == 0x07 || byte == 0x1b {
-                    // BEL or ESC terminates OSC
-                    // We ignore OSC sequences for now (window title, etc.)
+                match byte {
+                    0x07 => {
+                        // BEL terminates OSC (xterm's shorthand for ST)
+                        self.execute_osc("\x07");
+                        self.parser_state = ParserState::Normal;
+                    }
+                    0x1b => {
+                        // Possibly the start of ST (ESC \)
+                        self.parser_state = ParserState::OscEscape;
+                    }
+                    0x18 | 0x1a => {
+                        // CAN / SUB abandon the sequence
+                        self.parser_state = ParserState::Normal;
+                    }
+                    _ => {
+                        // Keep one byte past the limit to mark the sequence as oversized
+                        if self.osc_buffer.len() <= MAX_OSC_LEN {
+                            self.osc_buffer.push(byte);
+                        }
+                    }
+                }
+            }
+            ParserState::OscEscape => {
+                if byte == b'\\' {
+                    // ST - String Terminator
+                    self.execute_osc("\x1b\\");
                     self.parser_state = ParserState::Normal;
                 } else {
-                    self.esc_buffer.push(byte as char);
+                    // Any other escape abandons the OSC and starts a new sequence
+                    self.parser_state = ParserState::Escape;
+                    self.esc_buffer.clear();
+                    self.process_byte(byte);
                 }
             }
         }
     }
 
+    /// Execute an OSC sequence (replies end with the same `terminator`, as in xterm)
+    fn execute_osc(&mut self, terminator: &str) {
+        if self.osc_buffer.len() > MAX_OSC_LEN {
+            return;
+        }
+        let data = String::from_utf8_lossy(&self.osc_buffer).into_owned();
+        let (command, args) = data.split_once(';').unwrap_or((&data, ""));
+        match command {
+            "0" | "2" => {
+                // Icon name and window title / window title
+                let title: String = args.chars().filter(|c| !c.is_control()).collect();
+                self.title = (!title.is_empty()).then_some(title);
+                self.title_changed = true;
+            }
+            "4" => {
+                // Pairs of palette index and color spec ("?" asks for the current color)
+                let mut parts = args.split(';');
+                while let (Some(index), Some(spec)) = (parts.next(), parts.next()) {
+                    let Ok(index) = index.parse::<u8>() else {
+                        continue;
+                    };
+                    if spec == "?" {
+                        let rgb = self.palette.get(&index).copied().unwrap_or_else(|| Color::from(index).to_rgb());
+                        self.reply_color(&format!("4;{}", index), rgb, terminator);
+                    } else if let Some(rgb) = parse_color_spec(spec) {
+                        self.palette.insert(index, rgb);
+                    }
+                }
+            }
+            "8" => {
+                // Hyperlink: OSC 8 ; params ; URI (an empty URI ends the link)
+                let uri = args.split_once(';').map_or("", |(_, uri)| uri);
+                self.link = (!uri.is_empty() && !uri.chars().any(char::is_control)).then(|| Arc::from(uri));
+            }
+            "10" | "11" => {
+                // Default foreground / background; OSC 10 can go on to set 11 too
+                let first: usize = if command == "10" { 10 } else { 11 };
+                for (n, spec) in (first..=11).zip(args.split(';')) {
+                    if spec == "?" {
+                        let rgb = if n == 10 {
+                            self.default_fg.unwrap_or_else(|| Color::White.to_rgb())
+                        } else {
+                            self.default_bg.unwrap_or_else(|| Color::Black.to_rgb())
+                        };
+                        self.reply_color(&n.to_string(), rgb, terminator);
+                    } else if let Some(rgb) = parse_color_spec(spec) {
+                        if n == 10 {
+                            self.default_fg = Some(rgb);
+                        } else {
+                            self.default_bg = Some(rgb);
+                        }
+                    }
+                }
+            }
+            "104" => {
+                // Reset the listed palette entries, or all of them
+                if args.is_empty() {
+                    self.palette.clear();
+                }
+                for index in args.split(';').filter_map(|i| i.parse::<u8>().ok()) {
+                    self.palette.remove(&index);
+                }
+            }
+            "110" => self.default_fg = None,
+            "111" => self.default_bg = None,
+            _ => {
+                // Unsupported OSC (icon name, clipboard, etc.)
+            }
+        }
+    }
+
+    /// Queue a color report (`OSC prefix ; rgb:rrrr/gggg/bbbb`)
+    fn reply_color(&mut self, prefix: &str, (r, g, b): (u8, u8, u8), terminator: &str) {
+        let scale = |v: u8| v as u16 * 257;
+        let response = format!("\x1b]{};rgb:{:04x}/{:04x}/{:04x}{}", prefix, scale(r), scale(g), scale(b), terminator);
+        self.response_queue.push_back(response.into_bytes());
+    }
+
+    /// How a cell looks with the remote program's palette changes applied
+    pub fn display_cell<'a>(&self, cell: &'a Cell) -> Cow<'a, Cell> {
+        if self.palette.is_empty() && self.default_fg.is_none() && self.default_bg.is_none() {
+            return Cow::Borrowed(cell);
+        }
+        let recolor = |color: Color, default: Color, default_rgb: Option<(u8, u8, u8)>| {
+            match default_rgb {
+                Some((r, g, b)) if color == default => Color::Rgb(r, g, b),
+                _ => match color.index().and_then(|i| self.palette.get(&i)) {
+                    Some(&(r, g, b)) => Color::Rgb(r, g, b),
+                    None => color,
+                },
+            }
+        };
+        let mut cell = cell.clone();
+        cell.fg = recolor(cell.fg, Color::White, self.default_fg);
+        cell.bg = recolor(cell.bg, Color::Black, self.default_bg);
+        Cow::Owned(cell)
+    }
+
     /// Execute a CSI sequence
     fn execute_csi(&mut self, final_byte: char) {
+        // Private sequences start with one of ? > = <
+        let private = self.esc_buffer.chars().next().filter(|c| "?>=<".contains(*c));
+        // Intermediate bytes (space through /) come between the parameters and the final byte
+        let intermediate = self.esc_buffer.chars().last().filter(|c| (' '..='/').contains(c));
         let params: Vec<usize> = self.esc_buffer
+            .trim_start_matches(['?', '>', '=', '<'])
+            .trim_end_matches(|c: char| (' '..='/').contains(&c))
             .split(';')
             .map(|s| s.parse().unwrap_or(0))
             .collect();
 
+        if let Some(intermediate) = intermediate {
+            // Set cursor style (DECSCUSR); other sequences with intermediates aren't supported
+            if intermediate == ' ' && final_byte == 'q' && private.is_none() {
+                if let Some(style) = CursorStyle::from_param(params[0]) {
+                    self.cursor_style = style;
+                }
+            }
+            return;
+        }
+
+        match private {
+            None => {}
+            Some('?') if final_byte == 'h' || final_byte == 'l' => {
+                for &mode in &params {
+                    self.set_private_mode(mode, final_byte == 'h');
+                }
+                return;
+            }
+            // Other private sequences (DA2 queries, xterm key options...) aren't supported
+            Some(_) => return,
+        }
+
         match final_byte {
             'A' => {
-                // Cursor up
+                // Cursor up (stopping at the top margin from inside the region)
                 let n = params.first().copied().unwrap_or(1).max(1);
-                self.cursor_y = self.cursor_y.saturating_sub(n);
+                let top = if self.cursor_y >= self.scroll_top { self.scroll_top } else { 0 };
+                self.cursor_y = self.cursor_y.saturating_sub(n).max(top);
             }
             'B' => {
-                // Cursor down
+                // Cursor down (stopping at the bottom margin from inside the region)
                 let n = params.first().copied().unwrap_or(1).max(1);
-                self.cursor_y = (self.cursor_y + n).min(self.height - 1);
+                let bottom = if self.cursor_y <= self.scroll_bottom { self.scroll_bottom } else { self.height - 1 };
+                self.cursor_y = self.cursor_y.saturating_add(n).min(bottom);
             }
             'C' => {
                 // Cursor forward
                 let n = params.first().copied().unwrap_or(1).max(1);
-                self.cursor_x = (self.cursor_x + n).min(self.width - 1);
+                self.cursor_x = self.cursor_x.saturating_add(n).min(self.width - 1);
             }
             'D' => {
                 // Cursor back
@@ -287,7 +620,7 @@ impl Terminal {
             'E' => {
                 // Cursor next line
                 let n = params.first().copied().unwrap_or(1).max(1);
-                self.cursor_y = (self.cursor_y + n).min(self.height - 1);
+                self.cursor_y = self.cursor_y.saturating_add(n).min(self.height - 1);
                 self.cursor_x = 0;
             }
             'F' => {
@@ -302,10 +635,14 @@ impl Terminal {
                 self.cursor_x = (n - 1).min(self.width - 1);
             }
             'H' | 'f' => {
-                // Cursor position
+                // Cursor position (within the scrolling region in origin mode)
                 let row = params.first().copied().unwrap_or(1).max(1);
                 let col = params.get(1).copied().unwrap_or(1).max(1);
-                self.cursor_y = (row - 1).min(self.height - 1);
+                self.cursor_y = if self.modes.origin {
+                    self.scroll_top.saturating_add(row - 1).min(self.scroll_bottom)
+                } else {
+                    (row - 1).min(self.height - 1)
+                };
                 self.cursor_x = (col - 1).min(self.width - 1);
             }
             'J' => {
@@ -331,15 +668,69 @@ impl Terminal {
             'S' => {
                 // Scroll up
                 let n = params.first().copied().unwrap_or(1).max(1);
-                for _ in 0..n {
-                    self.scroll_up();
-                }
+                self.scroll_region_up(n);
             }
             'T' => {
                 // Scroll down
                 let n = params.first().copied().unwrap_or(1).max(1);
-                for _ in 0..n {
-                    self.scroll_down();
+                self.scroll_region_down(n);
+            }
+            'r' => {
+                // DECSTBM - set scrolling region (1-based, inclusive), then home the cursor
+                let top = params.first().copied().unwrap_or(1).max(1) - 1;
+                let bottom = match params.get(1).copied().unwrap_or(0) {
+                    0 => self.height - 1,
+                    n => (n - 1).min(self.height - 1),
+                };
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.home_cursor();
+                }
+            }
+            'L' => {
+                // IL - insert lines at the cursor, pushing the rest of the region down
+                let n = params.first().copied().unwrap_or(1).max(1);
+                if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_y) {
+                    self.shift_lines_down(self.cursor_y, n);
+                    self.cursor_x = 0;
+                }
+            }
+            'M' => {
+                // DL - delete lines at the cursor, pulling the rest of the region up
+                let n = params.first().copied().unwrap_or(1).max(1);
+                if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_y) {
+                    self.shift_lines_up(self.cursor_y, n);
+                    self.cursor_x = 0;
+                }
+            }
+            '@' => {
+                // ICH - insert blank characters at the cursor
+                let n = params.first().copied().unwrap_or(1).max(1);
+                let x = self.cursor_x.min(self.width);
+                let n = n.min(self.width - x);
+                let blank = self.blank_cell();
+                let row = &mut self.screen[self.cursor_y];
+                row.truncate(self.width - n);
+                row.splice(x..x, std::iter::repeat_n(blank, n));
+            }
+            'P' => {
+                // DCH - delete characters at the cursor, pulling the rest of the line left
+                let n = params.first().copied().unwrap_or(1).max(1);
+                let x = self.cursor_x.min(self.width);
+                let n = n.min(self.width - x);
+                let blank = self.blank_cell();
+                let row = &mut self.screen[self.cursor_y];
+                row.drain(x..x + n);
+                row.extend(std::iter::repeat_n(blank, n));
+            }
+            'X' => {
+                // ECH - erase characters from the cursor without moving it
+                let n = params.first().copied().unwrap_or(1).max(1);
+                // (counts come from the remote, so they can be anything)
+                let end = self.cursor_x.saturating_add(n).min(self.width);
+                for x in self.cursor_x.min(end)..end {
+                    self.screen[self.cursor_y][x] = self.blank_cell();
                 }
             }
             'm' => {
@@ -362,14 +753,15 @@ impl Terminal {
                 let n = params.first().copied().unwrap_or(0);
                 if n == 6 {
                     // ESC[6n - Cursor Position Report request
-                    // Respond with ESC[row;colR (1-indexed)
-                    let response = format!("\x1b[{};{}R", self.cursor_y + 1, self.cursor_x + 1);
+                    // Respond with ESC[row;colR (1-indexed, relative to the region in origin mode)
+                    let row = if self.modes.origin { self.cursor_y.saturating_sub(self.scroll_top) } else { self.cursor_y };
+                    let response = format!("\x1b[{};{}R", row + 1, self.cursor_x + 1);
                     self.response_queue.push_back(response.into_bytes());
                 }
                 // n=5 is status report (we'd respond ESC[0n for "OK") - ignore for now
             }
             'h' | 'l' => {
-                // Mode set/reset - we ignore most of these
+                // ANSI modes (insert mode etc.) aren't supported; DEC private modes are handled above
             }
             _ => {
                 // Unknown CSI sequence
@@ -416,11 +808,10 @@ impl Terminal {
                     self.fg = Color::from(params[i] as u8 - 30);
                 }
                 38 => {
-                    // Extended foreground color
-                    if i + 2 < params.len() && params[i + 1] == 5 {
-                        // 256-color mode
-                        self.fg = Color::from(params[i + 2] as u8);
-                        i += 2;
+                    // Extended foreground color (38;5;n or 38;2;r;g;b)
+                    if let Some((color, used)) = parse_extended_color(&params[i + 1..]) {
+                        self.fg = color;
+                        i += used;
                     }
                 }
                 39 => self.fg = Color::White, // Default foreground
@@ -429,11 +820,10 @@ impl Terminal {
                     self.bg = Color::from(params[i] as u8 - 40);
                 }
                 48 => {
-                    // Extended background color
-                    if i + 2 < params.len() && params[i + 1] == 5 {
-                        // 256-color mode
-                        self.bg = Color::from(params[i + 2] as u8);
-                        i += 2;
+                    // Extended background color (48;5;n or 48;2;r;g;b)
+                    if let Some((color, used)) = parse_extended_color(&params[i + 1..]) {
+                        self.bg = color;
+                        i += used;
                     }
                 }
                 49 => self.bg = Color::Black, // Default background
@@ -451,57 +841,177 @@ impl Terminal {
         }
     }
 
+    /// Set or reset a DEC private mode
+    fn set_private_mode(&mut self, mode: usize, on: bool) {
+        match mode {
+            1 => self.modes.application_cursor_keys = on,
+            6 => {
+                self.modes.origin = on;
+                self.home_cursor();
+            }
+            7 => self.modes.autowrap = on,
+            25 => self.modes.cursor_visible = on,
+            9 | 1000 | 1002 | 1003 => {
+                let tracking = match mode {
+                    9 => MouseTracking::X10,
+                    1000 => MouseTracking::Normal,
+                    1002 => MouseTracking::Button,
+                    _ => MouseTracking::Any,
+                };
+                if on {
+                    self.modes.mouse_tracking = tracking;
+                } else if self.modes.mouse_tracking == tracking {
+                    self.modes.mouse_tracking = MouseTracking::Off;
+                }
+            }
+            1006 => self.modes.sgr_mouse = on,
+            // Alternate screen (1049 also saves and restores the cursor)
+            47 | 1047 | 1049 => {
+                if on {
+                    self.enter_alternate_screen(mode == 1049);
+                } else {
+                    self.leave_alternate_screen();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the alternate screen is shown
+    pub fn alternate_screen(&self) -> bool {
+        self.main_screen.is_some()
+    }
+
+    /// Put the main screen aside and show a blank alternate screen
+    fn enter_alternate_screen(&mut self, save_cursor: bool) {
+        if self.main_screen.is_some() {
+            return;
+        }
+        let blank = vec![vec![self.blank_cell(); self.width]; self.height];
+        self.main_screen = Some(MainScreen {
+            screen: std::mem::replace(&mut self.screen, blank),
+            cursor: save_cursor.then_some((self.cursor_x, self.cursor_y)),
+        });
+    }
+
+    /// Bring back the main screen (and the cursor, if it was saved)
+    fn leave_alternate_screen(&mut self) {
+        if let Some(main) = self.main_screen.take() {
+            self.screen = main.screen;
+            if let Some((x, y)) = main.cursor {
+                self.cursor_x = x.min(self.width - 1);
+                self.cursor_y = y.min(self.height - 1);
+            }
+        }
+    }
+
+    /// Move the cursor to the top left (of the scrolling region in origin mode)
+    fn home_cursor(&mut self) {
+        self.cursor_x = 0;
+        self.cursor_y = if self.modes.origin { self.scroll_top } else { 0 };
+    }
+
     /// Put a character at cursor position and advance
     fn put_char(&mut self, ch: char) {
-        if self.cursor_x >= self.width {
-            // Wrap to next line
-            self.cursor_x = 0;
-            self.newline();
+        // Combining marks and other zero-width characters aren't drawn
+        let width = char_width(ch);
+        if width == 0 || width > self.width {
+            return;
         }
 
-        if self.cursor_y < self.height && self.cursor_x < self.width {
-            self.screen[self.cursor_y][self.cursor_x] = Cell::full(
-                ch,
-                self.fg,
-                self.bg,
-                self.attrs,
-            );
-            self.cursor_x += 1;
+        // A wide character that doesn't fit on the line wraps as a whole
+        if self.cursor_x + width > self.width {
+            if self.modes.autowrap {
+                // Wrap to next line
+                self.cursor_x = 0;
+                self.newline();
+            } else {
+                // Keep overwriting the last column
+                self.cursor_x = self.width - width;
+            }
+        }
+
+        if self.cursor_y < self.height {
+            let (x, y) = (self.cursor_x, self.cursor_y);
+            // Overwriting half of a wide character blanks its other half
+            if self.screen[y][x].width == CellWidth::Continuation && x > 0 {
+                self.screen[y][x - 1] = self.blank_cell();
+            }
+            if self.screen[y][x + width - 1].width == CellWidth::Wide && x + width < self.width {
+                self.screen[y][x + width] = self.blank_cell();
+            }
+
+            let cell = Cell { link: self.link.clone(), ..Cell::full(ch, self.fg, self.bg, self.attrs) };
+            if width == 2 {
+                self.screen[y][x + 1] = Cell { width: CellWidth::Continuation, char: ' ', ..cell.clone() };
+                self.screen[y][x] = Cell { width: CellWidth::Wide, ..cell };
+            } else {
+                self.screen[y][x] = cell;
+            }
+            self.cursor_x += width;
         }
     }
 
-    /// Move to next line, scrolling if needed
+    /// Move to next line, scrolling the region if at its bottom
     fn newline(&mut self) {
-        if self.cursor_y < self.height - 1 {
+        if self.cursor_y == self.scroll_bottom {
+            self.scroll_region_up(1);
+        } else if self.cursor_y < self.height - 1 {
             self.cursor_y += 1;
-        } else {
-            self.scroll_up();
         }
     }
 
-    /// Scroll screen up by one line
-    fn scroll_up(&mut self) {
-        if !self.screen.is_empty() {
+    /// A blank cell in the current background color
+    fn blank_cell(&self) -> Cell {
+        Cell::full(' ', self.fg, self.bg, Attrs::default())
+    }
+
+    /// Scroll the region up (lines leaving a full-screen region go to scrollback)
+    fn scroll_region_up(&mut self, n: usize) {
+        // The alternate screen has no scrollback
+        let full_screen = self.scroll_top == 0 && self.scroll_bottom == self.height - 1;
+        if !full_screen || self.alternate_screen() {
+            self.shift_lines_up(self.scroll_top, n);
+            return;
+        }
+        for _ in 0..n.min(self.height) {
             // Save top line to scrollback
             let top = self.screen.remove(0);
             self.scrollback.push_back(top);
             while self.scrollback.len() > self.max_scrollback {
                 self.scrollback.pop_front();
             }
+            // Keep a scrolled-back view on the same lines
+            if self.scroll_offset > 0 {
+                self.scroll_offset = (self.scroll_offset + 1).min(self.scrollback.len());
+            }
             // Add blank line at bottom
-            let blank = vec![Cell::full(' ', self.fg, self.bg, Attrs::default()); self.width];
-            self.screen.push(blank);
+            self.screen.push(vec![self.blank_cell(); self.width]);
         }
     }
 
-    /// Scroll screen down by one line
-    fn scroll_down(&mut self) {
-        if !self.screen.is_empty() {
-            // Remove bottom line
-            self.screen.pop();
-            // Add blank line at top
-            let blank = vec![Cell::full(' ', self.fg, self.bg, Attrs::default()); self.width];
-            self.screen.insert(0, blank);
+    /// Scroll the region down
+    fn scroll_region_down(&mut self, n: usize) {
+        self.shift_lines_down(self.scroll_top, n);
+    }
+
+    /// Remove lines at `y`, moving the region below up and blanking its bottom
+    fn shift_lines_up(&mut self, y: usize, n: usize) {
+        let n = n.min(self.scroll_bottom + 1 - y);
+        self.screen.drain(y..y + n);
+        let blank = vec![self.blank_cell(); self.width];
+        for _ in 0..n {
+            self.screen.insert(self.scroll_bottom + 1 - n, blank.clone());
+        }
+    }
+
+    /// Insert blank lines at `y`, moving the region below down (off its bottom)
+    fn shift_lines_down(&mut self, y: usize, n: usize) {
+        let n = n.min(self.scroll_bottom + 1 - y);
+        self.screen.drain(self.scroll_bottom + 1 - n..=self.scroll_bottom);
+        let blank = vec![self.blank_cell(); self.width];
+        for _ in 0..n {
+            self.screen.insert(y, blank.clone());
         }
     }
 
@@ -563,28 +1073,30 @@ impl Terminal {
         self.bg = Color::Black;
         self.attrs = Attrs::default();
         self.saved_cursor = None;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.height - 1;
+        self.modes = TerminalModes::default();
+        self.cursor_style = CursorStyle::default();
+        self.main_screen = None;
+        self.palette.clear();
+        self.default_fg = None;
+        self.default_bg = None;
+        self.link = None;
         self.erase_all();
     }
 
     /// Resize terminal
     pub fn resize(&mut self, new_width: usize, new_height: usize) {
-        let default_cell = Cell::full(' ', Color::White, Color::Black, Attrs::default());
-
-        // Create new screen buffer
-        let mut new_screen = vec![vec![default_cell.clone(); new_width]; new_height];
-
-        // Copy existing content
-        for y in 0..new_height.min(self.height) {
-            for x in 0..new_width.min(self.width) {
-                new_screen[y][x] = self.screen[y][x].clone();
-            }
+        self.screen = resize_screen(&self.screen, new_width, new_height);
+        if let Some(main) = &mut self.main_screen {
+            main.screen = resize_screen(&main.screen, new_width, new_height);
         }
-
-        self.screen = new_screen;
         self.width = new_width;
         self.height = new_height;
         self.cursor_x = self.cursor_x.min(new_width - 1);
         self.cursor_y = self.cursor_y.min(new_height - 1);
+        self.scroll_top = 0;
+        self.scroll_bottom = new_height - 1;
         self.dirty = true;
     }
 
@@ -592,6 +1104,94 @@ impl Terminal {
     pub fn get_screen(&self) -> &Vec<Vec<Cell>> {
         &self.screen
     }
+
+    /// Scroll the view into history (positive) or back toward the live screen (negative)
+    pub fn scroll_view(&mut self, lines: isize) {
+        let offset = self.scroll_offset.saturating_add_signed(lines).min(self.scrollback.len());
+        if offset != self.scroll_offset {
+            self.scroll_offset = offset;
+            self.dirty = true;
+        }
+    }
+
+    /// Return the view to the live screen; true if it was scrolled back
+    pub fn snap_to_bottom(&mut self) -> bool {
+        let scrolled = self.scroll_offset > 0;
+        self.scroll_view(-(self.scroll_offset as isize));
+        scrolled
+    }
+
+    /// A row of the view: history while scrolled back, the screen otherwise
+    /// (history rows keep the width they had, so may be shorter or longer)
+    pub fn view_row(&self, y: usize) -> &[Cell] {
+        let row = self.scrollback.len() - self.scroll_offset + y;
+        match self.scrollback.get(row) {
+            Some(line) => line,
+            None => self.screen.get(row - self.scrollback.len()).map(Vec::as_slice).unwrap_or(&[]),
+        }
+    }
+
+    /// Text of scrollback lines, oldest first (trailing blanks trimmed)
+    pub fn scrollback_text(&self, start: usize, count: usize) -> Vec<String> {
+        self.scrollback.iter()
+            .skip(start)
+            .take(count)
+            .map(|line| {
+                // The right halves of wide characters aren't text of their own
+                let text: String = line.iter().filter(|c| c.width != CellWidth::Continuation).map(|c| c.char).collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+/// Parse an X11 color spec: `rgb:r/g/b` (1-4 hex digits per channel) or `#rrggbb`
+fn parse_color_spec(spec: &str) -> Option<(u8, u8, u8)> {
+    let Some(channels) = spec.strip_prefix("rgb:") else {
+        return Color::from_hex(spec).map(|c| c.to_rgb());
+    };
+    let scale = |hex: &str| -> Option<u8> {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some(((value * 255 + max / 2) / max) as u8)
+    };
+    match channels.split('/').collect::<Vec<_>>()[..] {
+        [r, g, b] => Some((scale(r)?, scale(g)?, scale(b)?)),
+        _ => None,
+    }
+}
+
+/// Copy a screen buffer into a new size (cropping or padding with blanks)
+fn resize_screen(screen: &[Vec<Cell>], width: usize, height: usize) -> Vec<Vec<Cell>> {
+    let default_cell = Cell::full(' ', Color::White, Color::Black, Attrs::default());
+    let mut new_screen = vec![vec![default_cell; width]; height];
+    for (new_row, old_row) in new_screen.iter_mut().zip(screen.iter()) {
+        for (new_cell, old_cell) in new_row.iter_mut().zip(old_row.iter()) {
+            *new_cell = old_cell.clone();
+        }
+    }
+    new_screen
+}
+
+/// Parse the parameters following SGR 38/48
+/// Returns the color and how many parameters were consumed
+fn parse_extended_color(params: &[usize]) -> Option<(Color, usize)> {
+    match params.first() {
+        Some(5) => {
+            let n = *params.get(1)?;
+            Some((Color::from(n.min(255) as u8), 2))
+        }
+        Some(2) => {
+            let r = *params.get(1)?;
+            let g = *params.get(2)?;
+            let b = *params.get(3)?;
+            Some((Color::Rgb(r.min(255) as u8, g.min(255) as u8, b.min(255) as u8), 4))
+        }
+        _ => None,
+    }
 }
 
 /// Active terminal connection
@@ -607,3 +1207,260 @@ impl TerminalConnection {
         self.tx.send(data.to_vec()).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sgr_extended_colors() {
+        let mut term = Terminal::new("t".to_string(), 10, 2, TerminalType::Xterm);
+        term.process_data(b"\x1b[38;5;208mA\x1b[38;2;1;2;3;48;5;17mB");
+        assert_eq!(term.screen[0][0].fg, Color::Indexed(208));
+        assert_eq!(term.screen[0][1].fg, Color::Rgb(1, 2, 3));
+        assert_eq!(term.screen[0][1].bg, Color::Indexed(17));
+    }
+
+    /// The screen as text, one string per row
+    fn rows(term: &Terminal) -> Vec<String> {
+        term.screen.iter().map(|row| row.iter().map(|c| c.char).collect()).collect()
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut term = Terminal::new("t".to_string(), 3, 5, TerminalType::Ansi);
+        term.process_data(b"aaa\r\nbbb\r\nccc\r\nddd\r\neee");
+        // Region rows 2-4: a newline at its bottom scrolls only rows 2-4
+        term.process_data(b"\x1b[2;4r\x1b[4;1H\nXX");
+        assert_eq!(rows(&term), vec!["aaa", "ccc", "ddd", "XX ", "eee"]);
+        assert!(term.scrollback.is_empty());
+
+        // Reverse index at the region's top scrolls it down
+        term.process_data(b"\x1b[2;1H\x1bMYY");
+        assert_eq!(rows(&term), vec!["aaa", "YY ", "ccc", "ddd", "eee"]);
+
+        // Resetting the region makes the whole screen scroll again, into scrollback
+        term.process_data(b"\x1b[r\x1b[5;1H\n");
+        assert_eq!(rows(&term), vec!["YY ", "ccc", "ddd", "eee", "   "]);
+        assert_eq!(term.scrollback_text(0, 10), vec!["aaa"]);
+    }
+
+    #[test]
+    fn test_insert_delete_lines() {
+        let mut term = Terminal::new("t".to_string(), 3, 5, TerminalType::Ansi);
+        term.process_data(b"111\r\n222\r\n333\r\n444\r\n555");
+        term.process_data(b"\x1b[2;1H\x1b[2L");
+        assert_eq!(rows(&term), vec!["111", "   ", "   ", "222", "333"]);
+        term.process_data(b"\x1b[3M");
+        assert_eq!(rows(&term), vec!["111", "333", "   ", "   ", "   "]);
+
+        // Lines only move within the scrolling region
+        let mut term = Terminal::new("t".to_string(), 3, 5, TerminalType::Ansi);
+        term.process_data(b"111\r\n222\r\n333\r\n444\r\n555");
+        term.process_data(b"\x1b[1;3r\x1b[1;1H\x1b[L");
+        assert_eq!(rows(&term), vec!["   ", "111", "222", "444", "555"]);
+        term.process_data(b"\x1b[M\x1b[M");
+        assert_eq!(rows(&term), vec!["222", "   ", "   ", "444", "555"]);
+    }
+
+    #[test]
+    fn test_insert_delete_erase_chars() {
+        let mut term = Terminal::new("t".to_string(), 6, 1, TerminalType::Ansi);
+        term.process_data(b"abcdef\x1b[1;3H\x1b[2@");
+        assert_eq!(rows(&term), vec!["ab  cd"]);
+        term.process_data(b"\x1b[3P");
+        assert_eq!(rows(&term), vec!["abd   "]);
+        term.process_data(b"\x1b[1;1HXYZ\x1b[1;2H\x1b[2X");
+        assert_eq!(rows(&term), vec!["X     "]);
+        assert_eq!(term.cursor_x, 1);
+
+        // Erased and inserted cells take the current background
+        term.process_data(b"\x1b[44m\x1b[1@");
+        assert_eq!(term.screen[0][1].bg, Color::Blue);
+
+        // Huge counts are clamped to the line
+        term.process_data(format!("\x1b[0m\x1b[{}X\x1b[{}C", usize::MAX, usize::MAX).as_bytes());
+        assert_eq!(rows(&term), vec!["X     "]);
+        assert_eq!(term.cursor_x, 5);
+    }
+
+    #[test]
+    fn test_alternate_screen() {
+        let mut term = Terminal::new("t".to_string(), 4, 2, TerminalType::Ansi);
+        term.process_data(b"main\r\nab");
+        term.process_data(b"\x1b[?1049h\x1b[2J\x1b[1;1Hvi\r\n~\r\n~");
+        assert!(term.alternate_screen());
+        assert_eq!(rows(&term), vec!["~   ", "~   "]);
+        // Full-screen programs don't fill the scrollback
+        assert!(term.scrollback.is_empty());
+
+        term.process_data(b"\x1b[?1049l");
+        assert!(!term.alternate_screen());
+        assert_eq!(rows(&term), vec!["main", "ab  "]);
+        assert_eq!((term.cursor_x, term.cursor_y), (2, 1));
+    }
+
+    #[test]
+    fn test_private_modes() {
+        let mut term = Terminal::new("t".to_string(), 3, 3, TerminalType::Ansi);
+        term.process_data(b"\x1b[?25l\x1b[?1h");
+        assert!(!term.modes.cursor_visible);
+        assert!(term.modes.application_cursor_keys);
+        term.process_data(b"\x1b[?25h\x1b[?1;25l");
+        assert!(!term.modes.cursor_visible);
+        assert!(!term.modes.application_cursor_keys);
+        term.process_data(b"\x1b[?25h");
+        assert!(term.modes.cursor_visible);
+
+        // Mouse tracking: turning off a level that isn't active leaves the current one
+        term.process_data(b"\x1b[?1002h\x1b[?1006h\x1b[?1000l");
+        assert_eq!(term.modes.mouse_tracking, MouseTracking::Button);
+        assert!(term.modes.sgr_mouse);
+        term.process_data(b"\x1b[?1002l");
+        assert_eq!(term.modes.mouse_tracking, MouseTracking::Off);
+
+        // Without autowrap, the last column is overwritten
+        term.process_data(b"\x1b[?7labcde");
+        assert_eq!(rows(&term), vec!["abe", "   ", "   "]);
+
+        // Origin mode positions the cursor within the scrolling region
+        term.process_data(b"\x1b[2;3r\x1b[?6h\x1b[1;1HX\x1b[9;1HY");
+        assert_eq!(rows(&term), vec!["abe", "X  ", "Y  "]);
+        term.process_data(format!("\x1b[{};1H", usize::MAX).as_bytes());
+        assert_eq!(term.cursor_y, 2);
+
+        // Other private sequences are ignored rather than misread
+        term.process_data(b"\x1b[>4;1m\x1b[?1;2r");
+        assert_eq!(term.scroll_top, 1);
+    }
+
+    #[test]
+    fn test_utf8_decoding() {
+        let mut term = Terminal::new("t".to_string(), 6, 2, TerminalType::Ansi);
+        // A sequence split across reads still decodes
+        term.process_data("é".as_bytes());
+        term.process_data(&"€".as_bytes()[..1]);
+        term.process_data(&"€".as_bytes()[1..]);
+        // Malformed bytes show as U+FFFD
+        term.process_data(b"\xff\xe2\x82x");
+        assert_eq!(rows(&term)[0], "é€\u{fffd}\u{fffd}x ");
+
+        // Legacy mode reads CP437
+        term.encoding = TerminalEncoding::Cp437;
+        term.process_data(b"\r\n\xc9\xcd\xbb\x82");
+        assert_eq!(rows(&term)[1], "╔═╗é  ");
+    }
+
+    #[test]
+    fn test_wide_chars() {
+        let mut term = Terminal::new("t".to_string(), 5, 3, TerminalType::Ansi);
+        term.process_data("a漢字".as_bytes());
+        assert_eq!(term.screen[0][1].width, CellWidth::Wide);
+        assert_eq!(term.screen[0][2].width, CellWidth::Continuation);
+        assert_eq!(term.cursor_x, 5);
+
+        // A wide character that doesn't fit wraps as a whole
+        term.process_data("\r\nabcd字".as_bytes());
+        assert_eq!(rows(&term), vec!["a漢 字 ", "abcd ", "字    "]);
+
+        // Overwriting half of one blanks the other half
+        term.process_data(b"\x1b[1;3Hx");
+        assert_eq!(rows(&term)[0], "a x字 ");
+        assert!(term.screen[0][1..3].iter().all(|c| c.width == CellWidth::Single));
+
+        // Combining marks take no space
+        term.process_data("\u{301}".as_bytes());
+        assert_eq!(term.cursor_x, 3);
+    }
+
+    #[test]
+    fn test_cursor_style() {
+        let mut term = Terminal::new("t".to_string(), 3, 3, TerminalType::Ansi);
+        assert_eq!(term.cursor_style, CursorStyle::BlinkingBlock);
+        term.process_data(b"\x1b[6 q");
+        assert_eq!(term.cursor_style, CursorStyle::SteadyBar);
+        term.process_data(b"\x1b[3 q");
+        assert_eq!(term.cursor_style, CursorStyle::BlinkingUnderline);
+        term.process_data(b"\x1b[ q");
+        assert_eq!(term.cursor_style, CursorStyle::BlinkingBlock);
+
+        // Other sequences with intermediates don't touch the screen
+        term.process_data(b"\x1b[9 q\x1b[2\"qX\x1b[!p");
+        assert_eq!(term.cursor_style, CursorStyle::BlinkingBlock);
+        assert_eq!(rows(&term), vec!["X  ", "   ", "   "]);
+    }
+
+    #[test]
+    fn test_osc_title() {
+        let mut term = Terminal::new("t".to_string(), 4, 1, TerminalType::Ansi);
+        term.process_data("\x1b]2;mail — inbox\x07".as_bytes());
+        assert_eq!(term.title.as_deref(), Some("mail — inbox"));
+        assert!(term.title_changed);
+
+        // ST terminator, split across reads
+        term.process_data(b"\x1b]0;irssi\x1b");
+        term.process_data(b"\\ab");
+        assert_eq!(term.title.as_deref(), Some("irssi"));
+        assert_eq!(rows(&term)[0], "ab  ");
+
+        // A bare ESC abandons the OSC without swallowing the next sequence
+        term.process_data(b"\x1b]2;oops\x1b[1;4Hc");
+        assert_eq!(term.title.as_deref(), Some("irssi"));
+        assert_eq!(rows(&term)[0], "ab c");
+    }
+
+    #[test]
+    fn test_osc_colors() {
+        let mut term = Terminal::new("t".to_string(), 4, 1, TerminalType::Ansi);
+        term.process_data(b"\x1b]4;1;?\x07\x1b]11;?\x1b\\");
+        assert_eq!(term.response_queue.pop_front().unwrap(), b"\x1b]4;1;rgb:aaaa/0000/0000\x07");
+        assert_eq!(term.response_queue.pop_front().unwrap(), b"\x1b]11;rgb:0000/0000/0000\x1b\\");
+
+        term.process_data(b"\x1b]4;1;rgb:ff/80/0;2;#00ff00\x07\x1b]10;rgb:f/f/f\x07");
+        assert_eq!(term.palette.get(&1), Some(&(255, 128, 0)));
+        assert_eq!(term.palette.get(&2), Some(&(0, 255, 0)));
+        assert_eq!(term.default_fg, Some((255, 255, 255)));
+
+        // Redefined colors show on cells already drawn
+        term.process_data(b"\x1b[31mr\x1b[37mw");
+        assert_eq!(term.display_cell(&term.screen[0][0]).fg, Color::Rgb(255, 128, 0));
+        assert_eq!(term.display_cell(&term.screen[0][1]).fg, Color::Rgb(255, 255, 255));
+
+        term.process_data(b"\x1b]104\x07\x1b]110\x07");
+        assert!(term.palette.is_empty());
+        assert_eq!(term.display_cell(&term.screen[0][0]).fg, Color::Red);
+    }
+
+    #[test]
+    fn test_osc_hyperlinks() {
+        let mut term = Terminal::new("t".to_string(), 6, 1, TerminalType::Ansi);
+        term.process_data(b"<\x1b]8;id=1;https://example.com\x1b\\a\xe6\xbc\xa2\x1b]8;;\x1b\\>");
+        assert_eq!(rows(&term)[0], "<a漢 > ");
+        assert_eq!(term.screen[0][0].link, None);
+        assert_eq!(term.screen[0][1].link.as_deref(), Some("https://example.com"));
+        assert_eq!(term.screen[0][3].link.as_deref(), Some("https://example.com"));
+        assert_eq!(term.screen[0][4].link, None);
+    }
+
+    #[test]
+    fn test_scrollback_view() {
+        let mut term = Terminal::new("t".to_string(), 4, 2, TerminalType::Ansi);
+        term.process_data(b"1\r\n2\r\n3\r\n4");
+        assert_eq!(term.scrollback_text(0, 10), vec!["1", "2"]);
+
+        term.scroll_view(1);
+        assert_eq!(term.view_row(0)[0].char, '2');
+        assert_eq!(term.view_row(1)[0].char, '3');
+
+        // New output doesn't move a scrolled-back view
+        term.process_data(b"\r\n5");
+        assert_eq!(term.scroll_offset, 2);
+        assert_eq!(term.view_row(0)[0].char, '2');
+
+        term.scroll_view(100);
+        assert_eq!(term.view_row(0)[0].char, '1');
+        assert!(term.snap_to_bottom());
+        assert_eq!(term.view_row(0)[0].char, '4');
+        assert!(!term.snap_to_bottom());
+    }
+}
@@ -0,0 +1,460 @@
+//! Terminal Connection Backends
+//!
+//! What a terminal window talks to, picked by `CreateTerminal`'s `transport`:
+//! - `telnet`: TCP with telnet negotiation (the default)
+//! - `raw`: plain TCP with no IAC processing, for hosts that misbehave when
+//!   negotiated with
+//! - `pty`: a local program on a pseudo-terminal (Unix only)
+//! - `ssh`: the system `ssh` client on a pseudo-terminal (Unix only)
+//!
+//! [`connect`] opens a [`Connection`]: the byte stream, plus the [`Backend`]
+//! that speaks whatever protocol runs over it.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+use crate::telnet::{escape_iac, RemoteTelnet, RemoteTelnetEvent};
+use crate::terminal::Terminal;
+
+/// How a terminal connects to its remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// TCP with telnet negotiation
+    #[default]
+    Telnet,
+    /// TCP, bytes passed through untouched
+    Raw,
+    /// A local program on a pseudo-terminal
+    Pty,
+    /// `ssh` to the host, on a pseudo-terminal
+    Ssh,
+}
+
+impl Transport {
+    /// Parse a transport name (None if unknown)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "telnet" => Some(Transport::Telnet),
+            "raw" | "tcp" => Some(Transport::Raw),
+            "pty" | "local" => Some(Transport::Pty),
+            "ssh" => Some(Transport::Ssh),
+            _ => None,
+        }
+    }
+
+    /// Whether the transport runs a program on the server's machine
+    pub fn is_local(self) -> bool {
+        matches!(self, Transport::Pty | Transport::Ssh)
+    }
+}
+
+/// Where a terminal connects
+#[derive(Debug, Clone, Default)]
+pub struct Endpoint {
+    pub transport: Transport,
+    /// Remote host (`ssh` also takes `user@host`)
+    pub host: String,
+    /// Remote port (`ssh`: 0 means 22)
+    pub port: u16,
+    /// Program and arguments to run (`pty`), or the remote command (`ssh`)
+    pub command: Vec<String>,
+}
+
+impl Endpoint {
+    /// Check the endpoint can be connected to, before anything is spawned
+    pub fn validate(&self) -> Result<(), String> {
+        match self.transport {
+            Transport::Telnet | Transport::Raw if self.host.is_empty() => Err("A host is required".to_string()),
+            Transport::Pty if self.command.first().is_none_or(|program| program.is_empty()) => {
+                Err("A command is required for pty terminals".to_string())
+            }
+            // Would be read as an option by ssh
+            Transport::Ssh if self.host.is_empty() || self.host.starts_with('-') => Err(format!("Invalid ssh host: {:?}", self.host)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Short description for window titles and logs
+    pub fn describe(&self) -> String {
+        match self.transport {
+            Transport::Telnet | Transport::Raw => format!("{}:{}", self.host, self.port),
+            Transport::Pty => self.command.join(" "),
+            Transport::Ssh => self.host.clone(),
+        }
+    }
+}
+
+/// The protocol side of a terminal connection
+pub trait Backend: Send {
+    /// Bytes to send as soon as the connection is up
+    fn start(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Strip protocol from received bytes, queueing replies to send back and
+    /// applying anything negotiated to the terminal
+    /// Returns the bytes meant for the terminal emulator
+    fn receive(&mut self, data: &[u8], replies: &mut Vec<u8>, terminal: &mut Terminal) -> Vec<u8>;
+
+    /// Prepare the emulator's own responses (cursor reports etc.) for the wire
+    fn encode(&self, data: Vec<u8>) -> Vec<u8> {
+        data
+    }
+
+    /// The window was resized: bytes to tell the remote, if any
+    fn resize(&mut self, cols: u16, rows: u16) -> Vec<u8>;
+
+    /// Why the stream ended, once it has
+    fn closed(&mut self) -> Pin<Box<dyn Future<Output = String> + Send + '_>> {
+        Box::pin(async { "Connection closed".to_string() })
+    }
+}
+
+/// An open terminal connection
+pub struct Connection {
+    pub reader: Box<dyn AsyncRead + Send + Unpin>,
+    pub writer: Box<dyn AsyncWrite + Send + Unpin>,
+    pub backend: Box<dyn Backend>,
+}
+
+/// Open a connection for a terminal of the given size
+/// `terminal_types` is what to report over telnet; the first also names the
+/// local programs' TERM
+pub async fn connect(endpoint: &Endpoint, terminal_types: Vec<String>, cols: u16, rows: u16) -> io::Result<Connection> {
+    match endpoint.transport {
+        Transport::Telnet | Transport::Raw => {
+            let stream = TcpStream::connect(format!("{}:{}", endpoint.host, endpoint.port)).await?;
+            let (reader, writer) = stream.into_split();
+            let backend: Box<dyn Backend> = match endpoint.transport {
+                Transport::Telnet => Box::new(TelnetBackend::new(RemoteTelnet::new(terminal_types, cols, rows))),
+                _ => Box::new(RawBackend),
+            };
+            Ok(Connection { reader: Box::new(reader), writer: Box::new(writer), backend })
+        }
+        Transport::Pty | Transport::Ssh => {
+            let term = terminal_types.first().map(|t| t.to_lowercase()).unwrap_or_else(|| "dumb".to_string());
+            spawn_pty(&program_args(endpoint), &term, cols, rows)
+        }
+    }
+}
+
+/// The program and arguments a local transport runs
+fn program_args(endpoint: &Endpoint) -> Vec<String> {
+    match endpoint.transport {
+        Transport::Ssh => {
+            let port = if endpoint.port == 0 { 22 } else { endpoint.port };
+            let mut args = vec!["ssh".to_string(), "-t".to_string(), "-p".to_string(), port.to_string()];
+            args.push("--".to_string());
+            args.push(endpoint.host.clone());
+            args.extend(endpoint.command.iter().cloned());
+            args
+        }
+        _ => endpoint.command.clone(),
+    }
+}
+
+/// Telnet: negotiation handled by [`RemoteTelnet`]
+pub struct TelnetBackend {
+    telnet: RemoteTelnet,
+}
+
+impl TelnetBackend {
+    pub fn new(telnet: RemoteTelnet) -> Self {
+        Self { telnet }
+    }
+}
+
+impl Backend for TelnetBackend {
+    fn start(&mut self) -> Vec<u8> {
+        self.telnet.start()
+    }
+
+    fn receive(&mut self, data: &[u8], replies: &mut Vec<u8>, terminal: &mut Terminal) -> Vec<u8> {
+        let mut events = Vec::new();
+        let filtered = self.telnet.process(data, replies, &mut events);
+        for event in events {
+            match event {
+                RemoteTelnetEvent::Echo(echo) => terminal.remote_echo = echo,
+                RemoteTelnetEvent::Charset(encoding) => terminal.encoding = encoding,
+            }
+        }
+        filtered
+    }
+
+    fn encode(&self, data: Vec<u8>) -> Vec<u8> {
+        escape_iac(data)
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> Vec<u8> {
+        // Only once the remote has asked for NAWS
+        self.telnet.resize(cols, rows)
+    }
+}
+
+/// Raw TCP: everything goes straight through
+pub struct RawBackend;
+
+impl Backend for RawBackend {
+    fn receive(&mut self, data: &[u8], _replies: &mut Vec<u8>, _terminal: &mut Terminal) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn resize(&mut self, _cols: u16, _rows: u16) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+#[cfg(not(unix))]
+fn spawn_pty(_args: &[String], _term: &str, _cols: u16, _rows: u16) -> io::Result<Connection> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Local terminals aren't supported on this platform"))
+}
+
+#[cfg(unix)]
+use pty::spawn_pty;
+
+#[cfg(unix)]
+mod pty {
+    use std::fs::File;
+    use std::future::Future;
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::pin::Pin;
+    use std::process::Stdio;
+    use std::task::{ready, Context, Poll};
+    use std::time::Duration;
+
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use tokio::process::{Child, Command};
+
+    use super::{Backend, Connection};
+    use crate::terminal::Terminal;
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Server environment passed on to programs (anything else, like
+    /// `APU_AUTH_TOKEN`, stays out of reach of whoever types in the window)
+    const INHERITED_ENV: [&str; 4] = ["HOME", "PATH", "LANG", "USER"];
+
+    /// How long to wait for the program to exit once its pty has closed
+    const EXIT_WAIT: Duration = Duration::from_secs(1);
+
+    fn window_size(cols: u16, rows: u16) -> libc::winsize {
+        libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 }
+    }
+
+    /// Run a program on a new pseudo-terminal of the given size
+    pub fn spawn_pty(args: &[String], term: &str, cols: u16, rows: u16) -> io::Result<Connection> {
+        let (program, args) = args.split_first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No command given"))?;
+
+        let mut master = -1;
+        let mut slave = -1;
+        let mut size = window_size(cols, rows);
+        // SAFETY: openpty fills in two new descriptors, which are owned from here on
+        // (the size is passed as *mut because some platforms declare it so)
+        let (master, slave) = unsafe {
+            check(libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::addr_of_mut!(size)))?;
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .env_clear()
+            .envs(INHERITED_ENV.iter().filter_map(|name| Some((name, std::env::var_os(name)?))))
+            .env("TERM", term)
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave))
+            .kill_on_drop(true);
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                // A session of its own, with the pty as its controlling terminal
+                check(libc::setsid())?;
+                check(libc::ioctl(0, libc::TIOCSCTTY as _, 0))?;
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        // (our copies of the slave side close with `command`, so the master
+        // sees the end once the program and its children are gone)
+        drop(command);
+
+        // SAFETY: fcntl on a descriptor we own
+        unsafe {
+            let flags = check(libc::fcntl(master.as_raw_fd(), libc::F_GETFL))?;
+            check(libc::fcntl(master.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        }
+        let control = master.try_clone()?;
+        let stream = PtyStream(AsyncFd::new(File::from(master))?);
+        let (reader, writer) = tokio::io::split(stream);
+
+        Ok(Connection {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            backend: Box::new(PtyBackend { master: control, child }),
+        })
+    }
+
+    /// A pty's program, killed when the terminal goes away
+    struct PtyBackend {
+        /// Master side, for window size changes
+        master: OwnedFd,
+        child: Child,
+    }
+
+    impl Backend for PtyBackend {
+        fn receive(&mut self, data: &[u8], _replies: &mut Vec<u8>, _terminal: &mut Terminal) -> Vec<u8> {
+            data.to_vec()
+        }
+
+        fn resize(&mut self, cols: u16, rows: u16) -> Vec<u8> {
+            let size = window_size(cols, rows);
+            // SAFETY: TIOCSWINSZ reads a winsize; the program gets SIGWINCH
+            unsafe {
+                libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &size);
+            }
+            Vec::new()
+        }
+
+        fn closed(&mut self) -> Pin<Box<dyn Future<Output = String> + Send + '_>> {
+            // The pty can close a moment before the program is reaped, or
+            // without the program exiting at all
+            Box::pin(async {
+                match tokio::time::timeout(EXIT_WAIT, self.child.wait()).await {
+                    Ok(Ok(status)) => format!("Process exited ({})", status),
+                    _ => "Terminal closed".to_string(),
+                }
+            })
+        }
+    }
+
+    /// Non-blocking pty master
+    struct PtyStream(AsyncFd<File>);
+
+    impl AsyncRead for PtyStream {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            loop {
+                let mut guard = ready!(self.0.poll_read_ready(cx))?;
+                let unfilled = buf.initialize_unfilled();
+                match guard.try_io(|fd| fd.get_ref().read(unfilled)) {
+                    Ok(Ok(n)) => {
+                        buf.advance(n);
+                        return Poll::Ready(Ok(()));
+                    }
+                    // Linux reports the slave side closing as EIO: that's the end of the stream
+                    Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+                    Ok(Err(e)) => return Poll::Ready(Err(e)),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    impl AsyncWrite for PtyStream {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            loop {
+                let mut guard = ready!(self.0.poll_write_ready(cx))?;
+                match guard.try_io(|fd| fd.get_ref().write(buf)) {
+                    Ok(result) => return Poll::Ready(result),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::TerminalType;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_endpoint_validation() {
+        assert_eq!(Transport::parse("RAW"), Some(Transport::Raw));
+        assert_eq!(Transport::parse("serial"), None);
+        assert!(Transport::Pty.is_local() && !Transport::Raw.is_local());
+
+        let pty = Endpoint { transport: Transport::Pty, command: vec!["bash".into()], ..Default::default() };
+        assert_eq!(pty.validate(), Ok(()));
+        assert_eq!(pty.describe(), "bash");
+        assert!(Endpoint { transport: Transport::Pty, ..Default::default() }.validate().is_err());
+        let ssh = Endpoint { transport: Transport::Ssh, host: "-oProxyCommand=sh".into(), ..Default::default() };
+        assert!(ssh.validate().is_err());
+        let ssh = Endpoint { transport: Transport::Ssh, host: "me@example.com".into(), command: vec!["top".into()], ..Default::default() };
+        assert_eq!(program_args(&ssh), ["ssh", "-t", "-p", "22", "--", "me@example.com", "top"]);
+    }
+
+    #[tokio::test]
+    async fn test_raw_transport_passes_iac_through() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let endpoint = Endpoint { transport: Transport::Raw, host: "127.0.0.1".into(), port, command: Vec::new() };
+        let (connection, accepted) = tokio::join!(connect(&endpoint, vec!["ANSI".into()], 80, 24), listener.accept());
+        let mut connection = connection.unwrap();
+        let (mut remote, _) = accepted.unwrap();
+
+        // Nothing is negotiated, and IAC bytes reach the terminal untouched
+        assert!(connection.backend.start().is_empty());
+        remote.write_all(&[255, 253, 1, b'A']).await.unwrap();
+        let mut buf = [0u8; 16];
+        let n = connection.reader.read(&mut buf).await.unwrap();
+        let mut terminal = Terminal::new("t".into(), 80, 24, TerminalType::Ansi);
+        let mut replies = Vec::new();
+        assert_eq!(connection.backend.receive(&buf[..n], &mut replies, &mut terminal), [255, 253, 1, b'A']);
+        assert!(replies.is_empty());
+        assert_eq!(connection.backend.encode(vec![255]), [255]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_runs_program() {
+        // Set for the tests by cargo, and not one the program may see
+        assert!(std::env::var_os("CARGO_MANIFEST_DIR").is_some());
+        let endpoint = Endpoint {
+            transport: Transport::Pty,
+            command: vec!["sh".into(), "-c".into(), "stty size; echo $TERM; echo dir=$CARGO_MANIFEST_DIR; read line; echo got $line".into()],
+            ..Default::default()
+        };
+        let mut connection = connect(&endpoint, vec!["VT100".into()], 40, 10).await.unwrap();
+        connection.writer.write_all(b"hello\r").await.unwrap();
+
+        let mut output = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            let n = tokio::time::timeout(std::time::Duration::from_secs(5), connection.reader.read(&mut buf)).await.unwrap().unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.contains("10 40"), "{:?}", output);
+        assert!(output.contains("vt100"), "{:?}", output);
+        assert!(output.contains("got hello"), "{:?}", output);
+        // The server's environment isn't passed on
+        assert!(output.contains("dir=\r\n"), "{:?}", output);
+        assert_eq!(connection.backend.closed().await, "Process exited (exit status: 0)");
+    }
+}
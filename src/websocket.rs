@@ -0,0 +1,459 @@
+//! WebSocket Transport (RFC 6455)
+//!
+//! Lets browser terminals (e.g. xterm.js) connect to APU directly, without a bridge.
+//! - HTTP Upgrade handshake (Sec-WebSocket-Accept via SHA-1 + base64)
+//! - Frame decoding (client frames are masked) and encoding
+//! - Ping/pong and closing handshake
+//!
+//! After the handshake a connection is bridged to a plain byte stream, so the
+//! client session code sees the same input and output bytes as over telnet.
+//! Incoming text and binary messages become input; output goes out as binary messages.
+
+use std::io;
+
+use log::debug;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream,
+};
+use tokio::sync::mpsc;
+
+/// Magic GUID appended to the client's key (RFC 6455 section 1.3)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest handshake request we accept
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Largest frame payload we accept (input from a player is tiny)
+const MAX_PAYLOAD: u64 = 1 << 20;
+
+/// Close status: normal closure
+const CLOSE_NORMAL: u16 = 1000;
+
+/// Frame opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xA,
+}
+
+impl Opcode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+}
+
+/// A decoded WebSocket frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Final fragment of a message
+    pub fin: bool,
+    pub opcode: Opcode,
+    /// Payload (already unmasked)
+    pub payload: Vec<u8>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Compute the Sec-WebSocket-Accept value for a client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    let mut data = key.trim().as_bytes().to_vec();
+    data.extend_from_slice(WEBSOCKET_GUID.as_bytes());
+    base64_encode(&sha1(&data))
+}
+
+/// Read the client's HTTP Upgrade request and answer it, returning the request path
+/// On a bad request, a 400 response is sent and an error returned
+pub async fn handshake<R, W>(reader: &mut R, writer: &mut W) -> io::Result<String>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut line = String::new();
+    let mut total = 0;
+    let mut is_get = false;
+    let mut path = String::new();
+    let mut upgrade = false;
+    let mut version_ok = false;
+    let mut key: Option<String> = None;
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line).await?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed during handshake"));
+        }
+        total += n;
+        if total > MAX_REQUEST_BYTES {
+            return Err(invalid("handshake request too large"));
+        }
+
+        let header = line.trim_end();
+        if total == n {
+            // Request line: GET /path HTTP/1.1
+            let mut parts = header.split_whitespace();
+            is_get = parts.next() == Some("GET");
+            path = parts.next().unwrap_or("/").to_string();
+            continue;
+        }
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+                "sec-websocket-version" => version_ok = value == "13",
+                "sec-websocket-key" => key = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    match key {
+        Some(key) if is_get && upgrade && version_ok => {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)
+            );
+            writer.write_all(response.as_bytes()).await?;
+            writer.flush().await?;
+            Ok(path)
+        }
+        _ => {
+            let response = "HTTP/1.1 400 Bad Request\r\nSec-WebSocket-Version: 13\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            writer.write_all(response.as_bytes()).await?;
+            writer.flush().await?;
+            Err(invalid("not a WebSocket upgrade request"))
+        }
+    }
+}
+
+/// Read one frame sent by a client (clients must mask their frames)
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Frame> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header).await?;
+
+    let fin = header[0] & 0x80 != 0;
+    let opcode = Opcode::from_u8(header[0] & 0x0F).ok_or_else(|| invalid("unknown opcode"))?;
+    if header[1] & 0x80 == 0 {
+        return Err(invalid("unmasked client frame"));
+    }
+
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes).await?;
+            u16::from_be_bytes(bytes) as u64
+        }
+        127 => {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes).await?;
+            u64::from_be_bytes(bytes)
+        }
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(invalid("frame too large"));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Frame { fin, opcode, payload })
+}
+
+/// Encode a single unfragmented frame sent by the server (servers never mask)
+pub fn encode_frame(opcode: Opcode, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode as u8);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Perform the handshake on a connection, then bridge it to a byte stream
+///
+/// Returns the stream and the request path. Reading the stream yields the payload
+/// of the client's messages; writing to it sends binary messages. Dropping it
+/// closes the WebSocket.
+pub async fn accept<S>(stream: S) -> io::Result<(DuplexStream, String)>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let path = handshake(&mut reader, &mut writer).await?;
+
+    let (app, bridge) = tokio::io::duplex(64 * 1024);
+    let (mut bridge_reader, mut bridge_writer) = tokio::io::split(bridge);
+    let (control_tx, mut control_rx) = mpsc::channel::<Vec<u8>>(8);
+
+    // Client frames -> input bytes
+    tokio::spawn(async move {
+        loop {
+            let frame = match read_frame(&mut reader).await {
+                Ok(frame) => frame,
+                Err(e) => {
+                    debug!("WebSocket read ended: {}", e);
+                    break;
+                }
+            };
+            match frame.opcode {
+                Opcode::Text | Opcode::Binary | Opcode::Continuation => {
+                    if bridge_writer.write_all(&frame.payload).await.is_err() {
+                        break;
+                    }
+                }
+                Opcode::Ping => {
+                    let _ = control_tx.send(encode_frame(Opcode::Pong, &frame.payload)).await;
+                }
+                Opcode::Pong => {}
+                Opcode::Close => {
+                    // Echo the status code to complete the closing handshake
+                    let status = &frame.payload[..frame.payload.len().min(2)];
+                    let _ = control_tx.send(encode_frame(Opcode::Close, status)).await;
+                    break;
+                }
+            }
+        }
+        let _ = bridge_writer.shutdown().await;
+    });
+
+    // Output bytes (and control replies) -> server frames
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 16 * 1024];
+        loop {
+            tokio::select! {
+                result = bridge_reader.read(&mut buf) => {
+                    match result {
+                        Ok(n) if n > 0 => {
+                            if writer.write_all(&encode_frame(Opcode::Binary, &buf[..n])).await.is_err() {
+                                break;
+                            }
+                        }
+                        _ => {
+                            // Session ended: close the WebSocket
+                            let _ = writer.write_all(&encode_frame(Opcode::Close, &CLOSE_NORMAL.to_be_bytes())).await;
+                            break;
+                        }
+                    }
+                }
+                Some(control) = control_rx.recv() => {
+                    let closing = control[0] & 0x0F == Opcode::Close as u8;
+                    if writer.write_all(&control).await.is_err() || closing {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = writer.shutdown().await;
+    });
+
+    Ok((app, path))
+}
+
+/// SHA-1 digest (only used for the handshake, where RFC 6455 mandates it)
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// Standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        output.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        output.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        output.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        output.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a masked client frame
+    fn client_frame(opcode: Opcode, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![0x80 | opcode as u8, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_sha1_and_base64() {
+        let digest: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(digest, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn test_encode_frame_lengths() {
+        assert_eq!(encode_frame(Opcode::Text, b"Hello"), b"\x81\x05Hello");
+        let frame = encode_frame(Opcode::Binary, &[0u8; 300]);
+        assert_eq!(&frame[..4], &[0x82, 126, 0x01, 0x2C]);
+        assert_eq!(frame.len(), 304);
+    }
+
+    #[tokio::test]
+    async fn test_read_masked_frame() {
+        // Masked "Hello" from RFC 6455 section 5.7
+        let data: &[u8] = &[0x81, 0x85, 0x37, 0xFA, 0x21, 0x3D, 0x7F, 0x9F, 0x4D, 0x51, 0x58];
+        let frame = read_frame(&mut &data[..]).await.unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, Opcode::Text);
+        assert_eq!(frame.payload, b"Hello");
+
+        // Unmasked client frames are rejected
+        let data: &[u8] = b"\x81\x05Hello";
+        assert!(read_frame(&mut &data[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_plain_http() {
+        let request: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut response = Vec::new();
+        assert!(handshake(&mut &request[..], &mut response).await.is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400"));
+    }
+
+    #[tokio::test]
+    async fn test_accept_bridges_messages() {
+        let (client, server) = tokio::io::duplex(4096);
+        let accept_task = tokio::spawn(accept(server));
+        let (mut client_reader, mut client_writer) = tokio::io::split(client);
+
+        client_writer.write_all(
+            b"GET /apu HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        ).await.unwrap();
+        let (mut app, path) = accept_task.await.unwrap().unwrap();
+        assert_eq!(path, "/apu");
+
+        let mut client_reader = BufReader::new(&mut client_reader);
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            client_reader.read_line(&mut line).await.unwrap();
+            response.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // Client message -> input bytes
+        client_writer.write_all(&client_frame(Opcode::Text, b"hi")).await.unwrap();
+        let mut input = [0u8; 2];
+        app.read_exact(&mut input).await.unwrap();
+        assert_eq!(&input, b"hi");
+
+        // Output bytes -> binary message
+        app.write_all(b"\x1b[H").await.unwrap();
+        let mut frame = [0u8; 5];
+        client_reader.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\x82\x03\x1b[H");
+
+        // Ping -> pong
+        client_writer.write_all(&client_frame(Opcode::Ping, b"p")).await.unwrap();
+        let mut pong = [0u8; 3];
+        client_reader.read_exact(&mut pong).await.unwrap();
+        assert_eq!(&pong, b"\x8a\x01p");
+
+        // Close -> close echoed, input stream ends
+        client_writer.write_all(&client_frame(Opcode::Close, &CLOSE_NORMAL.to_be_bytes())).await.unwrap();
+        let mut close = [0u8; 4];
+        client_reader.read_exact(&mut close).await.unwrap();
+        assert_eq!(&close, b"\x88\x02\x03\xe8");
+        assert_eq!(app.read(&mut input).await.unwrap(), 0);
+    }
+}